@@ -0,0 +1,171 @@
+use std::{future::Future, time::Duration};
+use tokio::time::timeout;
+use crate::config::{CliArgs, Config, HashIndexBackend, HashListBackend, UsageBackend, WebhookBackend};
+use crate::helper::{db_api_helper, hash_index_store, s3_helpers, sqlite, sqs_helpers, web_helper, webhook_outbox};
+use crate::helper::token_verifier::TokenVerifier;
+use crate::helper::rate_limit::RateLimiter;
+use crate::helper::usage::UsageMeter;
+use crate::helper::hash_lists::HashLists;
+use crate::helper::model_versions::ModelVersions;
+
+///Exit code when every check passed
+pub const EXIT_OK: i32 = 0;
+///Exit code when the configuration could not be loaded or is invalid
+pub const EXIT_CONFIG_INVALID: i32 = 2;
+///Exit code when the S3 storage bucket can not be accessed
+pub const EXIT_S3_UNREACHABLE: i32 = 3;
+///Exit code when the SQS work queue can not be accessed
+pub const EXIT_SQS_UNREACHABLE: i32 = 4;
+///Exit code when the database API can not be reached
+pub const EXIT_DB_API_UNREACHABLE: i32 = 5;
+///Exit code when the database API does not exchange our authorization token for a JWT bearer token
+pub const EXIT_DB_API_LOGIN_FAILED: i32 = 6;
+///Exit code when the keys used to verify our clients' JWT bearer tokens can not be loaded
+pub const EXIT_JWT_KEYS_INVALID: i32 = 7;
+///Exit code when the store that keeps the counters of our rate limits can not be reached
+pub const EXIT_RATE_LIMIT_STORE_UNREACHABLE: i32 = 8;
+///Exit code when the store that records the usage of our projects can not be opened
+pub const EXIT_USAGE_STORE_UNAVAILABLE: i32 = 9;
+///Exit code when the outbox of our webhook deliveries can not be opened
+pub const EXIT_WEBHOOK_OUTBOX_UNAVAILABLE: i32 = 10;
+///Exit code when the index of the hashes our results are stored under can not be opened
+pub const EXIT_HASH_INDEX_UNAVAILABLE: i32 = 12;
+///Exit code when the database of our hash lists can not be opened
+pub const EXIT_HASH_LISTS_UNAVAILABLE: i32 = 13;
+///Exit code when the manifest of the model our workers scan with can not be read
+pub const EXIT_MODEL_MANIFEST_INVALID: i32 = 14;
+
+///Time a single backend check may take before it is considered failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+///Result of a single check
+struct CheckResult {
+    name: &'static str,
+    exit_code: i32,
+    error: Option<String>,
+}
+
+///Validates the configuration and checks that every backend it names is reachable, without starting the server.
+///Prints a pass/fail table of every check.
+///
+/// # Arguments
+/// args: &CliArgs - The parsed command line arguments
+///
+/// # Returns
+/// i32 - The exit code of the first failed check, or EXIT_OK if every check passed
+pub async fn run(args: &CliArgs) -> i32 {
+    let config = match Config::load(args) {
+        Ok(config) => config,
+        Err(err) => {
+            print_results(&[CheckResult { name: "configuration", exit_code: EXIT_CONFIG_INVALID, error: Some("invalid, see below".to_string()) }]);
+            println!();
+            println!("{}", err);
+            return EXIT_CONFIG_INVALID;
+        }
+    };
+
+    let results = vec![
+        CheckResult { name: "configuration", exit_code: EXIT_CONFIG_INVALID, error: None },
+        check("jwt keys", EXIT_JWT_KEYS_INVALID, check_jwt_keys(&config)).await,
+        check("s3 bucket", EXIT_S3_UNREACHABLE, s3_helpers::check_s3_access(&config)).await,
+        check("sqs queue", EXIT_SQS_UNREACHABLE, check_sqs(&config)).await,
+        check("db api connection", EXIT_DB_API_UNREACHABLE, check_db_api(&config)).await,
+        check("db api login", EXIT_DB_API_LOGIN_FAILED, check_db_api_login(&config)).await,
+        check("rate limit store", EXIT_RATE_LIMIT_STORE_UNREACHABLE, check_rate_limit_store(&config)).await,
+        check("usage store", EXIT_USAGE_STORE_UNAVAILABLE, check_usage_store(&config)).await,
+        check("webhook outbox", EXIT_WEBHOOK_OUTBOX_UNAVAILABLE, check_webhook_outbox(&config)).await,
+        check("hash index", EXIT_HASH_INDEX_UNAVAILABLE, check_hash_index(&config)).await,
+        check("hash lists", EXIT_HASH_LISTS_UNAVAILABLE, check_hash_lists(&config)).await,
+        check("model manifest", EXIT_MODEL_MANIFEST_INVALID, async { ModelVersions::new(&config.models).map(|_| ()) }).await,
+    ];
+
+    print_results(&results);
+
+    return results.iter()
+        .find(|result| result.error.is_some())
+        .map(|result| result.exit_code)
+        .unwrap_or(EXIT_OK);
+}
+
+///Runs a single check and converts a timeout into a failure
+async fn check(name: &'static str, exit_code: i32, check: impl Future<Output = Result<(), String>>) -> CheckResult {
+    let error = match timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result.err(),
+        Err(_) => Some(format!("timed out after {} seconds", CHECK_TIMEOUT.as_secs())),
+    };
+
+    return CheckResult { name, exit_code, error };
+}
+
+async fn check_jwt_keys(config: &Config) -> Result<(), String> {
+    return TokenVerifier::new(&config.jwt).await.map(|_| ());
+}
+
+async fn check_sqs(config: &Config) -> Result<(), String> {
+    let client = sqs_helpers::get_client(&config.sqs).await;
+
+    return sqs_helpers::check_queue(&client, &config.sqs.queue_url).await
+        .map_err(|err| format!("Could not access the queue: {}", err));
+}
+
+async fn check_db_api(config: &Config) -> Result<(), String> {
+    if !db_api_helper::check_db_connection(config).await {
+        return Err(format!("{}/db/v1/scan/CanConnect did not respond successfully", config.db_api.url));
+    }
+
+    return Ok(());
+}
+
+async fn check_db_api_login(config: &Config) -> Result<(), String> {
+    if web_helper::get_pam_token(&reqwest::Client::new(), config).await.is_none() {
+        return Err("Could not exchange the authorization token for a JWT bearer token".to_string());
+    }
+
+    return Ok(());
+}
+
+async fn check_rate_limit_store(config: &Config) -> Result<(), String> {
+    return RateLimiter::new(&config.rate_limit).await.map(|_| ());
+}
+
+//The checks of our SQLite databases don't open them the way we do when we start, as that would create them and their tables
+
+async fn check_usage_store(config: &Config) -> Result<(), String> {
+    return match config.usage.backend {
+        UsageBackend::Sqlite => sqlite::check(&config.usage.database_path, "usage database"),
+        UsageBackend::Redis => UsageMeter::new(&config.usage).await.map(|_| ()),
+    };
+}
+
+async fn check_webhook_outbox(config: &Config) -> Result<(), String> {
+    return match config.webhooks.backend {
+        WebhookBackend::Sqlite => sqlite::check(&config.webhooks.database_path, "webhook database"),
+        WebhookBackend::Redis => webhook_outbox::RedisStore::new(&config.webhooks.redis_url).await.map(|_| ()),
+    };
+}
+
+async fn check_hash_index(config: &Config) -> Result<(), String> {
+    return match config.hashing.backend {
+        HashIndexBackend::Sqlite => sqlite::check(&config.hashing.index_path, "hash index"),
+        HashIndexBackend::Redis => hash_index_store::RedisStore::new(&config.hashing.redis_url).await.map(|_| ()),
+    };
+}
+
+async fn check_hash_lists(config: &Config) -> Result<(), String> {
+    return match config.hash_lists.backend {
+        HashListBackend::Sqlite => sqlite::check(&config.hash_lists.database_path, "hash list database"),
+        HashListBackend::Redis => HashLists::new(&config.hash_lists).await.map(|_| ()),
+    };
+}
+
+///Prints the results of our checks as a table
+fn print_results(results: &[CheckResult]) {
+    println!("{:<20} {:<6} DETAILS", "CHECK", "RESULT");
+
+    for result in results {
+        match &result.error {
+            None => println!("{:<20} {:<6}", result.name, "PASS"),
+            Some(error) => println!("{:<20} {:<6} {} (exit code {})", result.name, "FAIL", error, result.exit_code),
+        }
+    }
+}
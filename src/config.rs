@@ -0,0 +1,1323 @@
+use std::{collections::HashMap, fmt, fs, path::PathBuf, str::FromStr};
+use reqwest::Url;
+use serde::Deserialize;
+use structopt::StructOpt;
+use crate::helper::misc::get_env_variable;
+use crate::helper::secrets::Secret;
+use crate::helper::url_fetcher;
+
+///Pamaxie's scan API. Command line flags override the values of the configuration file and the environment
+#[derive(StructOpt)]
+#[structopt(name = "pamaxie_scan_api")]
+pub struct CliArgs {
+    ///Path to the TOML configuration file
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    ///Port the API listens on (SCAN_API_PORT)
+    #[structopt(long, global = true)]
+    pub port: Option<u16>,
+
+    ///Public base URL of this API (PAM_BASE_URL)
+    #[structopt(long, global = true)]
+    pub base_url: Option<String>,
+
+    ///URL of the database API (DB_API_URL)
+    #[structopt(long, global = true)]
+    pub db_api_url: Option<String>,
+
+    ///URL of the S3 storage (S3_URL)
+    #[structopt(long, global = true)]
+    pub s3_url: Option<String>,
+
+    ///Name of the S3 storage bucket (S3_BUCKET_NAME)
+    #[structopt(long, global = true)]
+    pub s3_bucket: Option<String>,
+
+    ///Region of the S3 storage (S3_STORAGE_REGION)
+    #[structopt(long, global = true)]
+    pub s3_region: Option<String>,
+
+    ///URL of the SQS work queue (AWS_SQS_QUEUE_URL_0)
+    #[structopt(long, global = true)]
+    pub sqs_queue_url: Option<String>,
+
+    ///Region of the SQS work queue (AWS_DEFAULT_REGION)
+    #[structopt(long, global = true)]
+    pub sqs_region: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+///Commands that can be run instead of starting the server
+#[derive(StructOpt)]
+pub enum Command {
+    ///Validates the configuration and checks that every backend it names is reachable, without starting the server
+    CheckConfig,
+}
+
+///Configuration of the scan API.
+///
+///Values are layered: the defaults are overwritten by the TOML configuration file, which is overwritten by environment variables,
+///which are overwritten by command line flags. Secrets can't be passed on the command line to keep them out of the process list.
+///Instead they can be read from files (`*_file` keys and `*_FILE` environment variables), which are reloaded when they change.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    ///Port the API listens on
+    pub port: u16,
+    ///Public base URL of this API, used to build the image URLs handed to our workers
+    pub base_url: String,
+    ///Seconds between checks whether secret files changed
+    pub secret_reload_interval: u64,
+    pub db_api: DbApiConfig,
+    pub s3: S3Config,
+    pub sqs: SqsConfig,
+    pub jwt: JwtConfig,
+    pub auth_cache: AuthCacheConfig,
+    pub rate_limit: RateLimitConfig,
+    pub usage: UsageConfig,
+    pub webhooks: WebhookConfig,
+    pub batch: BatchConfig,
+    pub url_fetch: UrlFetchConfig,
+    pub uploads: UploadConfig,
+    pub images: ImageConfig,
+    pub preprocessing: PreprocessingConfig,
+    pub hashing: HashingConfig,
+    pub hash_lists: HashListConfig,
+    pub models: ModelConfig,
+}
+
+///Connection settings of the database API
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbApiConfig {
+    pub url: String,
+    ///Pamaxie authorization token that is exchanged for a JWT bearer token
+    pub auth_token: Secret,
+    pub auth_token_file: Option<PathBuf>,
+}
+
+///Connection settings of the S3 storage that holds images until they are scanned
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    pub access_key_id: String,
+    pub secret_access_key: Secret,
+    pub secret_access_key_file: Option<PathBuf>,
+    pub bucket: String,
+    pub region: String,
+    pub url: String,
+}
+
+///Connection settings of the SQS work queue
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqsConfig {
+    pub access_key_id: String,
+    pub secret_access_key: Secret,
+    pub secret_access_key_file: Option<PathBuf>,
+    pub region: String,
+    pub queue_url: String,
+}
+
+///Settings used to verify the JWT bearer tokens sent in by our clients. At least one kind of key has to be configured.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    ///Issuer that has to be set in the iss claim of every token
+    pub issuer: String,
+    ///Seconds of clock skew that are tolerated when validating the nbf and exp claims
+    pub leeway: u64,
+    ///Shared secret of HMAC signed tokens
+    pub hmac_secret: Secret,
+    pub hmac_secret_file: Option<PathBuf>,
+    ///PEM encoded RSA or EC public keys of asymmetrically signed tokens
+    pub public_key_files: Vec<PathBuf>,
+    ///File path or http(s) URL of a JWKS document with the issuer's public keys
+    pub jwks: String,
+    ///Seconds between reloads of the JWKS document
+    pub jwks_refresh_interval: u64,
+}
+
+///Settings of the cache that keeps the authentication decisions of the database API
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthCacheConfig {
+    ///Maximum number of tokens whose decision is cached
+    pub capacity: usize,
+    ///Seconds a token that was allowed to authenticate is cached for. 0 disables caching them.
+    pub positive_ttl: u64,
+    ///Seconds a token that was rejected is cached for. 0 disables caching them.
+    pub negative_ttl: u64,
+}
+
+///Where the counters of our rate limits are kept
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    ///In the memory of this instance, so every instance limits on its own
+    Memory,
+    ///In Redis, so the limits are shared by every instance
+    Redis,
+}
+
+impl FromStr for RateLimitBackend {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "memory" => Ok(RateLimitBackend::Memory),
+            "redis" => Ok(RateLimitBackend::Redis),
+            _ => Err(()),
+        };
+    }
+}
+
+///Limits on how fast a project may request scans and how many of its scans may be in flight at once
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub backend: RateLimitBackend,
+    ///URL of the Redis server used by the redis backend
+    pub redis_url: String,
+    ///Requests a project may send per second on average
+    pub requests_per_second: f64,
+    ///Requests a project may send at once before it has to slow down to requests_per_second
+    pub burst: u32,
+    ///Scans of a project that may be in flight at once. 0 disables the limit.
+    pub max_concurrent_scans: u32,
+    ///Limits of single projects, keyed by their project ID. Limits that are not set use the defaults above.
+    pub projects: HashMap<String, ProjectRateLimitConfig>,
+}
+
+///Limits of a single project that differ from the defaults
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectRateLimitConfig {
+    pub requests_per_second: Option<f64>,
+    pub burst: Option<u32>,
+    pub max_concurrent_scans: Option<u32>,
+}
+
+///Where the usage of our projects is recorded
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageBackend {
+    ///In a SQLite database of this instance
+    Sqlite,
+    ///In Redis, so every instance records into and enforces the same usage
+    Redis,
+}
+
+impl FromStr for UsageBackend {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "sqlite" => Ok(UsageBackend::Sqlite),
+            "redis" => Ok(UsageBackend::Redis),
+            _ => Err(()),
+        };
+    }
+}
+
+///Settings of the usage metering of our projects and their monthly quotas. Months are calendar months in UTC.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsageConfig {
+    pub backend: UsageBackend,
+    ///Path of the SQLite database used by the sqlite backend
+    pub database_path: PathBuf,
+    ///URL of the Redis server used by the redis backend
+    pub redis_url: String,
+    ///Detection requests a project may send per month. 0 disables the quota.
+    pub monthly_requests: u64,
+    ///Bytes a project may upload per month. 0 disables the quota.
+    pub monthly_bytes: u64,
+    ///Quotas of single projects, keyed by their project ID. Quotas that are not set use the defaults above.
+    pub projects: HashMap<String, ProjectQuotaConfig>,
+}
+
+///Quotas of a single project that differ from the defaults
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectQuotaConfig {
+    pub monthly_requests: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+///Limits of our batch detection endpoint
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchConfig {
+    ///Most items a single batch may contain
+    pub max_items: usize,
+    ///Most items a batch may contain to be answered right away. Larger batches are processed in the background as a batch job.
+    pub max_sync_items: usize,
+    ///Items of a single batch that are scanned at once
+    pub concurrency: usize,
+    ///Seconds the items of a batch that is answered right away wait for the result of our workers, before they are reported
+    ///as pending
+    pub item_wait: u64,
+}
+
+///Limits of the bodies clients send to our detection endpoints. The bodies are streamed, and large ones are spilled to
+///temporary files instead of being held in memory.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    ///Largest body of the detect endpoint, which accepts every type of data
+    pub max_bytes: usize,
+    ///Largest body of the detectImage endpoint
+    pub image_max_bytes: usize,
+    ///Largest body of the detectImageFromUrl endpoint
+    pub url_max_bytes: usize,
+    ///Largest body of the batch endpoint
+    pub batch_max_bytes: usize,
+    ///Largest JSON body of the detection endpoints and NDJSON body of the batch endpoint. Those bodies are held in memory
+    ///to be parsed, and the base64 encoded data they hold is decoded into a second copy, so they are limited more tightly.
+    pub json_max_bytes: usize,
+    ///Largest body of the routes that don't stream their body, like the results our workers post
+    pub default_max_bytes: usize,
+    ///Bytes of an upload that are held in memory, before it is spilled to a temporary file
+    pub memory_threshold: usize,
+    ///Directory the temporary files are created in. The temporary directory of the system is used if it isn't set.
+    pub temp_dir: Option<PathBuf>,
+}
+
+///Limits of the images we decode. They are checked against the header of an image before it is decoded, so images that
+///declare huge dimensions can't exhaust our memory.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageConfig {
+    ///Widest image that is decoded in pixels
+    pub max_width: u32,
+    ///Highest image that is decoded in pixels
+    pub max_height: u32,
+    ///Most pixels an image that is decoded may have
+    pub max_pixels: u64,
+    ///Most bytes the decoder of an image may allocate
+    pub max_alloc: u64,
+    ///Images that are decoded at once
+    pub decode_concurrency: usize,
+}
+
+///Data types we scan, which a preprocessing pipeline has to be configured for each
+pub const DATA_TYPES: [&str; 1] = ["image"];
+
+///Preprocessing pipelines the data is run through before it is handed to our workers, keyed by the data type they are run on,
+///like [preprocessing.image]. The output of a pipeline is what is scanned, so changing a pipeline changes the scan jobs of the data.
+#[derive(Deserialize, Clone)]
+#[serde(transparent)]
+pub struct PreprocessingConfig {
+    pub pipelines: HashMap<String, PipelineConfig>,
+}
+
+impl PreprocessingConfig {
+    ///Returns the pipeline of a data type. Every one of `DATA_TYPES` has one once the configuration has been validated.
+    pub fn pipeline(&self, data_type: &str) -> &PipelineConfig {
+        return &self.pipelines[data_type];
+    }
+}
+
+///How the perceptual hashes our results are stored under are computed, see `perceptual_hash::ImageHash`
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HashingConfig {
+    ///Looks results up under the hash the database API computes for an image if there is none under its perceptual hash, and
+    ///stores them under the perceptual hash once found. Results scanned before we hashed images ourselves are migrated that way.
+    ///It costs a second decode and a call to the database API for every image without a result. It is safe to turn off once
+    ///no results are stored under the legacy hashes only, as images whose result was not migrated are scanned again then.
+    pub legacy_lookup: bool,
+    ///Where the hashes our results are stored under are kept, which near duplicates are looked up in
+    pub backend: HashIndexBackend,
+    ///Path of the SQLite database used by the sqlite backend
+    pub index_path: PathBuf,
+    ///URL of the Redis server used by the redis backend
+    pub redis_url: String,
+    ///Seconds between reads of the hashes other instances added to the index
+    pub reload_interval: u64,
+    ///Most bits the hash of an image may differ in from the hash of a result for the result to be returned for the image. Near
+    ///duplicates aren't looked up if it is 0.
+    pub max_distance: u32,
+}
+
+///Where the hashes our results are stored under are kept
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashIndexBackend {
+    ///In a SQLite database of this instance, which only finds near duplicates of the results this instance stored
+    Sqlite,
+    ///In Redis, so every instance finds near duplicates of the results stored by any of them
+    Redis,
+}
+
+impl FromStr for HashIndexBackend {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "sqlite" => Ok(HashIndexBackend::Sqlite),
+            "redis" => Ok(HashIndexBackend::Redis),
+            _ => Err(()),
+        };
+    }
+}
+
+///Where the lists of hashes whose images are blocked or allowed are kept
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashListBackend {
+    ///In a SQLite database of this instance
+    Sqlite,
+    ///In Redis, so every instance matches images against the same lists
+    Redis,
+}
+
+impl FromStr for HashListBackend {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "sqlite" => Ok(HashListBackend::Sqlite),
+            "redis" => Ok(HashListBackend::Redis),
+            _ => Err(()),
+        };
+    }
+}
+
+///Settings of the lists of hashes whose images are blocked or allowed without being scanned
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HashListConfig {
+    pub backend: HashListBackend,
+    ///Path of the SQLite database used by the sqlite backend
+    pub database_path: PathBuf,
+    ///URL of the Redis server used by the redis backend
+    pub redis_url: String,
+    ///Seconds between checks whether the lists have been changed by another instance, which reloads them
+    pub reload_interval: u64,
+    ///Largest file that can be imported into a list at once in bytes
+    pub max_import_bytes: usize,
+}
+
+///What happens when the cached result of an image has been produced by another model than the current one
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    ///The cached result is returned with `"stale": true` and the image is queued to be scanned again in the background
+    Background,
+    ///The image is scanned again and the client waits for the new result
+    Rescan,
+}
+
+impl FromStr for StalePolicy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "background" => Ok(StalePolicy::Background),
+            "rescan" => Ok(StalePolicy::Rescan),
+            _ => Err(()),
+        };
+    }
+}
+
+///The model our workers scan images with. Results record the model that produced them, so results of older models are noticed
+///and scanned again. Results are never considered stale while no version is known.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelConfig {
+    ///Name of the current model
+    pub name: String,
+    ///Version of the current model
+    pub version: String,
+    ///JSON file like `{"name": "...", "version": "..."}` that is written whenever a model is deployed. It takes precedence over
+    ///name and version and is reloaded when it changes.
+    pub manifest_path: Option<PathBuf>,
+    ///Seconds between checks whether the manifest changed
+    pub manifest_reload_interval: u64,
+    pub stale_policy: StalePolicy,
+    ///Policies of single projects, keyed by their project ID
+    pub projects: HashMap<String, ProjectModelConfig>,
+}
+
+///Model settings of a single project
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectModelConfig {
+    pub stale_policy: Option<StalePolicy>,
+}
+
+///How the image of a pipeline is resized to its target size
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    ///Scaled to fit within the target size, keeping its aspect ratio
+    Fit,
+    ///Stretched to the target size
+    Fill,
+    ///Scaled to cover the target size, keeping its aspect ratio, and cropped to it around its center
+    Crop,
+}
+
+///Filter the image of a pipeline is resized with
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+///Encoding of the output of a pipeline
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputEncoding {
+    Png,
+    Jpeg,
+}
+
+///Steps of a preprocessing pipeline, which are run in the order they are declared in
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    ///Version of the pipeline, which is recorded in the jobs handed to our workers. Change it whenever the pipeline changes,
+    ///so results can be traced to the preprocessing of their data.
+    pub version: String,
+    ///Rotates and flips the image as its EXIF orientation says
+    pub normalize_orientation: bool,
+    ///Draws transparent images onto the background, so the transparent pixels don't depend on how the model reads them
+    pub flatten_alpha: bool,
+    ///Color transparent images are drawn onto, as #rrggbb
+    pub background: String,
+    pub resize: ResizeMode,
+    pub filter: ResizeFilter,
+    ///Width the image is resized to in pixels
+    pub width: u32,
+    ///Height the image is resized to in pixels. The height is derived from the width and the aspect ratio of the image if it is 0.
+    pub height: u32,
+    pub encoding: OutputEncoding,
+    ///Quality of JPEG outputs from 1 to 100
+    pub jpeg_quality: u8,
+}
+
+///Limits of the downloads of images our clients send us the URLs of
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UrlFetchConfig {
+    ///Seconds a download may take in total, including its redirects
+    pub timeout: u64,
+    ///Most redirects a download follows
+    pub max_redirects: usize,
+    ///Largest image that is downloaded in bytes
+    pub max_bytes: u64,
+    ///Hosts images may be downloaded from, including their subdomains. Every host is allowed while it is empty.
+    pub allowed_hosts: Vec<String>,
+    ///Hosts images are never downloaded from, including their subdomains
+    pub denied_hosts: Vec<String>,
+    ///Networks that may be downloaded from, even though they are private or internal, in CIDR notation
+    pub allowed_networks: Vec<String>,
+    ///Networks that are never downloaded from in addition to the private and internal ones, in CIDR notation
+    pub denied_networks: Vec<String>,
+}
+
+///Where the callbacks that wait for the results of scan jobs and the outbox of deliveries to them are kept
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookBackend {
+    ///In a SQLite database of this instance, so results have to be posted to the instance the scan was requested from
+    Sqlite,
+    ///In Redis, so the result of a scan is delivered no matter which instance it is posted to
+    Redis,
+}
+
+impl FromStr for WebhookBackend {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "sqlite" => Ok(WebhookBackend::Sqlite),
+            "redis" => Ok(WebhookBackend::Redis),
+            _ => Err(()),
+        };
+    }
+}
+
+///Settings of the webhooks that are called when a scan completes
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub backend: WebhookBackend,
+    ///Path of the SQLite database used by the sqlite backend
+    pub database_path: PathBuf,
+    ///URL of the Redis server used by the redis backend
+    pub redis_url: String,
+    ///Secret the deliveries are signed with. Webhooks are disabled while it is empty.
+    pub signing_secret: Secret,
+    pub signing_secret_file: Option<PathBuf>,
+    ///Seconds a callback has to respond within
+    pub timeout: u64,
+    ///Attempts after which a delivery is given up
+    pub max_attempts: u32,
+    ///Seconds before the first retry of a delivery. The delay doubles with every failed attempt.
+    pub retry_base_delay: u64,
+    ///Longest delay between two attempts of a delivery in seconds
+    pub retry_max_delay: u64,
+    ///Callbacks of single projects, keyed by their project ID. They are called for every scan of the project that is
+    ///handed to our workers, in addition to the callback passed with the request.
+    pub projects: HashMap<String, ProjectWebhookConfig>,
+}
+
+///Webhook settings of a single project
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectWebhookConfig {
+    pub callback_url: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 8080,
+            base_url: "https://api.pamaxie.com".to_string(),
+            secret_reload_interval: 30,
+            db_api: DbApiConfig::default(),
+            s3: S3Config::default(),
+            sqs: SqsConfig::default(),
+            jwt: JwtConfig::default(),
+            auth_cache: AuthCacheConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            usage: UsageConfig::default(),
+            webhooks: WebhookConfig::default(),
+            batch: BatchConfig::default(),
+            url_fetch: UrlFetchConfig::default(),
+            uploads: UploadConfig::default(),
+            images: ImageConfig::default(),
+            preprocessing: PreprocessingConfig::default(),
+            hashing: HashingConfig::default(),
+            hash_lists: HashListConfig::default(),
+            models: ModelConfig::default(),
+        }
+    }
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_items: 1000,
+            max_sync_items: 20,
+            concurrency: 8,
+            item_wait: 10,
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            max_bytes: 1000000 * 250,
+            image_max_bytes: 1000000 * 50,
+            url_max_bytes: 64 * 1024,
+            batch_max_bytes: 1000000 * 250,
+            json_max_bytes: 1000000 * 16,
+            default_max_bytes: 1000000 * 10,
+            memory_threshold: 1000000 * 4,
+            temp_dir: None,
+        }
+    }
+}
+
+impl Default for PreprocessingConfig {
+    fn default() -> Self {
+        PreprocessingConfig {
+            pipelines: DATA_TYPES.iter().map(|data_type| (data_type.to_string(), PipelineConfig::default())).collect(),
+        }
+    }
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            version: "1".to_string(),
+            normalize_orientation: true,
+            flatten_alpha: false,
+            background: "#ffffff".to_string(),
+            resize: ResizeMode::Fit,
+            filter: ResizeFilter::Nearest,
+            width: 250,
+            height: 250,
+            encoding: OutputEncoding::Png,
+            jpeg_quality: 90,
+        }
+    }
+}
+
+impl Default for HashingConfig {
+    fn default() -> Self {
+        HashingConfig {
+            legacy_lookup: true,
+            backend: HashIndexBackend::Sqlite,
+            index_path: PathBuf::from("hashes.sqlite"),
+            redis_url: String::new(),
+            reload_interval: 10,
+            max_distance: 4,
+        }
+    }
+}
+
+impl Default for HashListConfig {
+    fn default() -> Self {
+        HashListConfig {
+            backend: HashListBackend::Sqlite,
+            database_path: PathBuf::from("hash_lists.sqlite"),
+            redis_url: String::new(),
+            reload_interval: 10,
+            max_import_bytes: 1000000 * 50,
+        }
+    }
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        ModelConfig {
+            name: String::new(),
+            version: String::new(),
+            manifest_path: None,
+            manifest_reload_interval: 60,
+            stale_policy: StalePolicy::Background,
+            projects: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig {
+            max_width: 16384,
+            max_height: 16384,
+            max_pixels: 100000000,
+            max_alloc: 1024 * 1024 * 512,
+            decode_concurrency: 4,
+        }
+    }
+}
+
+impl Default for UrlFetchConfig {
+    fn default() -> Self {
+        UrlFetchConfig {
+            timeout: 10,
+            max_redirects: 5,
+            max_bytes: 1000000 * 50,
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            allowed_networks: Vec::new(),
+            denied_networks: Vec::new(),
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            backend: WebhookBackend::Sqlite,
+            database_path: PathBuf::from("webhooks.sqlite"),
+            redis_url: String::new(),
+            signing_secret: Secret::default(),
+            signing_secret_file: None,
+            timeout: 10,
+            max_attempts: 10,
+            retry_base_delay: 10,
+            retry_max_delay: 3600,
+            projects: HashMap::new(),
+        }
+    }
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            backend: UsageBackend::Sqlite,
+            database_path: PathBuf::from("usage.sqlite"),
+            redis_url: String::new(),
+            monthly_requests: 0,
+            monthly_bytes: 0,
+            projects: HashMap::new(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            backend: RateLimitBackend::Memory,
+            redis_url: String::new(),
+            requests_per_second: 5.0,
+            burst: 20,
+            max_concurrent_scans: 10,
+            projects: HashMap::new(),
+        }
+    }
+}
+
+impl Default for AuthCacheConfig {
+    fn default() -> Self {
+        AuthCacheConfig {
+            capacity: 10000,
+            positive_ttl: 60,
+            negative_ttl: 10,
+        }
+    }
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            issuer: String::new(),
+            leeway: 60,
+            hmac_secret: Secret::default(),
+            hmac_secret_file: None,
+            public_key_files: Vec::new(),
+            jwks: String::new(),
+            jwks_refresh_interval: 3600,
+        }
+    }
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        S3Config {
+            access_key_id: String::new(),
+            secret_access_key: Secret::default(),
+            secret_access_key_file: None,
+            bucket: "production".to_string(),
+            region: String::new(),
+            url: String::new(),
+        }
+    }
+}
+
+impl Default for SqsConfig {
+    fn default() -> Self {
+        SqsConfig {
+            access_key_id: String::new(),
+            secret_access_key: Secret::default(),
+            secret_access_key_file: None,
+            region: "us-east-1".to_string(),
+            queue_url: String::new(),
+        }
+    }
+}
+
+///A single invalid or missing configuration value
+pub struct ConfigProblem {
+    ///Key of the value in the configuration file
+    pub field: String,
+    ///Environment variable that can be used to set the value, if there is one
+    pub env: Option<&'static str>,
+    pub message: String,
+}
+
+///Report of every problem that was found while loading the configuration
+pub struct ConfigError {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "The configuration is invalid ({} problem(s) found). Please refer to our documentation to see how to configure the API.", self.problems.len())?;
+
+        for problem in &self.problems {
+            match problem.env {
+                Some(env) => writeln!(f, "  - {} ({}): {}", problem.field, env, problem.message)?,
+                None => writeln!(f, "  - {}: {}", problem.field, problem.message)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigError {
+    fn single(field: &str, message: String) -> ConfigError {
+        ConfigError { problems: vec![ConfigProblem { field: field.to_string(), env: None, message }] }
+    }
+}
+
+impl Config {
+    ///Loads the configuration from the configuration file, the environment and the command line arguments and validates it
+    ///
+    /// # Arguments
+    /// args: &CliArgs - The parsed command line arguments
+    ///
+    /// # Returns
+    /// Result<Config, ConfigError> - The configuration, or a report of every problem that was found
+    pub fn load(args: &CliArgs) -> Result<Config, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+
+        let mut problems = Vec::new();
+        config.apply_env(&mut problems);
+        config.apply_secrets(&mut problems);
+        config.apply_args(args);
+        config.validate(&mut problems);
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+
+        return Ok(config);
+    }
+
+    ///Reads the configuration file at the given path
+    fn from_file(path: &PathBuf) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| ConfigError::single("--config", format!("Could not read the configuration file {}: {}", path.display(), err)))?;
+
+        return toml::from_str(&contents)
+            .map_err(|err| ConfigError::single("--config", format!("Could not parse the configuration file {}: {}", path.display(), err)));
+    }
+
+    ///Overwrites the values of the configuration with the ones set in the environment
+    fn apply_env(&mut self, problems: &mut Vec<ConfigProblem>) {
+        parse_from_env(problems, "port", "SCAN_API_PORT", &mut self.port);
+        parse_from_env(problems, "secret_reload_interval", "SECRET_RELOAD_INTERVAL", &mut self.secret_reload_interval);
+        parse_from_env(problems, "jwt.leeway", "JWT_LEEWAY", &mut self.jwt.leeway);
+        parse_from_env(problems, "jwt.jwks_refresh_interval", "JWT_JWKS_REFRESH_INTERVAL", &mut self.jwt.jwks_refresh_interval);
+        parse_from_env(problems, "auth_cache.capacity", "AUTH_CACHE_CAPACITY", &mut self.auth_cache.capacity);
+        parse_from_env(problems, "auth_cache.positive_ttl", "AUTH_CACHE_POSITIVE_TTL", &mut self.auth_cache.positive_ttl);
+        parse_from_env(problems, "auth_cache.negative_ttl", "AUTH_CACHE_NEGATIVE_TTL", &mut self.auth_cache.negative_ttl);
+        parse_from_env(problems, "rate_limit.backend", "RATE_LIMIT_BACKEND", &mut self.rate_limit.backend);
+        parse_from_env(problems, "rate_limit.requests_per_second", "RATE_LIMIT_REQUESTS_PER_SECOND", &mut self.rate_limit.requests_per_second);
+        parse_from_env(problems, "rate_limit.burst", "RATE_LIMIT_BURST", &mut self.rate_limit.burst);
+        parse_from_env(problems, "rate_limit.max_concurrent_scans", "RATE_LIMIT_MAX_CONCURRENT_SCANS", &mut self.rate_limit.max_concurrent_scans);
+        parse_from_env(problems, "usage.backend", "USAGE_BACKEND", &mut self.usage.backend);
+        parse_from_env(problems, "usage.database_path", "USAGE_DATABASE_PATH", &mut self.usage.database_path);
+        parse_from_env(problems, "usage.monthly_requests", "USAGE_MONTHLY_REQUESTS", &mut self.usage.monthly_requests);
+        parse_from_env(problems, "usage.monthly_bytes", "USAGE_MONTHLY_BYTES", &mut self.usage.monthly_bytes);
+        parse_from_env(problems, "webhooks.backend", "WEBHOOK_BACKEND", &mut self.webhooks.backend);
+        parse_from_env(problems, "webhooks.database_path", "WEBHOOK_DATABASE_PATH", &mut self.webhooks.database_path);
+        parse_from_env(problems, "webhooks.timeout", "WEBHOOK_TIMEOUT", &mut self.webhooks.timeout);
+        parse_from_env(problems, "webhooks.max_attempts", "WEBHOOK_MAX_ATTEMPTS", &mut self.webhooks.max_attempts);
+        parse_from_env(problems, "batch.max_items", "BATCH_MAX_ITEMS", &mut self.batch.max_items);
+        parse_from_env(problems, "batch.max_sync_items", "BATCH_MAX_SYNC_ITEMS", &mut self.batch.max_sync_items);
+        parse_from_env(problems, "batch.concurrency", "BATCH_CONCURRENCY", &mut self.batch.concurrency);
+        parse_from_env(problems, "batch.item_wait", "BATCH_ITEM_WAIT", &mut self.batch.item_wait);
+        parse_from_env(problems, "url_fetch.timeout", "URL_FETCH_TIMEOUT", &mut self.url_fetch.timeout);
+        parse_from_env(problems, "url_fetch.max_redirects", "URL_FETCH_MAX_REDIRECTS", &mut self.url_fetch.max_redirects);
+        parse_from_env(problems, "url_fetch.max_bytes", "URL_FETCH_MAX_BYTES", &mut self.url_fetch.max_bytes);
+        parse_from_env(problems, "uploads.max_bytes", "UPLOAD_MAX_BYTES", &mut self.uploads.max_bytes);
+        parse_from_env(problems, "images.max_width", "IMAGE_MAX_WIDTH", &mut self.images.max_width);
+        parse_from_env(problems, "images.max_height", "IMAGE_MAX_HEIGHT", &mut self.images.max_height);
+        parse_from_env(problems, "images.max_pixels", "IMAGE_MAX_PIXELS", &mut self.images.max_pixels);
+        parse_from_env(problems, "images.max_alloc", "IMAGE_MAX_ALLOC", &mut self.images.max_alloc);
+        parse_from_env(problems, "images.decode_concurrency", "IMAGE_DECODE_CONCURRENCY", &mut self.images.decode_concurrency);
+        parse_from_env(problems, "uploads.image_max_bytes", "UPLOAD_IMAGE_MAX_BYTES", &mut self.uploads.image_max_bytes);
+        parse_from_env(problems, "uploads.url_max_bytes", "UPLOAD_URL_MAX_BYTES", &mut self.uploads.url_max_bytes);
+        parse_from_env(problems, "uploads.batch_max_bytes", "UPLOAD_BATCH_MAX_BYTES", &mut self.uploads.batch_max_bytes);
+        parse_from_env(problems, "uploads.json_max_bytes", "UPLOAD_JSON_MAX_BYTES", &mut self.uploads.json_max_bytes);
+        parse_from_env(problems, "uploads.default_max_bytes", "UPLOAD_DEFAULT_MAX_BYTES", &mut self.uploads.default_max_bytes);
+        parse_from_env(problems, "uploads.memory_threshold", "UPLOAD_MEMORY_THRESHOLD", &mut self.uploads.memory_threshold);
+        parse_from_env(problems, "hashing.legacy_lookup", "HASHING_LEGACY_LOOKUP", &mut self.hashing.legacy_lookup);
+        parse_from_env(problems, "hashing.backend", "HASHING_BACKEND", &mut self.hashing.backend);
+        parse_from_env(problems, "hashing.index_path", "HASHING_INDEX_PATH", &mut self.hashing.index_path);
+        parse_from_env(problems, "hashing.reload_interval", "HASHING_RELOAD_INTERVAL", &mut self.hashing.reload_interval);
+        parse_from_env(problems, "hashing.max_distance", "HASHING_MAX_DISTANCE", &mut self.hashing.max_distance);
+        parse_from_env(problems, "hash_lists.backend", "HASH_LISTS_BACKEND", &mut self.hash_lists.backend);
+        parse_from_env(problems, "hash_lists.database_path", "HASH_LISTS_DATABASE_PATH", &mut self.hash_lists.database_path);
+        parse_from_env(problems, "hash_lists.reload_interval", "HASH_LISTS_RELOAD_INTERVAL", &mut self.hash_lists.reload_interval);
+        parse_from_env(problems, "hash_lists.max_import_bytes", "HASH_LISTS_MAX_IMPORT_BYTES", &mut self.hash_lists.max_import_bytes);
+        parse_from_env(problems, "models.manifest_reload_interval", "MODEL_MANIFEST_RELOAD_INTERVAL", &mut self.models.manifest_reload_interval);
+        parse_from_env(problems, "models.stale_policy", "MODEL_STALE_POLICY", &mut self.models.stale_policy);
+
+        override_from_env(&mut self.base_url, "PAM_BASE_URL");
+        override_from_env(&mut self.db_api.url, "DB_API_URL");
+        override_from_env(&mut self.s3.access_key_id, "S3_ACCESS_KEY_ID");
+        override_from_env(&mut self.s3.bucket, "S3_BUCKET_NAME");
+        override_from_env(&mut self.s3.region, "S3_STORAGE_REGION");
+        override_from_env(&mut self.s3.url, "S3_URL");
+        override_from_env(&mut self.sqs.access_key_id, "AWS_ACCESS_KEY_ID");
+        override_from_env(&mut self.sqs.region, "AWS_DEFAULT_REGION");
+        override_from_env(&mut self.sqs.queue_url, "AWS_SQS_QUEUE_URL_0");
+        override_from_env(&mut self.jwt.issuer, "JWT_ISSUER");
+        override_from_env(&mut self.jwt.jwks, "JWT_JWKS");
+        override_from_env(&mut self.rate_limit.redis_url, "RATE_LIMIT_REDIS_URL");
+        override_from_env(&mut self.usage.redis_url, "USAGE_REDIS_URL");
+        override_from_env(&mut self.hash_lists.redis_url, "HASH_LISTS_REDIS_URL");
+        override_from_env(&mut self.webhooks.redis_url, "WEBHOOK_REDIS_URL");
+        override_from_env(&mut self.hashing.redis_url, "HASHING_REDIS_URL");
+        override_from_env(&mut self.models.name, "MODEL_NAME");
+        override_from_env(&mut self.models.version, "MODEL_VERSION");
+
+        if let Some(files) = env_value("JWT_PUBLIC_KEY_FILES") {
+            self.jwt.public_key_files = files.split(',').map(|file| PathBuf::from(file.trim())).collect();
+        }
+
+        if let Some(path) = env_value("MODEL_MANIFEST_PATH") {
+            self.models.manifest_path = Some(PathBuf::from(path));
+        }
+
+        if let Some(dir) = env_value("UPLOAD_TEMP_DIR") {
+            self.uploads.temp_dir = Some(PathBuf::from(dir));
+        }
+
+        list_from_env(&mut self.url_fetch.allowed_hosts, "URL_FETCH_ALLOWED_HOSTS");
+        list_from_env(&mut self.url_fetch.denied_hosts, "URL_FETCH_DENIED_HOSTS");
+        list_from_env(&mut self.url_fetch.allowed_networks, "URL_FETCH_ALLOWED_NETWORKS");
+        list_from_env(&mut self.url_fetch.denied_networks, "URL_FETCH_DENIED_NETWORKS");
+    }
+
+    ///Loads the secrets from the files named in the configuration file or the environment, or from the environment itself
+    fn apply_secrets(&mut self, problems: &mut Vec<ConfigProblem>) {
+        resolve_secret(problems, "db_api.auth_token", "PAM_AUTH_TOKEN", "PAM_AUTH_TOKEN_FILE", &mut self.db_api.auth_token, &self.db_api.auth_token_file);
+        resolve_secret(problems, "s3.secret_access_key", "S3_ACCESS_KEY_SECRET", "S3_ACCESS_KEY_SECRET_FILE", &mut self.s3.secret_access_key, &self.s3.secret_access_key_file);
+        resolve_secret(problems, "sqs.secret_access_key", "AWS_SECRET_ACCESS_KEY", "AWS_SECRET_ACCESS_KEY_FILE", &mut self.sqs.secret_access_key, &self.sqs.secret_access_key_file);
+        resolve_secret(problems, "jwt.hmac_secret", "JWT_HMAC_SECRET", "JWT_HMAC_SECRET_FILE", &mut self.jwt.hmac_secret, &self.jwt.hmac_secret_file);
+        resolve_secret(problems, "webhooks.signing_secret", "WEBHOOK_SIGNING_SECRET", "WEBHOOK_SIGNING_SECRET_FILE", &mut self.webhooks.signing_secret, &self.webhooks.signing_secret_file);
+    }
+
+    ///Returns every secret of the configuration together with its name, so they can be watched for changes
+    pub fn secrets(&self) -> Vec<(&'static str, Secret)> {
+        return vec![
+            ("db_api.auth_token", self.db_api.auth_token.clone()),
+            ("s3.secret_access_key", self.s3.secret_access_key.clone()),
+            ("sqs.secret_access_key", self.sqs.secret_access_key.clone()),
+            ("jwt.hmac_secret", self.jwt.hmac_secret.clone()),
+            ("webhooks.signing_secret", self.webhooks.signing_secret.clone()),
+        ];
+    }
+
+    ///Overwrites the values of the configuration with the ones passed on the command line
+    fn apply_args(&mut self, args: &CliArgs) {
+        if let Some(port) = args.port {
+            self.port = port;
+        }
+
+        override_from_arg(&mut self.base_url, &args.base_url);
+        override_from_arg(&mut self.db_api.url, &args.db_api_url);
+        override_from_arg(&mut self.s3.url, &args.s3_url);
+        override_from_arg(&mut self.s3.bucket, &args.s3_bucket);
+        override_from_arg(&mut self.s3.region, &args.s3_region);
+        override_from_arg(&mut self.sqs.queue_url, &args.sqs_queue_url);
+        override_from_arg(&mut self.sqs.region, &args.sqs_region);
+    }
+
+    ///Validates the configuration and adds every problem that was found to the given list
+    fn validate(&self, problems: &mut Vec<ConfigProblem>) {
+        require_url(problems, "base_url", "PAM_BASE_URL", &self.base_url);
+        require_url(problems, "db_api.url", "DB_API_URL", &self.db_api.url);
+        require(problems, "db_api.auth_token", "PAM_AUTH_TOKEN", &self.db_api.auth_token.get());
+        require(problems, "s3.access_key_id", "S3_ACCESS_KEY_ID", &self.s3.access_key_id);
+        require(problems, "s3.secret_access_key", "S3_ACCESS_KEY_SECRET", &self.s3.secret_access_key.get());
+        require(problems, "s3.bucket", "S3_BUCKET_NAME", &self.s3.bucket);
+        require_url(problems, "s3.url", "S3_URL", &self.s3.url);
+        require(problems, "sqs.access_key_id", "AWS_ACCESS_KEY_ID", &self.sqs.access_key_id);
+        require(problems, "sqs.secret_access_key", "AWS_SECRET_ACCESS_KEY", &self.sqs.secret_access_key.get());
+        require(problems, "sqs.region", "AWS_DEFAULT_REGION", &self.sqs.region);
+        require_url(problems, "sqs.queue_url", "AWS_SQS_QUEUE_URL_0", &self.sqs.queue_url);
+        require(problems, "jwt.issuer", "JWT_ISSUER", &self.jwt.issuer);
+
+        if self.jwt.hmac_secret.get().is_empty() && self.jwt.public_key_files.is_empty() && self.jwt.jwks.is_empty()
+            && !problems.iter().any(|problem| problem.field.starts_with("jwt.hmac_secret")) {
+            problems.push(ConfigProblem {
+                field: "jwt".to_string(),
+                env: None,
+                message: "no key to verify bearer tokens has been set. Please set jwt.hmac_secret (JWT_HMAC_SECRET), jwt.public_key_files (JWT_PUBLIC_KEY_FILES) or jwt.jwks (JWT_JWKS)".to_string(),
+            });
+        }
+
+        if self.rate_limit.backend == RateLimitBackend::Redis {
+            require(problems, "rate_limit.redis_url", "RATE_LIMIT_REDIS_URL", &self.rate_limit.redis_url);
+        }
+
+        validate_rate_limit(problems, "rate_limit", self.rate_limit.requests_per_second, self.rate_limit.burst);
+
+        for (project_id, limits) in &self.rate_limit.projects {
+            let field = format!("rate_limit.projects.{}", project_id);
+
+            if project_id.parse::<u64>().is_err() {
+                problems.push(ConfigProblem { field: field.to_string(), env: None, message: "is not a valid project ID".to_string() });
+            }
+
+            validate_rate_limit(problems, &field,
+                limits.requests_per_second.unwrap_or(self.rate_limit.requests_per_second),
+                limits.burst.unwrap_or(self.rate_limit.burst));
+        }
+
+        if self.usage.backend == UsageBackend::Redis {
+            require(problems, "usage.redis_url", "USAGE_REDIS_URL", &self.usage.redis_url);
+        }
+
+        if self.hash_lists.backend == HashListBackend::Redis {
+            require(problems, "hash_lists.redis_url", "HASH_LISTS_REDIS_URL", &self.hash_lists.redis_url);
+        }
+
+        if self.hash_lists.reload_interval == 0 {
+            problems.push(ConfigProblem { field: "hash_lists.reload_interval".to_string(), env: Some("HASH_LISTS_RELOAD_INTERVAL"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.hashing.backend == HashIndexBackend::Redis {
+            require(problems, "hashing.redis_url", "HASHING_REDIS_URL", &self.hashing.redis_url);
+        }
+
+        if self.hashing.reload_interval == 0 {
+            problems.push(ConfigProblem { field: "hashing.reload_interval".to_string(), env: Some("HASHING_RELOAD_INTERVAL"), message: "has to be at least 1".to_string() });
+        }
+
+        for project_id in self.usage.projects.keys().filter(|project_id| project_id.parse::<u64>().is_err()) {
+            problems.push(ConfigProblem { field: format!("usage.projects.{}", project_id), env: None, message: "is not a valid project ID".to_string() });
+        }
+
+        if self.webhooks.backend == WebhookBackend::Redis {
+            require(problems, "webhooks.redis_url", "WEBHOOK_REDIS_URL", &self.webhooks.redis_url);
+        }
+
+        if self.webhooks.max_attempts == 0 {
+            problems.push(ConfigProblem { field: "webhooks.max_attempts".to_string(), env: Some("WEBHOOK_MAX_ATTEMPTS"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.webhooks.timeout == 0 {
+            problems.push(ConfigProblem { field: "webhooks.timeout".to_string(), env: Some("WEBHOOK_TIMEOUT"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.batch.max_items == 0 {
+            problems.push(ConfigProblem { field: "batch.max_items".to_string(), env: Some("BATCH_MAX_ITEMS"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.batch.concurrency == 0 {
+            problems.push(ConfigProblem { field: "batch.concurrency".to_string(), env: Some("BATCH_CONCURRENCY"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.url_fetch.timeout == 0 {
+            problems.push(ConfigProblem { field: "url_fetch.timeout".to_string(), env: Some("URL_FETCH_TIMEOUT"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.url_fetch.max_bytes == 0 {
+            problems.push(ConfigProblem { field: "url_fetch.max_bytes".to_string(), env: Some("URL_FETCH_MAX_BYTES"), message: "has to be at least 1".to_string() });
+        }
+
+        if self.models.manifest_reload_interval == 0 {
+            problems.push(ConfigProblem { field: "models.manifest_reload_interval".to_string(), env: Some("MODEL_MANIFEST_RELOAD_INTERVAL"), message: "has to be at least 1".to_string() });
+        }
+
+        for (field, env, max_bytes) in [
+            ("uploads.max_bytes", "UPLOAD_MAX_BYTES", self.uploads.max_bytes),
+            ("uploads.image_max_bytes", "UPLOAD_IMAGE_MAX_BYTES", self.uploads.image_max_bytes),
+            ("uploads.url_max_bytes", "UPLOAD_URL_MAX_BYTES", self.uploads.url_max_bytes),
+            ("uploads.batch_max_bytes", "UPLOAD_BATCH_MAX_BYTES", self.uploads.batch_max_bytes),
+            ("uploads.json_max_bytes", "UPLOAD_JSON_MAX_BYTES", self.uploads.json_max_bytes),
+            ("uploads.default_max_bytes", "UPLOAD_DEFAULT_MAX_BYTES", self.uploads.default_max_bytes),
+            ("hash_lists.max_import_bytes", "HASH_LISTS_MAX_IMPORT_BYTES", self.hash_lists.max_import_bytes),
+        ] {
+            if max_bytes == 0 {
+                problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: "has to be at least 1".to_string() });
+            }
+        }
+
+        for (field, env, limit) in [
+            ("images.max_width", "IMAGE_MAX_WIDTH", self.images.max_width as u64),
+            ("images.max_height", "IMAGE_MAX_HEIGHT", self.images.max_height as u64),
+            ("images.max_pixels", "IMAGE_MAX_PIXELS", self.images.max_pixels),
+            ("images.max_alloc", "IMAGE_MAX_ALLOC", self.images.max_alloc),
+            ("images.decode_concurrency", "IMAGE_DECODE_CONCURRENCY", self.images.decode_concurrency as u64),
+        ] {
+            if limit == 0 {
+                problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: "has to be at least 1".to_string() });
+            }
+        }
+
+        for data_type in DATA_TYPES {
+            if !self.preprocessing.pipelines.contains_key(data_type) {
+                problems.push(ConfigProblem { field: format!("preprocessing.{}", data_type), env: None, message: "has to be set, as we scan this data type".to_string() });
+            }
+        }
+
+        for (data_type, pipeline) in &self.preprocessing.pipelines {
+            if DATA_TYPES.contains(&data_type.as_str()) {
+                validate_pipeline(problems, &format!("preprocessing.{}", data_type), pipeline);
+            } else {
+                problems.push(ConfigProblem { field: format!("preprocessing.{}", data_type), env: None, message: format!("is not a data type we scan, which are {}", DATA_TYPES.join(", ")) });
+            }
+        }
+
+        //Hashes have 64 bits, so they can't differ in more
+        if self.hashing.max_distance > 64 {
+            problems.push(ConfigProblem { field: "hashing.max_distance".to_string(), env: Some("HASHING_MAX_DISTANCE"), message: "has to be at most 64".to_string() });
+        }
+
+        if self.uploads.temp_dir.as_ref().is_some_and(|dir| !dir.is_dir()) {
+            problems.push(ConfigProblem { field: "uploads.temp_dir".to_string(), env: Some("UPLOAD_TEMP_DIR"), message: "is not a directory".to_string() });
+        }
+
+        for (field, env, networks) in [
+            ("url_fetch.allowed_networks", "URL_FETCH_ALLOWED_NETWORKS", &self.url_fetch.allowed_networks),
+            ("url_fetch.denied_networks", "URL_FETCH_DENIED_NETWORKS", &self.url_fetch.denied_networks),
+        ] {
+            for network in networks.iter().filter(|network| url_fetcher::parse_network(network).is_none()) {
+                problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: format!("\"{}\" is not a valid network", network) });
+            }
+        }
+
+        for (project_id, webhook) in &self.webhooks.projects {
+            let field = format!("webhooks.projects.{}", project_id);
+
+            if project_id.parse::<u64>().is_err() {
+                problems.push(ConfigProblem { field: field.to_string(), env: None, message: "is not a valid project ID".to_string() });
+            }
+
+            if !is_http_url(&webhook.callback_url) {
+                problems.push(ConfigProblem { field: format!("{}.callback_url", field), env: None, message: format!("\"{}\" is not a valid http(s) URL", webhook.callback_url) });
+            }
+        }
+
+        for project_id in self.models.projects.keys().filter(|project_id| project_id.parse::<u64>().is_err()) {
+            problems.push(ConfigProblem { field: format!("models.projects.{}", project_id), env: None, message: "is not a valid project ID".to_string() });
+        }
+
+        if !self.webhooks.projects.is_empty() && self.webhooks.signing_secret.get().is_empty() {
+            require(problems, "webhooks.signing_secret", "WEBHOOK_SIGNING_SECRET", "");
+        }
+
+        if self.s3.region.is_empty() {
+            eprintln!("The S3 storage region (s3.region / S3_STORAGE_REGION) has not been set. If this was intentional you can ignore this warning.");
+        }
+    }
+}
+
+///Returns the value of the environment variable, or none if it is not set or empty
+fn env_value(env_var_name: &str) -> Option<String> {
+    let value = get_env_variable(env_var_name.to_string(), "".to_string());
+
+    if value.is_empty() {
+        return None;
+    }
+
+    return Some(value);
+}
+
+///Parses the value of the environment variable into the target, or reports the value if it can't be parsed
+fn parse_from_env<T: FromStr>(problems: &mut Vec<ConfigProblem>, field: &str, env: &'static str, target: &mut T) {
+    if let Some(value) = env_value(env) {
+        match value.parse() {
+            Ok(value) => *target = value,
+            Err(_) => problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: format!("\"{}\" is not a valid value", value) }),
+        }
+    }
+}
+
+///Replaces the list with the comma separated values of the environment variable, if it is set
+fn list_from_env(target: &mut Vec<String>, env_var_name: &str) {
+    if let Some(value) = env_value(env_var_name) {
+        *target = value.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect();
+    }
+}
+
+fn override_from_env(target: &mut String, env_var_name: &str) {
+    if let Some(value) = env_value(env_var_name) {
+        *target = value;
+    }
+}
+
+fn override_from_arg(target: &mut String, arg: &Option<String>) {
+    if let Some(value) = arg {
+        *target = value.to_string();
+    }
+}
+
+///Resolves a secret, in order of precedence, from the `*_FILE` environment variable, the plain environment variable,
+///the `*_file` key of the configuration file or its plain key. Setting both environment variables is an error.
+fn resolve_secret(problems: &mut Vec<ConfigProblem>, field: &str, env: &'static str, file_env: &'static str, target: &mut Secret, file: &Option<PathBuf>) {
+    let env_secret = env_value(env);
+    let env_file = env_value(file_env);
+
+    if env_secret.is_some() && env_file.is_some() {
+        problems.push(ConfigProblem { field: field.to_string(), env: Some(file_env), message: format!("can't be set together with {}", env) });
+        return;
+    }
+
+    let file = match env_file {
+        Some(env_file) => Some(PathBuf::from(env_file)),
+        None if env_secret.is_some() => None,
+        None => file.clone(),
+    };
+
+    if let Some(value) = env_secret {
+        *target = Secret::new(value);
+    }
+
+    if let Some(file) = file {
+        match Secret::from_file(&file) {
+            Ok(secret) => *target = secret,
+            Err(err) => problems.push(ConfigProblem { field: format!("{}_file", field), env: Some(file_env), message: err }),
+        }
+    }
+}
+
+fn require(problems: &mut Vec<ConfigProblem>, field: &str, env: &'static str, value: &str) {
+    //Don't report a missing value if we already reported why it could not be loaded
+    if value.is_empty() && !problems.iter().any(|problem| problem.field.starts_with(field)) {
+        problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: "is required but has not been set".to_string() });
+    }
+}
+
+fn require_url(problems: &mut Vec<ConfigProblem>, field: &str, env: &'static str, value: &str) {
+    if value.is_empty() {
+        require(problems, field, env, value);
+        return;
+    }
+
+    if !is_http_url(value) {
+        problems.push(ConfigProblem { field: field.to_string(), env: Some(env), message: format!("\"{}\" is not a valid http(s) URL", value) });
+    }
+}
+
+///Returns true if the value is an absolute http or https URL
+pub fn is_http_url(value: &str) -> bool {
+    return Url::parse(value).is_ok_and(|url| url.scheme() == "http" || url.scheme() == "https");
+}
+
+fn validate_pipeline(problems: &mut Vec<ConfigProblem>, field: &str, pipeline: &PipelineConfig) {
+    if pipeline.version.is_empty() {
+        problems.push(ConfigProblem { field: format!("{}.version", field), env: None, message: "has to be set".to_string() });
+    }
+
+    if parse_color(&pipeline.background).is_none() {
+        problems.push(ConfigProblem { field: format!("{}.background", field), env: None, message: format!("\"{}\" is not a color formatted as #rrggbb", pipeline.background) });
+    }
+
+    if pipeline.width == 0 {
+        problems.push(ConfigProblem { field: format!("{}.width", field), env: None, message: "has to be at least 1".to_string() });
+    }
+
+    if pipeline.height == 0 && pipeline.resize != ResizeMode::Fit {
+        problems.push(ConfigProblem { field: format!("{}.height", field), env: None, message: "can only be derived from the width when resizing to fit".to_string() });
+    }
+
+    if pipeline.jpeg_quality == 0 || pipeline.jpeg_quality > 100 {
+        problems.push(ConfigProblem { field: format!("{}.jpeg_quality", field), env: None, message: "has to be between 1 and 100".to_string() });
+    }
+}
+
+///Parses a color formatted as #rrggbb
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+
+    return Some([channel(0)?, channel(2)?, channel(4)?]);
+}
+
+fn validate_rate_limit(problems: &mut Vec<ConfigProblem>, field: &str, requests_per_second: f64, burst: u32) {
+    if !requests_per_second.is_finite() || requests_per_second <= 0.0 {
+        problems.push(ConfigProblem { field: format!("{}.requests_per_second", field), env: None, message: "has to be greater than 0".to_string() });
+    }
+
+    if burst == 0 {
+        problems.push(ConfigProblem { field: format!("{}.burst", field), env: None, message: "has to be at least 1".to_string() });
+    }
+}
@@ -0,0 +1,200 @@
+use std::{future::{ready, Ready}, rc::Rc};
+use actix_web::{dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform}, body::EitherBody, web, Error, FromRequest, HttpMessage, HttpRequest, ResponseError};
+use futures::future::LocalBoxFuture;
+use crate::config::Config;
+use super::auth_cache::{hash_token, AuthCache, AuthDecision};
+use super::scan_error::ScanError;
+use super::token_verifier::TokenVerifier;
+use super::web_helper;
+
+///Level of authentication a route requires
+#[derive(Clone, Copy, PartialEq)]
+pub enum AuthLevel {
+    ///Anyone can access the route
+    Public,
+    ///Any client with a valid token can access the route
+    Authenticated,
+    ///Only clients with one of pamaxie's internal tokens can access the route
+    Internal,
+}
+
+///A client that has been authenticated for the current request. Handlers receive it by taking it as an argument.
+#[derive(Clone)]
+#[allow(dead_code)]
+pub struct AuthenticatedClient {
+    pub owner_id: u64,
+    pub project_id: u64,
+    pub machine_guid: String,
+    ///True if the client authenticated with one of pamaxie's internal tokens
+    pub is_internal: bool,
+}
+
+impl AuthenticatedClient {
+    ///Rejects clients that didn't authenticate with one of pamaxie's internal tokens. Handlers of internal routes check this
+    ///themselves as well, so they stay protected even if a request slipped past the level of its route.
+    pub fn require_internal(&self) -> Result<(), ScanError> {
+        if !self.is_internal {
+            return Err(ScanError::Forbidden("Currently only pamaxie's own clients are allowed to access this route. Stay tuned for more.".to_string()));
+        }
+
+        return Ok(());
+    }
+}
+
+impl FromRequest for AuthenticatedClient {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        //Only set by our Authentication middleware, so this fails for routes that have been declared public
+        return ready(req.extensions().get::<AuthenticatedClient>().cloned()
+            .ok_or_else(|| ScanError::Unauthorized("This route requires authentication".to_string()).into()));
+    }
+}
+
+///Middleware that authenticates every request once, before it reaches a handler, and rejects it if the client doesn't have the level
+///of authentication the route requires. Routes require authentication unless they are declared otherwise.
+///
+/// # Example
+/// ```
+/// App::new().wrap(Authentication::new()
+///     .route("/scan/v1/status", AuthLevel::Public)
+///     .route("/scan/v1/worker/", AuthLevel::Internal))
+/// ```
+pub struct Authentication {
+    routes: Rc<Vec<(&'static str, AuthLevel)>>,
+}
+
+impl Authentication {
+    pub fn new() -> Authentication {
+        return Authentication { routes: Rc::new(Vec::new()) };
+    }
+
+    ///Declares the level of authentication required for every path that starts with the given prefix. The longest matching prefix wins.
+    ///Prefixes are matched against the percent-decoded path, which is what our routes are matched against as well.
+    pub fn route(mut self, path_prefix: &'static str, level: AuthLevel) -> Authentication {
+        Rc::get_mut(&mut self.routes).unwrap().push((path_prefix, level));
+        return self;
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(AuthenticationMiddleware { service: Rc::new(service), routes: self.routes.clone() }));
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+    routes: Rc<Vec<(&'static str, AuthLevel)>>,
+}
+
+impl<S> AuthenticationMiddleware<S> {
+    ///Returns the level of authentication the given path requires
+    fn get_level(&self, path: &str) -> AuthLevel {
+        return self.routes.iter()
+            .filter(|(prefix, _)| path.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(AuthLevel::Authenticated);
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        //The raw path may percent-encode characters of the prefix, e.g. /scan/v1/%77orker/, which still routes to the worker
+        //endpoints, so the level is picked from the decoded path our routes are matched against
+        let level = self.get_level(req.match_info().as_str());
+
+        return Box::pin(async move {
+            if level == AuthLevel::Public {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            }
+
+            let client = match authenticate(req.parts_mut().0).await {
+                Ok(client) => client,
+                Err(err) => return Ok(req.into_response(err.error_response()).map_into_right_body()),
+            };
+
+            if level == AuthLevel::Internal {
+                if let Err(err) = client.require_internal() {
+                    return Ok(req.into_response(err.error_response()).map_into_right_body());
+                }
+            }
+
+            req.extensions_mut().insert(client);
+            return service.call(req).await.map(ServiceResponse::map_into_left_body);
+        });
+    }
+}
+
+///Authenticates the client of a request by verifying its bearer token and asking our database API whether the token is allowed to scan.
+///The decisions of our database API are cached, so it is only asked once in a while for each token.
+///
+/// # Arguments
+/// req: &HttpRequest - The request to authenticate
+///
+/// # Returns
+/// Result<AuthenticatedClient, ScanError> - The authenticated client, or the error to reject the request with
+async fn authenticate(req: &HttpRequest) -> Result<AuthenticatedClient, ScanError> {
+    let config = req.app_data::<web::Data<Config>>().expect("The configuration has not been registered as app data");
+    let verifier = req.app_data::<web::Data<TokenVerifier>>().expect("The token verifier has not been registered as app data");
+    let auth_cache = req.app_data::<web::Data<AuthCache>>().expect("The authentication cache has not been registered as app data");
+    let http_client = req.app_data::<web::Data<reqwest::Client>>().expect("The HTTP client has not been registered as app data");
+
+    let payload = web_helper::get_scan_token_payload(req, verifier)
+        .map_err(|err| ScanError::Unauthorized(format!("The token sent to authorize with us is invalid. {}", err)))?;
+
+    //Verified above, so the token is present
+    let token = web_helper::get_bearer_token(req).unwrap();
+    let token_hash = hash_token(token);
+
+    let decision = match auth_cache.get(&token_hash) {
+        Some(decision) => decision,
+        None => {
+            let (can_authenticate, is_internal) = futures::join!(
+                web_helper::check_auth(http_client, config, token),
+                web_helper::is_internal_auth(http_client, config, token));
+
+            let decision = match (can_authenticate, is_internal) {
+                (Some(can_authenticate), Some(is_internal)) => AuthDecision { can_authenticate, is_internal },
+                _ => return Err(ScanError::AuthUnavailable),
+            };
+
+            auth_cache.insert(token_hash, decision, payload.exp);
+            decision
+        }
+    };
+
+    if !decision.can_authenticate {
+        return Err(ScanError::Unauthorized("The token sent to authorize with us has been rejected.".to_string()));
+    }
+
+    return Ok(AuthenticatedClient {
+        owner_id: payload.ownerId,
+        project_id: payload.projectId,
+        machine_guid: payload.apiTokenMachineGuid,
+        is_internal: decision.is_internal,
+    });
+}
@@ -0,0 +1,74 @@
+use std::{sync::Mutex, time::{Duration, SystemTime, UNIX_EPOCH}};
+use sha2::{Digest, Sha256};
+use crate::config::AuthCacheConfig;
+use super::ttl_map::TtlMap;
+
+///Hash of a bearer token. We never keep the tokens themselves in memory longer than a request.
+pub type TokenHash = [u8; 32];
+
+///Decision of our database API on whether a token may access our API
+#[derive(Clone, Copy)]
+pub struct AuthDecision {
+    pub can_authenticate: bool,
+    pub is_internal: bool,
+}
+
+///Bounded in-memory cache of the authentication decisions of our database API, so we don't have to ask it on every request
+pub struct AuthCache {
+    entries: Mutex<TtlMap<TokenHash, AuthDecision>>,
+    capacity: usize,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+}
+
+///Returns the hash of a bearer token, which is used as its key in the cache
+pub fn hash_token(token: &str) -> TokenHash {
+    return Sha256::digest(token.as_bytes()).into();
+}
+
+impl AuthCache {
+    pub fn new(config: &AuthCacheConfig) -> AuthCache {
+        return AuthCache {
+            entries: Mutex::new(TtlMap::new(config.capacity)),
+            capacity: config.capacity,
+            positive_ttl: Duration::from_secs(config.positive_ttl),
+            negative_ttl: Duration::from_secs(config.negative_ttl),
+        };
+    }
+
+    ///Returns the cached decision for the token, if there is one that hasn't expired yet
+    pub fn get(&self, token_hash: &TokenHash) -> Option<AuthDecision> {
+        return self.entries.lock().unwrap().get(token_hash).copied();
+    }
+
+    ///Caches a decision. It is never cached past the expiry of the token it was made for.
+    ///
+    /// # Arguments
+    /// token_hash: TokenHash - The hash of the token the decision was made for
+    /// decision: AuthDecision - The decision of our database API
+    /// token_exp: i64 - The exp claim of the token, in seconds since the unix epoch
+    pub fn insert(&self, token_hash: TokenHash, decision: AuthDecision, token_exp: i64) {
+        let ttl = if decision.can_authenticate { self.positive_ttl } else { self.negative_ttl };
+        let now_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let ttl = ttl.min(Duration::from_secs((token_exp - now_unix).max(0) as u64));
+
+        if ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+
+        self.entries.lock().unwrap().insert(token_hash, decision, ttl);
+    }
+
+    ///Removes the decision for a token, e.g. because it has been revoked
+    ///
+    /// # Returns
+    /// bool - True if a decision was cached for the token
+    pub fn evict(&self, token_hash: &TokenHash) -> bool {
+        return self.entries.lock().unwrap().remove(token_hash).is_some();
+    }
+
+    ///Removes every cached decision
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
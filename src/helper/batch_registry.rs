@@ -0,0 +1,121 @@
+use std::{sync::Mutex, time::Duration};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use super::{scan_error::ErrorDetail, ttl_map::TtlMap};
+
+///Time a batch job is remembered after it has been submitted, so its results can still be polled
+const BATCH_RETENTION: Duration = Duration::from_secs(3600);
+///Most batch jobs that are remembered at once
+const MAX_BATCHES: usize = 1000;
+
+///Status of a single item of a batch
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    ///The item has not been scanned yet
+    Queued,
+    ///The item has been handed to our workers, but its result did not arrive in time. It can be polled with its job ID.
+    Pending,
+    ///The item has been scanned
+    Done,
+    ///The item could not be scanned
+    Failed,
+}
+
+///Outcome of a single item of a batch
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItem {
+    ///Position of the item in the batch
+    pub index: usize,
+    ///ID the client gave the item, if it gave it one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub status: ItemStatus,
+    ///ID of the scan job of the item, which is the hash of its data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+impl BatchItem {
+    pub fn new(index: usize, id: Option<String>) -> BatchItem {
+        return BatchItem { index, id, status: ItemStatus::Queued, job_id: None, result: None, error: None };
+    }
+}
+
+///Status of a batch, as returned by our batch endpoints
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchStatus {
+    ///ID of the batch job, if the batch is processed in the background
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    ///running or done
+    pub status: &'static str,
+    pub total: usize,
+    ///Items that are not queued anymore
+    pub completed: usize,
+    ///Items in the order they have been sent in
+    pub items: Vec<BatchItem>,
+}
+
+impl BatchStatus {
+    pub fn new(id: Option<String>, items: Vec<BatchItem>) -> BatchStatus {
+        let completed = items.iter().filter(|item| item.status != ItemStatus::Queued).count();
+        let status = if completed == items.len() { "done" } else { "running" };
+
+        return BatchStatus { id, status, total: items.len(), completed, items };
+    }
+}
+
+struct Batch {
+    project_id: u64,
+    items: Vec<BatchItem>,
+}
+
+///Remembers the batch jobs that are processed in the background and the outcome of their items. Batch jobs are only known to the
+///instance they were submitted to.
+pub struct BatchRegistry {
+    batches: Mutex<TtlMap<String, Batch>>,
+}
+
+impl BatchRegistry {
+    pub fn new() -> BatchRegistry {
+        return BatchRegistry { batches: Mutex::new(TtlMap::new(MAX_BATCHES)) };
+    }
+
+    ///Remembers a new batch job of a project
+    ///
+    /// # Returns
+    /// String - The ID of the batch job
+    pub fn submit(&self, project_id: u64, items: Vec<BatchItem>) -> String {
+        let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        self.batches.lock().unwrap().insert(id.to_string(), Batch { project_id, items }, BATCH_RETENTION);
+
+        return id;
+    }
+
+    ///Stores the outcome of an item of a batch job
+    pub fn complete_item(&self, id: &str, item: BatchItem) {
+        if let Some(batch) = self.batches.lock().unwrap().get_mut(id) {
+            let index = item.index;
+
+            if let Some(slot) = batch.items.get_mut(index) {
+                *slot = item;
+            }
+        }
+    }
+
+    ///Returns the status of a batch job, if the project submitted one with the ID that is still remembered
+    pub fn get_status(&self, id: &str, project_id: u64) -> Option<BatchStatus> {
+        let batches = self.batches.lock().unwrap();
+        let batch = batches.get(id).filter(|batch| batch.project_id == project_id)?;
+
+        return Some(BatchStatus::new(Some(id.to_string()), batch.items.clone()));
+    }
+}
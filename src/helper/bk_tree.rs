@@ -0,0 +1,209 @@
+use super::perceptual_hash::ImageHash;
+
+///Node of a BK-tree. Each child is stored together with its distance to the node, and every hash below a child has that
+///distance to the node as well.
+struct Node {
+    hash: ImageHash,
+    children: Vec<(u32, usize)>,
+}
+
+///BK-tree over perceptual hashes, which finds the hashes close to a hash without comparing it to every hash in the tree. As the
+///Hamming distance is a metric, a subtree whose distance to a node differs by more than the searched distance from the distance
+///of the searched hash to the node can't hold a match and is skipped.
+#[derive(Default)]
+pub struct BkTree {
+    nodes: Vec<Node>,
+}
+
+impl BkTree {
+    ///Adds a hash to the tree, unless it is already part of it
+    pub fn insert(&mut self, hash: ImageHash) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node { hash, children: Vec::new() });
+            return;
+        }
+
+        let mut current = 0;
+
+        loop {
+            let distance = self.nodes[current].hash.distance(&hash);
+
+            if distance == 0 {
+                return;
+            }
+
+            match self.nodes[current].children.iter().find(|(child_distance, _)| *child_distance == distance) {
+                Some((_, child)) => current = *child,
+                None => {
+                    self.nodes.push(Node { hash, children: Vec::new() });
+                    let index = self.nodes.len() - 1;
+                    self.nodes[current].children.push((distance, index));
+                    return;
+                }
+            }
+        }
+    }
+
+    ///Returns true if the hash is part of the tree
+    pub fn contains(&self, hash: &ImageHash) -> bool {
+        let mut current = match self.nodes.is_empty() {
+            true => return false,
+            false => 0,
+        };
+
+        loop {
+            let distance = self.nodes[current].hash.distance(hash);
+
+            if distance == 0 {
+                return true;
+            }
+
+            match self.nodes[current].children.iter().find(|(child_distance, _)| *child_distance == distance) {
+                Some((_, child)) => current = *child,
+                None => return false,
+            }
+        }
+    }
+
+    ///Returns the hash closest to the given one within the maximum distance, other than the hash itself
+    pub fn find_closest(&self, hash: &ImageHash, max_distance: u32) -> Option<(ImageHash, u32)> {
+        let mut closest: Option<(ImageHash, u32)> = None;
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+
+        while let Some(current) = pending.pop() {
+            let node = &self.nodes[current];
+            let distance = node.hash.distance(hash);
+
+            if distance > 0 && closest.map_or(distance <= max_distance, |(_, closest_distance)| distance < closest_distance) {
+                closest = Some((node.hash, distance));
+            }
+
+            let limit = closest.map_or(max_distance, |(_, closest_distance)| closest_distance);
+            pending.extend(node.children.iter()
+                .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= limit)
+                .map(|(_, child)| *child));
+        }
+
+        return closest;
+    }
+
+    ///Returns every hash within the maximum distance of the given one, including the hash itself, with its distance
+    pub fn find_within(&self, hash: &ImageHash, max_distance: u32) -> Vec<(ImageHash, u32)> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+
+        while let Some(current) = pending.pop() {
+            let node = &self.nodes[current];
+            let distance = node.hash.distance(hash);
+
+            if distance <= max_distance {
+                found.push((node.hash, distance));
+            }
+
+            pending.extend(node.children.iter()
+                .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= max_distance)
+                .map(|(_, child)| *child));
+        }
+
+        return found;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BkTree;
+    use crate::helper::perceptual_hash::ImageHash;
+
+    ///Hashes spread over the whole space and clusters of hashes that differ in few bits, so searches have to skip subtrees
+    fn test_hashes() -> Vec<ImageHash> {
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut hashes = Vec::new();
+
+        for _ in 0..200 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let base = state;
+            hashes.push(ImageHash(base));
+
+            for bit in [3, 17, 40] {
+                hashes.push(ImageHash(base ^ (1 << bit)));
+                hashes.push(ImageHash(base ^ (1 << bit) ^ (1 << (bit + 5))));
+            }
+        }
+
+        return hashes;
+    }
+
+    fn build(hashes: &[ImageHash]) -> BkTree {
+        let mut tree = BkTree::default();
+
+        for hash in hashes {
+            tree.insert(*hash);
+        }
+
+        return tree;
+    }
+
+    #[test]
+    fn contains_finds_inserted_hashes_only() {
+        let hashes = test_hashes();
+        let tree = build(&hashes);
+
+        assert!(hashes.iter().all(|hash| tree.contains(hash)));
+        assert!(!tree.contains(&ImageHash(hashes[0].0 ^ 1)));
+        assert!(!BkTree::default().contains(&hashes[0]));
+    }
+
+    #[test]
+    fn find_within_matches_a_linear_search() {
+        let hashes = test_hashes();
+        let tree = build(&hashes);
+
+        for max_distance in [0, 1, 2, 4, 8] {
+            for query in hashes.iter().step_by(7).map(|hash| ImageHash(hash.0 ^ 0b100)) {
+                let mut found = tree.find_within(&query, max_distance);
+                let mut expected: Vec<_> = hashes.iter()
+                    .map(|hash| (*hash, hash.distance(&query)))
+                    .filter(|(_, distance)| *distance <= max_distance)
+                    .collect();
+
+                found.sort_by_key(|(hash, _)| hash.0);
+                expected.sort_by_key(|(hash, _)| hash.0);
+                expected.dedup();
+                assert_eq!(found, expected, "query {} within {}", query, max_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn find_closest_matches_a_linear_search() {
+        let hashes = test_hashes();
+        let tree = build(&hashes);
+
+        for max_distance in [1, 2, 4, 8] {
+            for query in hashes.iter().step_by(5).copied().chain(hashes.iter().step_by(11).map(|hash| ImageHash(hash.0 ^ (1 << 60)))) {
+                let closest = tree.find_closest(&query, max_distance).map(|(_, distance)| distance);
+                let expected = hashes.iter()
+                    .map(|hash| hash.distance(&query))
+                    .filter(|distance| *distance > 0 && *distance <= max_distance)
+                    .min();
+
+                //Several hashes may be equally close, so only the distance is compared
+                assert_eq!(closest, expected, "query {} within {}", query, max_distance);
+
+                if let Some((hash, distance)) = tree.find_closest(&query, max_distance) {
+                    assert_eq!(hash.distance(&query), distance);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_closest_skips_the_hash_itself() {
+        let base = ImageHash(0xf0f0f0f0f0f0f0f0);
+        let tree = build(&[base, ImageHash(base.0 ^ 0b11)]);
+
+        assert_eq!(tree.find_closest(&base, 4), Some((ImageHash(base.0 ^ 0b11), 2)));
+        assert_eq!(tree.find_closest(&base, 1), None);
+        assert_eq!(BkTree::default().find_closest(&base, 64), None);
+    }
+}
@@ -1,10 +1,6 @@
-use std::time::Duration;
 use actix_web::{web::{Bytes}};
-use tokio::time::sleep;
-
-use crate::JWT_TOKEN;
-
-use super::web_helper::get_pam_db_url;
+use crate::config::Config;
+use super::token_manager::TokenManager;
 
 ///Checks if we can connect to our Database API
 /// # Returns
@@ -16,23 +12,22 @@ use super::web_helper::get_pam_db_url;
 /// 
 /// let can_connect = check_database_connection();
 /// ```
-pub(crate) async fn check_db_connection() -> bool{
+pub(crate) async fn check_db_connection(config: &Config) -> bool{
     let client = reqwest::Client::new();
     let response = client
-            .get(format!("{}{}", get_pam_db_url(), "/db/v1/scan/CanConnect"))
+            .get(format!("{}{}", config.db_api.url, "/db/v1/scan/CanConnect"))
             .send()
             .await;
 
-    if response.is_ok(){ 
-        response.as_ref().unwrap().status().is_success()
-    }
-    else {
-        false 
+    match response {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false
     }
 }
 
 ///Gets a scan from via our Database API
 /// # Arguments
+/// * `tokens` - The token manager used to authenticate with our Database API
 /// * `hash` - The hash of the scan to get
 /// 
 /// # Returns
@@ -43,50 +38,29 @@ pub(crate) async fn check_db_connection() -> bool{
 /// ```
 /// use pamaxie_api::database_helper::get_scan;
 /// 
-/// let scan = get_scan("hash");
+/// let scan = get_scan(&tokens, "hash");
 /// ```
-pub(crate) async fn get_scan(hash: &String) -> Option<String>{
-    //Take 100 attempts to get the lock. might fail multiple times since this method is polled quite a lot.
-    let x = std::ops::Range {start: 0, end: 100};
-
-    for _i in x{
-        let mut lock = JWT_TOKEN.try_lock();
-
-
-        if let Ok(ref mut mutex) = lock {
-            let client = reqwest::Client::new();
-            let token = mutex.as_str();
-            let response = client
-                    .get(format!("{}{}", get_pam_db_url(), format!("/db/v1/scan/get={}", hash)))
-                    .header("Authorization", format!("Bearer {}", token))
-                    .send()
-                    .await;
-            
-            std::mem::drop(lock);
-    
-            if response.is_err() {
-                eprintln!("Could not authenticate with JWT bearer token. This should normally not happen.");
-                return None;
-            }
-    
-            //Item could probably not be found.
-            if !response.as_ref().unwrap().status().is_success(){
-                return None;
-            }
-    
-            let response_body = response.unwrap().text().await;
-            return Some(response_body.unwrap());
-        } else {
-            sleep(Duration::from_millis(30)).await;
-            continue;
-        };
+pub(crate) async fn get_scan(tokens: &TokenManager, hash: &str) -> Option<String>{
+    let url = tokens.url(&format!("/db/v1/scan/get={}", hash));
+    let response = tokens.send(|client| client.get(&url)).await;
+
+    if let Err(err) = response {
+        eprintln!("Could not get a scan from our Database API. {}", err);
+        return None;
     }
 
-    return None;
+    //Item could probably not be found.
+    let response = response.unwrap();
+    if !response.status().is_success(){
+        return None;
+    }
+
+    return response.text().await.ok();
 }
 
 ///Removes a scan from our Database API
 /// # Arguments
+/// * `tokens` - The token manager used to authenticate with our Database API
 /// * `hash` - The hash of the scan to remove
 /// 
 /// # Returns
@@ -96,50 +70,29 @@ pub(crate) async fn get_scan(hash: &String) -> Option<String>{
 /// ```
 /// use pamaxie_api::database_helper::remove_scan;
 /// 
-/// let removal_result = remove_scan("hash");
+/// let removal_result = remove_scan(&tokens, "hash");
 /// ```
-pub(crate) async fn remove_scan(hash: &String) -> Result<(), ()>{
-    //Take 100 attempts to get the lock. might fail multiple times since this method is polled quite a lot.
-    let x = std::ops::Range {start: 0, end: 100};
-
-    for _i in x{
-        let mut lock = JWT_TOKEN.try_lock();
-
-
-        if let Ok(ref mut mutex) = lock {
-            let client = reqwest::Client::new();
-            let token = mutex.as_str();
-            let response = client
-                    .delete(format!("{}{}", get_pam_db_url(), format!("/db/v1/scan/delete={}", hash)))
-                    .header("Authorization", format!("Bearer {}", token))
-                    .send()
-                    .await;
-            
-            std::mem::drop(lock);
-    
-            if response.is_err() {
-                eprintln!("Could not authenticate with JWT bearer token. This should normally not happen.");
-                return Err(());
-            }
-    
-            //Item could probably not be found.
-            if !response.as_ref().unwrap().status().is_success(){
-                return Err(());
-            }
-
-            return Ok(())
-        } else {
-            sleep(Duration::from_millis(30)).await;
-            continue;
-        };
+pub(crate) async fn remove_scan(tokens: &TokenManager, hash: &str) -> Result<(), ()>{
+    let url = tokens.url(&format!("/db/v1/scan/delete={}", hash));
+    let response = tokens.send(|client| client.delete(&url)).await;
+
+    if let Err(err) = response {
+        eprintln!("Could not remove a scan from our Database API. {}", err);
+        return Err(());
     }
 
-    return Err(());
+    //Item could probably not be found.
+    if !response.unwrap().status().is_success(){
+        return Err(());
+    }
+
+    return Ok(());
 }
 
 ///Sets the scan result and data in the database
 /// # Arguments
-/// * `hash` - The hash of the scan to get
+/// * `tokens` - The token manager used to authenticate with our Database API
+/// * `scan_data` - The scan result as a JSON string
 /// 
 /// # Returns
 /// * `bool` - True if the operation was successful
@@ -152,95 +105,54 @@ pub(crate) async fn remove_scan(hash: &String) -> Result<(), ()>{
 ///  result: "result".to_string(),
 ///  data: "data".to_string()
 /// };
-/// let scan = set_scan(scan_data);
+/// let scan = set_scan(&tokens, scan_data);
 /// ```
-pub(crate) async fn set_scan(scan_data: &String) -> bool{
-    //Take 100 attempts to set the lock.
-    let range = std::ops::Range {start: 0, end: 100};
-
-    for _n in range {
-        let mut lock = JWT_TOKEN.try_lock();
-
-        if let Ok(ref mut mutex) = lock{
-            let client = reqwest::Client::new();
-            let token = mutex.as_str();
-    
-            let response = client
-                    .post(format!("{}{}", get_pam_db_url(), "/db/v1/scan/update"))
-                    .header("Authorization", format!("Bearer {}", token))
-                    .body(scan_data.to_string())
-                    .send()
-                    .await;
-            
-            std::mem::drop(lock);
-    
-            if response.is_err() {
-                eprintln!("Could not communicate with API, this could be because of several issues, like an invalid Auth token.");
-                
-                return false;
-            }
-
-            if response.as_ref().unwrap().status() == 401{
-                eprintln!("We could not connect and authenticate with the API via the provided Auth Token");
-                return false;
-            }
-    
-            //No errors found, so the data was stored successfully
-            if response.as_ref().unwrap().status().is_success(){
-                return true;
-            }
-
-            return false;
-        }else{
-            sleep(Duration::from_millis(30)).await;
-            continue;
-        }
+pub(crate) async fn set_scan(tokens: &TokenManager, scan_data: &str) -> bool{
+    let url = tokens.url("/db/v1/scan/update");
+    let response = tokens.send(|client| client.post(&url).body(scan_data.to_string())).await;
+
+    if let Err(err) = response {
+        eprintln!("Could not communicate with API, this could be because of several issues, like an invalid Auth token. {}", err);
+        return false;
     }
-    
-    return false;
+
+    let status = response.unwrap().status();
+
+    if status == 401{
+        eprintln!("We could not connect and authenticate with the API via the provided Auth Token");
+        return false;
+    }
+
+    //No errors found, so the data was stored successfully
+    return status.is_success();
 }
 
-pub(crate) async fn get_image_hash(image_data: &Bytes) -> Option<String>{
-    //Take 100 attempts to set the lock.
-    let range = std::ops::Range {start: 0, end: 100};
-
-    for _n in range {
-        let mut lock = JWT_TOKEN.try_lock();
-
-        if let Ok(ref mut mutex) = lock{
-            let client = reqwest::Client::new();
-            let token = mutex.as_str();
-            let body_data = image_data.to_vec();
-    
-            let response = client
-                    .post(format!("{}{}", get_pam_db_url(), "/db/v1/scan/GetImageHash"))
-                    .header("Authorization", format!("Bearer {}", token))
-                    .body(body_data)
-                    .send()
-                    .await;
-            std::mem::drop(lock);
-    
-            if response.is_err() {
-                eprintln!("Could not communicate with API, this could be because of several issues, like an invalid Auth token.");
-                return None;
-            }
-
-            if response.as_ref().unwrap().status() == 401{
-                eprintln!("We could not connect and authenticate with the API via the provided Auth Token");
-                return None;
-            }
-
-            if !response.as_ref().unwrap().status().is_success() {
-                return None;
-            }
-    
-            let body_text = response.unwrap().text().await;
-            return Some(body_text.unwrap());
-        }else{
-            sleep(Duration::from_millis(30)).await;
-            continue;
-        }
+///Gets the hash of an image from our Database API
+/// # Arguments
+/// * `tokens` - The token manager used to authenticate with our Database API
+/// * `image_data` - The image to hash
+/// 
+/// # Returns
+/// * `String` - The hash of the image
+pub(crate) async fn get_image_hash(tokens: &TokenManager, image_data: &Bytes) -> Option<String>{
+    let url = tokens.url("/db/v1/scan/GetImageHash");
+    let response = tokens.send(|client| client.post(&url).body(image_data.clone())).await;
+
+    if let Err(err) = response {
+        eprintln!("Could not communicate with API, this could be because of several issues, like an invalid Auth token. {}", err);
+        return None;
+    }
+
+    let response = response.unwrap();
+
+    if response.status() == 401{
+        eprintln!("We could not connect and authenticate with the API via the provided Auth Token");
+        return None;
+    }
+
+    if !response.status().is_success() {
+        return None;
     }
-    
-    return None;
-}
\ No newline at end of file
+
+    return response.text().await.ok();
+}
@@ -0,0 +1,183 @@
+use actix_multipart::Multipart;
+use actix_web::{HttpMessage, HttpRequest, web};
+use actix_web::web::Bytes;
+use futures::TryStreamExt;
+use serde::Deserialize;
+use crate::config::UploadConfig;
+use super::scan_error::ScanError;
+use super::upload::{self, Sniff, Upload};
+
+///Largest text field of a multipart body, like the url or the callback_url
+const MAX_TEXT_FIELD_BYTES: usize = 64 * 1024;
+
+///Data a client wants to have scanned
+pub enum DetectionInput {
+    ///The data itself
+    Data(Upload),
+    ///The URL the data has to be downloaded from
+    Url(String),
+}
+
+///Options a client can pass with a detection request, in the query or together with the data in the body
+#[derive(Deserialize, Default)]
+pub struct RequestOptions {
+    ///Seconds to wait for the result before responding with the job to poll
+    pub wait: Option<u64>,
+    ///URL the result is posted to once our workers scanned the data
+    pub callback_url: Option<String>,
+}
+
+impl RequestOptions {
+    ///Returns the options, with the ones that are set in the other options replacing ours
+    pub fn merge(self, other: RequestOptions) -> RequestOptions {
+        return RequestOptions { wait: other.wait.or(self.wait), callback_url: other.callback_url.or(self.callback_url) };
+    }
+}
+
+///Body of a detection request
+pub struct DetectionBody {
+    pub input: DetectionInput,
+    pub options: RequestOptions,
+    ///Bytes of the body that have been read
+    pub size: usize,
+}
+
+///What a raw body that is neither multipart/form-data nor JSON holds
+#[derive(Clone, Copy)]
+pub enum RawBody {
+    Data,
+    Url,
+}
+
+///A JSON detection request. Either the base64 encoded data or the URL of the data has to be set.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonBody {
+    data: Option<String>,
+    url: Option<String>,
+    wait: Option<u64>,
+    callback_url: Option<String>,
+}
+
+///Reads the body of a detection request according to its Content-Type:
+/// - multipart/form-data: a file field with the data or a `url` field, plus optional `wait` and `callback_url` fields
+/// - application/json: `{"data": "<base64>"}` or `{"url": "..."}`, plus optional `wait` and `callback_url` keys
+/// - anything else: the raw data, or the bare URL of the data, depending on the endpoint
+///
+///The body is streamed: the data is checked with the sniff of the endpoint as soon as its start arrived, reading stops once
+///the body gets larger than the endpoint accepts and large data is spilled to a temporary file. JSON bodies are held in memory
+///instead, and limited to `uploads.json_max_bytes`.
+///
+/// # Arguments
+/// req: &HttpRequest - The request, whose Content-Type selects the format of the body
+/// payload: web::Payload - The body of the request
+/// config: &UploadConfig - The settings of our uploads
+/// max_bytes: usize - The largest body the endpoint accepts
+/// raw: RawBody - What the body holds if it is neither multipart/form-data nor JSON
+/// sniff: Sniff - The check of the start of the data, which rejects data of a type the endpoint doesn't accept
+///
+/// # Returns
+/// Result<DetectionBody, ScanError> - The data to scan and the options sent with it
+pub async fn parse(req: &HttpRequest, payload: web::Payload, config: &UploadConfig, max_bytes: usize, raw: RawBody, sniff: Sniff) -> Result<DetectionBody, ScanError> {
+    let content_type = req.content_type().to_ascii_lowercase();
+
+    if content_type == "multipart/form-data" {
+        return parse_multipart(req, payload, config, max_bytes, sniff).await;
+    }
+
+    //JSON bodies have to be held in memory to be parsed, so they are limited more tightly than the streamed ones
+    if content_type == "application/json" {
+        let json_max_bytes = max_bytes.min(config.json_max_bytes);
+        let body = upload::read_bytes(payload, json_max_bytes).await.map_err(|err| match err {
+            ScanError::PayloadTooLarge(_) => ScanError::PayloadTooLarge(format!("JSON bodies may be at most {} bytes large. Larger data can be sent raw or as multipart/form-data.", json_max_bytes)),
+            err => err,
+        })?;
+
+        return parse_json(&body, sniff);
+    }
+
+    return match raw {
+        RawBody::Data => {
+            let data = upload::read_stream(payload, max_bytes, config, sniff).await?;
+            Ok(DetectionBody { size: data.len(), input: DetectionInput::Data(data), options: RequestOptions::default() })
+        },
+        RawBody::Url => {
+            let body = upload::read_bytes(payload, max_bytes).await?;
+            let url = String::from_utf8(body.to_vec())
+                .map_err(|_| ScanError::InvalidRequest("Please ensure you specify a valid url to detect from".to_string()))?;
+
+            Ok(DetectionBody { size: body.len(), input: DetectionInput::Url(url), options: RequestOptions::default() })
+        },
+    };
+}
+
+fn parse_json(body: &Bytes, sniff: Sniff) -> Result<DetectionBody, ScanError> {
+    let invalid = || ScanError::InvalidRequest("Please send a JSON object with either the base64 encoded data or the url of an image.".to_string());
+    let json: JsonBody = serde_json::from_slice(body).map_err(|_| invalid())?;
+
+    let input = match (json.data, json.url) {
+        (Some(data), None) => {
+            let data = Bytes::from(base64::decode(&data).map_err(|_| ScanError::InvalidRequest("Please ensure the data is base64 encoded.".to_string()))?);
+
+            if !data.is_empty() {
+                sniff(&data[..data.len().min(upload::SNIFF_BYTES)])?;
+            }
+
+            DetectionInput::Data(Upload::from(data))
+        },
+        (None, Some(url)) => DetectionInput::Url(url),
+        _ => return Err(invalid()),
+    };
+
+    return Ok(DetectionBody { input, options: RequestOptions { wait: json.wait, callback_url: json.callback_url }, size: body.len() });
+}
+
+///Sets the data of a multipart request, which may only be sent once
+fn set_input(input: &mut Option<DetectionInput>, value: DetectionInput) -> Result<(), ScanError> {
+    if input.replace(value).is_some() {
+        return Err(ScanError::InvalidRequest("Please send a single image. Many images can be scanned with our batch endpoint.".to_string()));
+    }
+
+    return Ok(());
+}
+
+async fn parse_multipart(req: &HttpRequest, payload: web::Payload, config: &UploadConfig, max_bytes: usize, sniff: Sniff) -> Result<DetectionBody, ScanError> {
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut input = None;
+    let mut options = RequestOptions::default();
+    let mut size = 0;
+
+    while let Some(field) = multipart.try_next().await.map_err(|_| ScanError::InvalidRequest("The multipart body is malformed.".to_string()))? {
+        let name = field.content_disposition().get_name().unwrap_or_default().to_string();
+        let is_file = field.content_disposition().get_filename().is_some();
+
+        if is_file || matches!(name.as_str(), "file" | "data" | "image") {
+            let data = upload::read_stream(field, max_bytes, config, sniff).await?;
+            size += data.len();
+            set_input(&mut input, DetectionInput::Data(data))?;
+        } else {
+            if !matches!(name.as_str(), "url" | "wait" | "callback_url") {
+                return Err(ScanError::InvalidRequest(format!("The field {} is not supported.", name)));
+            }
+
+            let data = upload::read_bytes(field, MAX_TEXT_FIELD_BYTES).await?;
+            size += data.len();
+
+            let text = String::from_utf8(data.to_vec()).map_err(|_| ScanError::InvalidRequest(format!("Please ensure the {} field holds valid UTF-8 text.", name)))?;
+
+            match name.as_str() {
+                "url" => set_input(&mut input, DetectionInput::Url(text))?,
+                "wait" => options.wait = Some(text.trim().parse()
+                    .map_err(|_| ScanError::InvalidRequest("Please specify the wait field as a whole number of seconds.".to_string()))?),
+                _ => options.callback_url = Some(text),
+            }
+        }
+
+        if size > max_bytes {
+            return Err(ScanError::PayloadTooLarge(format!("The data may be at most {} bytes large.", max_bytes)));
+        }
+    }
+
+    let input = input.ok_or(ScanError::MissingBody)?;
+    return Ok(DetectionBody { input, options, size });
+}
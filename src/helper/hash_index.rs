@@ -0,0 +1,94 @@
+use std::{sync::{Arc, RwLock}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use actix_web::web;
+use tokio::{sync::Mutex, time::sleep};
+use crate::config::{HashIndexBackend, HashingConfig};
+use super::bk_tree::BkTree;
+use super::hash_index_store::{HashIndexStore, RedisStore, SqliteStore};
+use super::perceptual_hash::ImageHash;
+
+///Index of the perceptual hashes our results are stored under, so the results of images that look alike can be found. The
+///hashes are kept in the configured store and held in a BK-tree. Hashes added by this instance go into the tree right away, and
+///the ones added by other instances once it reads the hashes added to the store since it last did.
+pub struct HashIndex {
+    store: Arc<dyn HashIndexStore>,
+    tree: RwLock<BkTree>,
+    ///Position in the store up to which the hashes have been read, locked while they are read so none is read twice
+    position: Mutex<u64>,
+}
+
+impl HashIndex {
+    ///Opens the configured store and loads every hash it holds
+    ///
+    /// # Returns
+    /// Result<HashIndex, String> - The index or why its store could not be opened
+    pub async fn new(config: &HashingConfig) -> Result<HashIndex, String> {
+        let store: Arc<dyn HashIndexStore> = match config.backend {
+            HashIndexBackend::Sqlite => Arc::new(SqliteStore::new(&config.index_path)?),
+            HashIndexBackend::Redis => Arc::new(RedisStore::new(&config.redis_url).await?),
+        };
+
+        let index = HashIndex { store, tree: RwLock::new(BkTree::default()), position: Mutex::new(0) };
+        index.load_new().await.map_err(|err| format!("Could not load the hash index: {}", err))?;
+
+        return Ok(index);
+    }
+
+    ///Reads the hashes added to the store since they have last been read into the tree
+    async fn load_new(&self) -> Result<(), String> {
+        let mut position = self.position.lock().await;
+        let (hashes, next_position) = self.store.load_since(*position).await?;
+
+        if !hashes.is_empty() {
+            let mut tree = self.tree.write().unwrap();
+
+            //Hashes of other versions of our algorithm can't be compared to ours, so they are skipped
+            for hash in hashes {
+                if let Ok(hash) = hash.parse() {
+                    tree.insert(hash);
+                }
+            }
+        }
+
+        *position = next_position;
+        return Ok(());
+    }
+
+    ///Adds the hash of a result to the index, unless it is already part of it. Failures are only logged, as the result is still
+    ///found by its exact hash.
+    pub async fn add(&self, hash: ImageHash) {
+        if self.tree.read().unwrap().contains(&hash) {
+            return;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+        match self.store.add(hash, now).await {
+            //Another instance may have added it before we read it, so it goes into our tree either way
+            Ok(_) => self.tree.write().unwrap().insert(hash),
+            Err(err) => eprintln!("Could not add {} to the hash index: {}", hash, err),
+        }
+    }
+
+    ///Finds the indexed hash that is closest to the given one
+    ///
+    /// # Arguments
+    /// hash: &ImageHash - The hash to find a near duplicate of
+    /// max_distance: u32 - The most bits the hashes may differ in
+    ///
+    /// # Returns
+    /// Option<(ImageHash, u32)> - The closest hash other than the given one and its distance, if there is one within the distance
+    pub fn find_closest(&self, hash: &ImageHash, max_distance: u32) -> Option<(ImageHash, u32)> {
+        return self.tree.read().unwrap().find_closest(hash, max_distance);
+    }
+}
+
+///Periodically reads the hashes other instances added to the index
+pub async fn watch_changes(index: web::Data<HashIndex>, interval: Duration) {
+    loop {
+        sleep(interval).await;
+
+        if let Err(err) = index.load_new().await {
+            eprintln!("Could not read the new hashes of the hash index. {}", err);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use futures::future::BoxFuture;
+use redis::{aio::ConnectionManager, Script};
+use rusqlite::params;
+use super::perceptual_hash::ImageHash;
+use super::sqlite::{self, Database};
+
+///Keeps the hashes of our hash index in the order they have been added, so the instances that hold the index in memory only
+///read the hashes added since they last read them. Implementations have to be safe to use from every worker at once.
+pub trait HashIndexStore: Send + Sync {
+    ///Adds a hash, unless it has been added before
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the hash has been added
+    fn add<'a>(&'a self, hash: ImageHash, created_at: i64) -> BoxFuture<'a, Result<bool, String>>;
+
+    ///Returns the hashes added after the given position, oldest first
+    ///
+    /// # Arguments
+    /// position: u64 - The position returned by the previous call, or 0 to read every hash
+    ///
+    /// # Returns
+    /// Result<(Vec<String>, u64), String> - The hashes and the position to read the hashes added after them from
+    fn load_since<'a>(&'a self, position: u64) -> BoxFuture<'a, Result<(Vec<String>, u64), String>>;
+}
+
+///Keeps the hashes in a SQLite database of this instance. Their row ID is their position.
+pub struct SqliteStore {
+    database: Database,
+}
+
+impl SqliteStore {
+    ///Opens the database at the given path and creates its table, if it doesn't exist yet
+    pub fn new(path: &Path) -> Result<SqliteStore, String> {
+        let connection = sqlite::open(path, "hash index", "
+            CREATE TABLE IF NOT EXISTS hashes (
+                hash TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL
+            );
+        ")?;
+
+        return Ok(SqliteStore { database: Database::new(connection) });
+    }
+}
+
+impl HashIndexStore for SqliteStore {
+    fn add<'a>(&'a self, hash: ImageHash, created_at: i64) -> BoxFuture<'a, Result<bool, String>> {
+        return Box::pin(async move {
+            let inserted = self.database.run(move |connection| connection
+                .execute("INSERT OR IGNORE INTO hashes (hash, created_at) VALUES (?1, ?2)", params![hash.to_string(), created_at])).await?;
+
+            return Ok(inserted > 0);
+        });
+    }
+
+    fn load_since<'a>(&'a self, position: u64) -> BoxFuture<'a, Result<(Vec<String>, u64), String>> {
+        return Box::pin(async move {
+            let rows = self.database.run(move |connection| {
+                let mut statement = connection.prepare("SELECT rowid, hash FROM hashes WHERE rowid > ?1 ORDER BY rowid")?;
+                let rows = statement.query_map(params![position as i64], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()
+            }).await?;
+
+            let position = rows.last().map(|(rowid, _)| *rowid as u64).unwrap_or(position);
+            return Ok((rows.into_iter().map(|(_, hash)| hash).collect(), position));
+        });
+    }
+}
+
+///Key of the set of every hash, which keeps a hash from being added twice
+const REDIS_HASHES_KEY: &str = "pamaxie:hash_index:hashes";
+///Key of the list of every hash in the order they have been added. The index of a hash in it is its position.
+const REDIS_LOG_KEY: &str = "pamaxie:hash_index:log";
+
+///Keeps the hashes in Redis, so every instance of the API finds the near duplicates of the results stored by any of them
+pub struct RedisStore {
+    connection: ConnectionManager,
+    add_script: Script,
+}
+
+impl RedisStore {
+    ///Connects to the Redis server at the given URL
+    pub async fn new(url: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|err| format!("{} is not a valid Redis URL: {}", url, err))?;
+        let connection = ConnectionManager::new(client).await.map_err(|err| format!("Could not connect to Redis at {}: {}", url, err))?;
+
+        //Appends the hash to the list only if it is new to the set, so no hash is listed twice
+        let add_script = Script::new(r"
+            if redis.call('SADD', KEYS[1], ARGV[1]) == 0 then
+                return 0
+            end
+            redis.call('RPUSH', KEYS[2], ARGV[1])
+            return 1
+        ");
+
+        return Ok(RedisStore { connection, add_script });
+    }
+}
+
+impl HashIndexStore for RedisStore {
+    fn add<'a>(&'a self, hash: ImageHash, _created_at: i64) -> BoxFuture<'a, Result<bool, String>> {
+        return Box::pin(async move {
+            let added: u32 = self.add_script.key(REDIS_HASHES_KEY).key(REDIS_LOG_KEY).arg(hash.to_string())
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(added > 0);
+        });
+    }
+
+    fn load_since<'a>(&'a self, position: u64) -> BoxFuture<'a, Result<(Vec<String>, u64), String>> {
+        return Box::pin(async move {
+            let hashes: Vec<String> = redis::cmd("LRANGE").arg(REDIS_LOG_KEY).arg(position).arg(-1)
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            let position = position + hashes.len() as u64;
+            return Ok((hashes, position));
+        });
+    }
+}
@@ -0,0 +1,288 @@
+use std::{collections::HashMap, path::Path};
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use super::hash_lists::{ListEntry, ListKind, ListScope};
+use super::perceptual_hash::ImageHash;
+use super::sqlite::{self, Database};
+
+///A hash on a list, as it is loaded to match images against it
+pub struct StoredEntry {
+    pub scope: ListScope,
+    pub list: ListKind,
+    pub hash: ImageHash,
+    pub max_distance: u32,
+    pub label: Option<String>,
+}
+
+///Keeps our hash lists. Every change bumps the version of the lists, which tells the instances that hold them in memory to
+///reload them. Implementations have to be safe to use from every worker at once.
+pub trait HashListStore: Send + Sync {
+    ///Adds hashes to a list, all of them or none. Hashes that are already on the list keep when they were added, but get the
+    ///distance and label they are added with.
+    fn add<'a>(&'a self, scope: ListScope, list: ListKind, entries: Vec<(ImageHash, u32, Option<String>)>, created_at: i64) -> BoxFuture<'a, Result<(), String>>;
+
+    ///Removes a hash from a list
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the hash has been on the list
+    fn remove<'a>(&'a self, scope: ListScope, list: ListKind, hash: ImageHash) -> BoxFuture<'a, Result<bool, String>>;
+
+    ///Returns the hashes on a list, newest first
+    fn list<'a>(&'a self, scope: ListScope, list: ListKind, limit: u32, offset: u32) -> BoxFuture<'a, Result<Vec<ListEntry>, String>>;
+
+    ///Returns every hash on every list
+    fn load<'a>(&'a self) -> BoxFuture<'a, Result<Vec<StoredEntry>, String>>;
+
+    ///Returns the version of the lists, which changes whenever one of them changes
+    fn version<'a>(&'a self) -> BoxFuture<'a, Result<u64, String>>;
+}
+
+fn read_entry(row: &Row) -> rusqlite::Result<ListEntry> {
+    return Ok(ListEntry {
+        hash: row.get(0)?,
+        max_distance: row.get(1)?,
+        label: row.get(2)?,
+        created_at: row.get(3)?,
+    });
+}
+
+///Keeps the lists in a SQLite database of this instance
+pub struct SqliteStore {
+    database: Database,
+}
+
+impl SqliteStore {
+    ///Opens the database at the given path and creates its tables, if they don't exist yet
+    pub fn new(path: &Path) -> Result<SqliteStore, String> {
+        let connection = sqlite::open(path, "hash list database", "
+            CREATE TABLE IF NOT EXISTS entries (
+                scope TEXT NOT NULL,
+                list TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                max_distance INTEGER NOT NULL,
+                label TEXT,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (scope, list, hash)
+            );
+            CREATE TABLE IF NOT EXISTS version (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                version INTEGER NOT NULL
+            );
+            INSERT OR IGNORE INTO version (id, version) VALUES (0, 0);
+        ")?;
+
+        return Ok(SqliteStore { database: Database::new(connection) });
+    }
+}
+
+impl HashListStore for SqliteStore {
+    fn add<'a>(&'a self, scope: ListScope, list: ListKind, entries: Vec<(ImageHash, u32, Option<String>)>, created_at: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(self.database.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            for (hash, max_distance, label) in &entries {
+                transaction.execute(
+                    "INSERT INTO entries (scope, list, hash, max_distance, label, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                        ON CONFLICT (scope, list, hash) DO UPDATE SET max_distance = excluded.max_distance, label = excluded.label",
+                    params![scope.key(), list.as_str(), hash.to_string(), max_distance, label, created_at])?;
+            }
+
+            transaction.execute("UPDATE version SET version = version + 1", [])?;
+            transaction.commit()
+        }));
+    }
+
+    fn remove<'a>(&'a self, scope: ListScope, list: ListKind, hash: ImageHash) -> BoxFuture<'a, Result<bool, String>> {
+        return Box::pin(self.database.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let removed = transaction.execute("DELETE FROM entries WHERE scope = ?1 AND list = ?2 AND hash = ?3", params![scope.key(), list.as_str(), hash.to_string()])?;
+
+            if removed > 0 {
+                transaction.execute("UPDATE version SET version = version + 1", [])?;
+            }
+
+            transaction.commit()?;
+            Ok(removed > 0)
+        }));
+    }
+
+    fn list<'a>(&'a self, scope: ListScope, list: ListKind, limit: u32, offset: u32) -> BoxFuture<'a, Result<Vec<ListEntry>, String>> {
+        return Box::pin(self.database.run(move |connection| {
+            let mut statement = connection.prepare("SELECT hash, max_distance, label, created_at FROM entries WHERE scope = ?1 AND list = ?2 ORDER BY created_at DESC, hash LIMIT ?3 OFFSET ?4")?;
+            let rows = statement.query_map(params![scope.key(), list.as_str(), limit, offset], read_entry)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        }));
+    }
+
+    fn load<'a>(&'a self) -> BoxFuture<'a, Result<Vec<StoredEntry>, String>> {
+        return Box::pin(self.database.run(|connection| {
+            let mut statement = connection.prepare("SELECT scope, list, hash, max_distance, label FROM entries")?;
+            let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?, row.get::<_, Option<String>>(4)?)))?;
+
+            //Hashes of other versions of our algorithm can't be compared to ours, so they are skipped
+            return Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?.into_iter()
+                .filter_map(|(scope, list, hash, max_distance, label)| Some(StoredEntry {
+                    scope: ListScope::from_key(&scope)?,
+                    list: list.parse().ok()?,
+                    hash: hash.parse().ok()?,
+                    max_distance,
+                    label,
+                }))
+                .collect());
+        }));
+    }
+
+    fn version<'a>(&'a self) -> BoxFuture<'a, Result<u64, String>> {
+        return Box::pin(self.database.run(|connection| connection.query_row("SELECT version FROM version", [], |row| row.get::<_, i64>(0)).map(|version| version as u64)));
+    }
+}
+
+///The details of a hash on a list, as they are stored in Redis. When the hash was added is its score in the sorted set of the
+///list.
+#[derive(Serialize, Deserialize)]
+struct RedisEntry {
+    max_distance: u32,
+    label: Option<String>,
+}
+
+///Keeps the lists in Redis, so every instance of the API shares them. Every list is a hash that holds the details of its
+///entries, and a sorted set of its entries scored by when they were added, which its pages are read from.
+pub struct RedisStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStore {
+    ///Connects to the Redis server at the given URL
+    pub async fn new(url: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|err| format!("{} is not a valid Redis URL: {}", url, err))?;
+        let connection = ConnectionManager::new(client).await.map_err(|err| format!("Could not connect to Redis at {}: {}", url, err))?;
+
+        return Ok(RedisStore { connection });
+    }
+}
+
+///Key of the set that holds every list that has entries, as scope:list
+const REDIS_LISTS_KEY: &str = "pamaxie:hash_lists:lists";
+///Key of the version of the lists
+const REDIS_VERSION_KEY: &str = "pamaxie:hash_lists:version";
+
+fn redis_list(scope: ListScope, list: ListKind) -> String {
+    return format!("{}:{}", scope.key(), list.as_str());
+}
+
+fn redis_entries_key(list: &str) -> String {
+    return format!("pamaxie:hash_lists:{}:entries", list);
+}
+
+fn redis_created_key(list: &str) -> String {
+    return format!("pamaxie:hash_lists:{}:created", list);
+}
+
+impl HashListStore for RedisStore {
+    fn add<'a>(&'a self, scope: ListScope, list: ListKind, entries: Vec<(ImageHash, u32, Option<String>)>, created_at: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let name = redis_list(scope, list);
+            let mut pipe = redis::pipe();
+            pipe.atomic().sadd(REDIS_LISTS_KEY, &name).ignore();
+
+            for (hash, max_distance, label) in entries {
+                let details = serde_json::to_string(&RedisEntry { max_distance, label }).map_err(|err| err.to_string())?;
+
+                pipe.hset(redis_entries_key(&name), hash.to_string(), details).ignore()
+                    .cmd("ZADD").arg(redis_created_key(&name)).arg("NX").arg(created_at).arg(hash.to_string()).ignore();
+            }
+
+            return pipe.incr(REDIS_VERSION_KEY, 1).ignore()
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string());
+        });
+    }
+
+    fn remove<'a>(&'a self, scope: ListScope, list: ListKind, hash: ImageHash) -> BoxFuture<'a, Result<bool, String>> {
+        return Box::pin(async move {
+            let name = redis_list(scope, list);
+
+            let (removed,): (u32,) = redis::pipe().atomic()
+                .hdel(redis_entries_key(&name), hash.to_string())
+                .zrem(redis_created_key(&name), hash.to_string()).ignore()
+                .incr(REDIS_VERSION_KEY, 1).ignore()
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(removed > 0);
+        });
+    }
+
+    fn list<'a>(&'a self, scope: ListScope, list: ListKind, limit: u32, offset: u32) -> BoxFuture<'a, Result<Vec<ListEntry>, String>> {
+        return Box::pin(async move {
+            if limit == 0 {
+                return Ok(Vec::new());
+            }
+
+            let name = redis_list(scope, list);
+            let mut connection = self.connection.clone();
+
+            let page: Vec<(String, i64)> = redis::cmd("ZREVRANGE").arg(redis_created_key(&name)).arg(offset).arg(offset as u64 + limit as u64 - 1).arg("WITHSCORES")
+                .query_async(&mut connection).await
+                .map_err(|err| err.to_string())?;
+
+            if page.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let details: Vec<Option<String>> = redis::cmd("HMGET").arg(redis_entries_key(&name)).arg(page.iter().map(|(hash, _)| hash).collect::<Vec<_>>())
+                .query_async(&mut connection).await
+                .map_err(|err| err.to_string())?;
+
+            //Entries that have been removed while the page was read are left out
+            return Ok(page.into_iter().zip(details)
+                .filter_map(|((hash, created_at), details)| {
+                    let details: RedisEntry = serde_json::from_str(&details?).ok()?;
+                    Some(ListEntry { hash, max_distance: details.max_distance, label: details.label, created_at })
+                })
+                .collect());
+        });
+    }
+
+    fn load<'a>(&'a self) -> BoxFuture<'a, Result<Vec<StoredEntry>, String>> {
+        return Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let names: Vec<String> = redis::cmd("SMEMBERS").arg(REDIS_LISTS_KEY)
+                .query_async(&mut connection).await
+                .map_err(|err| err.to_string())?;
+
+            let mut entries = Vec::new();
+
+            for name in names {
+                let (scope, list) = match name.split_once(':').and_then(|(scope, list)| Some((ListScope::from_key(scope)?, list.parse().ok()?))) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+
+                let details: HashMap<String, String> = redis::cmd("HGETALL").arg(redis_entries_key(&name))
+                    .query_async(&mut connection).await
+                    .map_err(|err| err.to_string())?;
+
+                //Hashes of other versions of our algorithm can't be compared to ours, so they are skipped
+                entries.extend(details.into_iter().filter_map(|(hash, details)| {
+                    let details: RedisEntry = serde_json::from_str(&details).ok()?;
+                    Some(StoredEntry { scope, list, hash: hash.parse().ok()?, max_distance: details.max_distance, label: details.label })
+                }));
+            }
+
+            return Ok(entries);
+        });
+    }
+
+    fn version<'a>(&'a self) -> BoxFuture<'a, Result<u64, String>> {
+        return Box::pin(async move {
+            let version: Option<u64> = redis::cmd("GET").arg(REDIS_VERSION_KEY)
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(version.unwrap_or(0));
+        });
+    }
+}
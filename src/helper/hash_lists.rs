@@ -0,0 +1,281 @@
+use std::{collections::HashMap, str::FromStr, sync::{Arc, RwLock, atomic::{AtomicU64, Ordering}}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use actix_web::web;
+use serde::Serialize;
+use tokio::time::sleep;
+use crate::config::{HashListBackend, HashListConfig};
+use super::bk_tree::BkTree;
+use super::hash_list_store::{HashListStore, RedisStore, SqliteStore};
+use super::perceptual_hash::ImageHash;
+
+///Kind of a hash list, which decides the verdict images on it get
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ListKind {
+    ///Images on the list are blocked without being scanned
+    Block,
+    ///Images on the list are allowed without being scanned
+    Allow,
+}
+
+impl ListKind {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            ListKind::Block => "block",
+            ListKind::Allow => "allow",
+        };
+    }
+}
+
+impl FromStr for ListKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "block" => Ok(ListKind::Block),
+            "allow" => Ok(ListKind::Allow),
+            _ => Err(()),
+        };
+    }
+}
+
+///Whom a hash list applies to
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ListScope {
+    ///The list applies to every project
+    Global,
+    ///The list applies to a single project
+    Project(u64),
+}
+
+impl ListScope {
+    ///Returns how the scope is stored in our database
+    pub fn key(&self) -> String {
+        return match self {
+            ListScope::Global => "global".to_string(),
+            ListScope::Project(project_id) => project_id.to_string(),
+        };
+    }
+
+    pub fn from_key(key: &str) -> Option<ListScope> {
+        return match key {
+            "global" => Some(ListScope::Global),
+            project_id => project_id.parse().ok().map(ListScope::Project),
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ListScope::Global => "global",
+            ListScope::Project(_) => "project",
+        };
+    }
+}
+
+///A hash on a list, as returned by our list endpoints. Timestamps are unix timestamps in seconds.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListEntry {
+    pub hash: String,
+    pub max_distance: u32,
+    pub label: Option<String>,
+    pub created_at: i64,
+}
+
+///A hash on a list, as it is held in memory to match images against it
+struct Entry {
+    hash: ImageHash,
+    max_distance: u32,
+    label: Option<String>,
+}
+
+///The entries of a list, indexed so images are matched against them without comparing them to every entry
+#[derive(Default)]
+struct ListIndex {
+    ///Every entry of the list by its hash, which finds exact matches
+    entries: HashMap<ImageHash, Entry>,
+    ///The hashes of the entries that match near duplicates as well. Removed hashes stay in the tree until the lists are
+    ///reloaded, and are skipped as they are no longer among the entries.
+    tree: BkTree,
+    ///Largest distance an entry of the list has been added with
+    max_distance: u32,
+}
+
+impl ListIndex {
+    ///Adds an entry to the list, replacing the entry of its hash
+    fn insert(&mut self, entry: Entry) {
+        if entry.max_distance > 0 {
+            self.tree.insert(entry.hash);
+            self.max_distance = self.max_distance.max(entry.max_distance);
+        }
+
+        self.entries.insert(entry.hash, entry);
+    }
+
+    ///Returns the closest entry the hash is within the distance of, and its distance to it
+    fn find_closest(&self, hash: &ImageHash) -> Option<(&Entry, u32)> {
+        if let Some(entry) = self.entries.get(hash) {
+            return Some((entry, 0));
+        }
+
+        if self.max_distance == 0 {
+            return None;
+        }
+
+        return self.tree.find_within(hash, self.max_distance).into_iter()
+            .filter_map(|(candidate, distance)| self.entries.get(&candidate)
+                .filter(|entry| distance <= entry.max_distance)
+                .map(|entry| (entry, distance)))
+            .min_by_key(|(_, distance)| *distance);
+    }
+}
+
+///The entry of a list an image matched
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListMatch {
+    pub list: ListKind,
+    ///global or project
+    pub scope: &'static str,
+    ///Hash of the entry, which differs from the hash of the image if the entry allows near matches
+    pub hash: String,
+    pub distance: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+///Lists of hashes whose images are blocked or allowed without being scanned, for every project and for all of them. The lists
+///are kept in the configured store and held in memory, so every image can be matched against them. Changes are applied to the
+///lists in memory right away, and the other instances reload them once they notice the version of the lists changed.
+pub struct HashLists {
+    store: Arc<dyn HashListStore>,
+    lists: RwLock<HashMap<(ListScope, ListKind), ListIndex>>,
+    ///Version of the lists that are held in memory
+    version: AtomicU64,
+}
+
+impl HashLists {
+    ///Opens the configured store and loads every list it holds
+    ///
+    /// # Returns
+    /// Result<HashLists, String> - The lists or why their store could not be opened
+    pub async fn new(config: &HashListConfig) -> Result<HashLists, String> {
+        let store: Arc<dyn HashListStore> = match config.backend {
+            HashListBackend::Sqlite => Arc::new(SqliteStore::new(&config.database_path)?),
+            HashListBackend::Redis => Arc::new(RedisStore::new(&config.redis_url).await?),
+        };
+
+        let lists = HashLists { store, lists: RwLock::new(HashMap::new()), version: AtomicU64::new(0) };
+        lists.reload().await.map_err(|err| format!("Could not load the hash lists: {}", err))?;
+
+        return Ok(lists);
+    }
+
+    ///Loads every list from the store, replacing the lists held in memory
+    async fn reload(&self) -> Result<(), String> {
+        //Read before the lists, so changes made while they are read are picked up by the next reload
+        let version = self.store.version().await?;
+        let mut lists: HashMap<(ListScope, ListKind), ListIndex> = HashMap::new();
+
+        for entry in self.store.load().await? {
+            lists.entry((entry.scope, entry.list)).or_default().insert(Entry { hash: entry.hash, max_distance: entry.max_distance, label: entry.label });
+        }
+
+        *self.lists.write().unwrap() = lists;
+        self.version.store(version, Ordering::SeqCst);
+
+        return Ok(());
+    }
+
+    ///Reloads the lists if they changed since they have been loaded
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the lists have been reloaded
+    async fn reload_if_changed(&self) -> Result<bool, String> {
+        if self.store.version().await? == self.version.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+
+        self.reload().await?;
+        return Ok(true);
+    }
+
+    ///Adds hashes to a list. Hashes that are already on the list get the distance and label they are added with.
+    ///
+    /// # Arguments
+    /// scope: ListScope - Whom the list applies to
+    /// list: ListKind - The list the hashes are added to
+    /// entries: &[(ImageHash, u32, Option<String>)] - The hashes, the distance they match within and their labels
+    ///
+    /// # Returns
+    /// Result<(), String> - An error if the hashes could not be stored, in which case none of them has been added
+    pub async fn add(&self, scope: ListScope, list: ListKind, entries: &[(ImageHash, u32, Option<String>)]) -> Result<(), String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        self.store.add(scope, list, entries.to_vec(), now).await?;
+
+        //The last time a hash is added wins, like it does in the store
+        let mut lists = self.lists.write().unwrap();
+        let index = lists.entry((scope, list)).or_default();
+
+        for (hash, max_distance, label) in entries {
+            index.insert(Entry { hash: *hash, max_distance: *max_distance, label: label.clone() });
+        }
+
+        return Ok(());
+    }
+
+    ///Removes a hash from a list
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the hash has been on the list
+    pub async fn remove(&self, scope: ListScope, list: ListKind, hash: &ImageHash) -> Result<bool, String> {
+        let removed = self.store.remove(scope, list, *hash).await?;
+
+        if let Some(index) = self.lists.write().unwrap().get_mut(&(scope, list)) {
+            index.entries.remove(hash);
+        }
+
+        return Ok(removed);
+    }
+
+    ///Returns the hashes on a list, newest first
+    pub async fn list(&self, scope: ListScope, list: ListKind, limit: u32, offset: u32) -> Result<Vec<ListEntry>, String> {
+        return self.store.list(scope, list, limit, offset).await;
+    }
+
+    ///Matches the hash of an image against the lists of a project and the global lists. The lists of the project take precedence
+    ///over the global ones, and blocklists over allowlists, so an image that is on both lists of a scope is blocked. Within a list
+    ///the closest entry matches.
+    ///
+    /// # Arguments
+    /// project_id: u64 - The project the image has been sent by
+    /// hash: &ImageHash - The hash of the image
+    ///
+    /// # Returns
+    /// Option<ListMatch> - The entry the image matched, if it is on one of the lists
+    pub fn find_match(&self, project_id: u64, hash: &ImageHash) -> Option<ListMatch> {
+        let lists = self.lists.read().unwrap();
+
+        for scope in [ListScope::Project(project_id), ListScope::Global] {
+            for list in [ListKind::Block, ListKind::Allow] {
+                let closest = lists.get(&(scope, list)).and_then(|index| index.find_closest(hash));
+
+                if let Some((entry, distance)) = closest {
+                    return Some(ListMatch { list, scope: scope.name(), hash: entry.hash.to_string(), distance, label: entry.label.clone() });
+                }
+            }
+        }
+
+        return None;
+    }
+}
+
+///Periodically checks whether another instance changed the lists, and reloads them if it did
+pub async fn watch_changes(lists: web::Data<HashLists>, interval: Duration) {
+    loop {
+        sleep(interval).await;
+
+        if let Err(err) = lists.reload_if_changed().await {
+            eprintln!("Could not reload the hash lists, keeping the previous ones. {}", err);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use actix_web::web::Bytes;
+use image::{io::{Limits, Reader}, DynamicImage, ImageError};
+use tokio::sync::Semaphore;
+use crate::config::{ImageConfig, PipelineConfig};
+use super::perceptual_hash::{self, ImageHash};
+use super::preprocessing;
+use super::scan_error::ScanError;
+use super::upload::Upload;
+
+fn invalid_image() -> ScanError {
+    return ScanError::InvalidImage("Encountered an issue while attempting to process your image. Please validate it's data type is correct.".to_string());
+}
+
+///Decodes an image, after its header has been checked against our limits. Images can declare dimensions that need far more
+///memory than their data, so their dimensions are checked before anything is allocated for them.
+fn decode(image: &Upload, config: &ImageConfig) -> Result<DynamicImage, ScanError> {
+    let open = || -> Result<_, ScanError> {
+        return Reader::new(image.reader().map_err(|_| invalid_image())?).with_guessed_format().map_err(|_| invalid_image());
+    };
+
+    let (width, height) = open()?.into_dimensions().map_err(|_| invalid_image())?;
+
+    if width > config.max_width || height > config.max_height || width as u64 * height as u64 > config.max_pixels {
+        return Err(ScanError::ImageTooLarge(format!("The image is {}x{} pixels large. We scan images of up to {}x{} and {} pixels in total.",
+            width, height, config.max_width, config.max_height, config.max_pixels)));
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(config.max_width);
+    limits.max_image_height = Some(config.max_height);
+    limits.max_alloc = Some(config.max_alloc);
+
+    let mut reader = open()?;
+    reader.limits(limits);
+
+    return reader.decode().map_err(|err| match err {
+        ImageError::Limits(_) => ScanError::ImageTooLarge("Decoding the image would need more memory than we allow.".to_string()),
+        _ => invalid_image(),
+    });
+}
+
+///Decodes and preprocesses the images our clients send us. The work is done on tokio's blocking threads, so it doesn't stall the
+///requests that are handled on the async executor, and only `decode_concurrency` images are decoded at once.
+pub struct ImageDecoder {
+    config: ImageConfig,
+    permits: Semaphore,
+}
+
+impl ImageDecoder {
+    pub fn new(config: &ImageConfig) -> ImageDecoder {
+        return ImageDecoder { config: config.clone(), permits: Semaphore::new(config.decode_concurrency) };
+    }
+
+    ///Decodes an image, computes its perceptual hash and runs it through a preprocessing pipeline, see `perceptual_hash::compute`
+    ///and `preprocessing::run`
+    ///
+    /// # Arguments
+    /// image: &Upload - The image to preprocess
+    /// pipeline: &PipelineConfig - The pipeline the image is run through
+    ///
+    /// # Returns
+    /// Result<(Bytes, ImageHash), ScanError> - The output of the pipeline and the hash of the image, or image_too_large if the
+    /// image exceeds our limits
+    pub async fn preprocess(&self, image: &Upload, pipeline: &PipelineConfig) -> Result<(Bytes, ImageHash), ScanError> {
+        //The semaphore is never closed
+        let _permit = self.permits.acquire().await.unwrap();
+        let (image, config, pipeline) = (image.clone(), self.config.clone(), pipeline.clone());
+
+        return tokio::task::spawn_blocking(move || {
+            let decoded = decode(&image, &config)?;
+            let orientation = preprocessing::read_orientation(&image);
+            //Hashed before the pipeline runs, so changing the pipeline doesn't change the hashes our results are stored under
+            let hash = perceptual_hash::compute(&decoded, orientation);
+
+            let output = preprocessing::run(decoded, orientation, &pipeline).ok_or_else(invalid_image)?;
+            return Ok((output, hash));
+        }).await.map_err(|_| invalid_image())?;
+    }
+
+    ///Decodes an image and resizes it like we did before images were run through a pipeline, see `preprocessing::run_legacy`
+    ///
+    /// # Arguments
+    /// image: &Upload - The image to resize
+    ///
+    /// # Returns
+    /// Result<Bytes, ScanError> - The resized image as PNG, or image_too_large if the image exceeds our limits
+    pub async fn resize_legacy(&self, image: &Upload) -> Result<Bytes, ScanError> {
+        //The semaphore is never closed
+        let _permit = self.permits.acquire().await.unwrap();
+        let (image, config) = (image.clone(), self.config.clone());
+
+        return tokio::task::spawn_blocking(move || {
+            let decoded = decode(&image, &config)?;
+            return preprocessing::run_legacy(decoded).ok_or_else(invalid_image);
+        }).await.map_err(|_| invalid_image())?;
+    }
+}
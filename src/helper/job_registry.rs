@@ -0,0 +1,66 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+use super::ttl_map::TtlMap;
+
+///Time after which a job whose result did not arrive is considered failed
+const JOB_TIMEOUT: Duration = Duration::from_secs(600);
+///Time a job is remembered after it has been submitted, so its result can still be polled
+const JOB_RETENTION: Duration = Duration::from_secs(3600);
+///Most jobs that are remembered at once
+const MAX_JOBS: usize = 100000;
+
+///State of a scan job, as far as we know it without asking the database for its result
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    ///The job is waiting for a worker to post its result
+    Pending,
+    ///No result arrived within JOB_TIMEOUT
+    Failed,
+}
+
+struct Job {
+    ///Projects that submitted the data of the job. Only they may poll its result.
+    project_ids: Vec<u64>,
+    submitted_at: Instant,
+}
+
+///Remembers the scan jobs that have been handed to our workers, keyed by the hash of their data. Lets clients poll for the result of
+///their job and keeps us from queueing the same data again while it is being scanned. Jobs are only known to the instance they were
+///submitted to.
+pub struct JobRegistry {
+    jobs: Mutex<TtlMap<String, Job>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> JobRegistry {
+        return JobRegistry { jobs: Mutex::new(TtlMap::new(MAX_JOBS)) };
+    }
+
+    ///Returns true if a job for the hash is waiting for its result, so it doesn't have to be queued again
+    pub fn is_pending(&self, hash: &str) -> bool {
+        return self.jobs.lock().unwrap().get(hash).is_some_and(|job| job.submitted_at.elapsed() < JOB_TIMEOUT);
+    }
+
+    ///Remembers that a project submitted a job for the hash. Jobs that have been submitted again are restarted if they failed.
+    pub fn submit(&self, hash: &str, project_id: u64) {
+        let mut jobs = self.jobs.lock().unwrap();
+
+        let job = jobs.get_or_insert_with(hash.to_string(), JOB_RETENTION, || Job { project_ids: Vec::new(), submitted_at: Instant::now() });
+
+        if !job.project_ids.contains(&project_id) {
+            job.project_ids.push(project_id);
+        }
+
+        if job.submitted_at.elapsed() >= JOB_TIMEOUT {
+            job.submitted_at = Instant::now();
+            jobs.set_ttl(hash, JOB_RETENTION);
+        }
+    }
+
+    ///Returns the state of the job for the hash, if the project submitted one that is still remembered
+    pub fn get_state(&self, hash: &str, project_id: u64) -> Option<JobState> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(hash).filter(|job| job.project_ids.contains(&project_id))?;
+
+        return Some(if job.submitted_at.elapsed() < JOB_TIMEOUT { JobState::Pending } else { JobState::Failed });
+    }
+}
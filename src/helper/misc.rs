@@ -1,45 +1,7 @@
-use std::{env, io::Cursor};
+use std::env;
 use actix_web::web::Bytes;
-use image::{DynamicImage};
 use serde_json::Value;
 
-///Resizes an image
-/// # Arguments
-/// bytes: &Bytes - The data of the image to resize
-/// width: &u16 - Width of the image to resize to
-/// height: &u16 - Height of the image to resize to, if left blank it's calculated off of the width
-/// 
-/// # Returns
-/// Bytes - The resized image
-pub(crate) async fn resize_image(bytes: &Bytes, width: &u32, height: &u32) -> Option<Bytes>{
-    let image = image::load_from_memory(&bytes);
-
-    if image.is_err(){
-        return None;
-    }
-
-    let unwrapped_image = image.unwrap();
-    let resized_image: DynamicImage;
-
-    if height > &0 {
-        resized_image = unwrapped_image.resize(*width, *height, image::imageops::FilterType::Nearest);
-    }else{
-        let ratio =  unwrapped_image.width() as f32 / unwrapped_image.height() as f32;
-        let new_height = *width as f32 * ratio;
-        let new_height_int = new_height as u32;
-        resized_image = unwrapped_image.resize(*width, new_height_int, image::imageops::FilterType::Lanczos3);
-    }
-
-    let mut resized_image_bytes: Vec<u8> = Vec::new();
-    let write_result = resized_image.write_to(&mut Cursor::new(&mut resized_image_bytes), image::ImageOutputFormat::Png);
-
-    if write_result.is_err() {
-        return None;
-    }
-
-    return Some(Bytes::from(resized_image_bytes))
-}
-
 ///Returns the enviorment variable with the given name, or the alternate value if the variable is not set
 /// 
 /// # Arguments
@@ -58,23 +20,20 @@ pub(crate) async fn resize_image(bytes: &Bytes, width: &u32, height: &u32) -> Op
 pub fn get_env_variable(env_var_name: String, alternate_value: String) -> String{
     let env_value = env::var(&env_var_name);
 
-    return if env_value.is_err() || env_value.as_ref().unwrap().is_empty()
-    {
-        alternate_value
-    } else
-    {
-        env_value.unwrap()
+    return match env_value {
+        Ok(value) if !value.is_empty() => value,
+        _ => alternate_value
     }
 }
 
 ///Get's a json value from the given string or returns none if the string is not a valid json
 /// 
 /// # Arguments
-/// content: &String - The string to parse as json
+/// content: &str - The string to parse as json
 /// 
 /// # Returns
 /// Option<Value> - The json value or none if the string is not a valid json
-pub fn get_json_value(contents: &String) -> Option<Value>{
+pub fn get_json_value(contents: &str) -> Option<Value>{
     let v: Value = match serde_json::from_str(contents) {
         Ok(it) => it,
         Err(_err) => return None,
@@ -0,0 +1,123 @@
+use std::{fs, path::Path, sync::RwLock, time::Duration};
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::sleep;
+use crate::config::{ModelConfig, StalePolicy};
+
+///A model our workers scan images with
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Model {
+    pub name: String,
+    pub version: String,
+}
+
+///Reads the manifest of the current model
+fn read_manifest(path: &Path) -> Result<Model, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("Could not read the model manifest {}: {}", path.display(), err))?;
+    let model: Model = serde_json::from_str(&contents).map_err(|err| format!("Could not parse the model manifest {}: {}", path.display(), err))?;
+
+    if model.version.trim().is_empty() {
+        return Err(format!("The model manifest {} does not contain a version", path.display()));
+    }
+
+    return Ok(model);
+}
+
+///Returns a field of a result, whether it has been posted by one of our workers (PascalCase) or returned by our database API
+///(camelCase)
+fn result_field<'a>(result: &'a Value, pascal_case: &str, camel_case: &str) -> Option<&'a str> {
+    return result.get(pascal_case).or_else(|| result.get(camel_case)).and_then(Value::as_str);
+}
+
+///Returns true if a result records the model that produced it. Our workers have to record it in every result they post, as
+///only they know which model scanned the image, which may not be the current one while a model is being rolled out.
+pub fn records_model(result: &Value) -> bool {
+    return [("ModelName", "modelName"), ("ModelVersion", "modelVersion")].iter()
+        .all(|(pascal_case, camel_case)| result_field(result, pascal_case, camel_case).is_some_and(|value| !value.trim().is_empty()));
+}
+
+///Knows the model our workers currently scan images with, from the configuration or a manifest file that is reloaded when it
+///changes, and tells which results have been produced by another model
+pub struct ModelVersions {
+    config: ModelConfig,
+    current: RwLock<Model>,
+}
+
+impl ModelVersions {
+    pub fn new(config: &ModelConfig) -> Result<ModelVersions, String> {
+        let current = match &config.manifest_path {
+            Some(path) => read_manifest(path)?,
+            None => Model { name: config.name.clone(), version: config.version.clone() },
+        };
+
+        return Ok(ModelVersions { config: config.clone(), current: RwLock::new(current) });
+    }
+
+    ///Returns the current model
+    pub fn current(&self) -> Model {
+        return self.current.read().unwrap().clone();
+    }
+
+    ///Returns true if a result has been produced by another model than the current one. Results that don't record their model
+    ///have been produced before models were recorded, so they are stale as well. Nothing is stale while no version is known.
+    pub fn is_stale(&self, result: &Value) -> bool {
+        let current = self.current.read().unwrap();
+
+        if current.version.is_empty() {
+            return false;
+        }
+
+        return result_field(result, "ModelName", "modelName") != Some(current.name.as_str())
+            || result_field(result, "ModelVersion", "modelVersion") != Some(current.version.as_str());
+    }
+
+    ///Returns what happens with stale results of a project
+    pub fn stale_policy(&self, project_id: u64) -> StalePolicy {
+        return self.config.projects.get(&project_id.to_string())
+            .and_then(|project| project.stale_policy)
+            .unwrap_or(self.config.stale_policy);
+    }
+
+    ///Rereads the manifest
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the model changed, or a description of why the manifest could not be read
+    fn reload(&self) -> Result<bool, String> {
+        let path = match &self.config.manifest_path {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+
+        let model = read_manifest(path)?;
+        let mut current = self.current.write().unwrap();
+
+        if *current == model {
+            return Ok(false);
+        }
+
+        *current = model;
+        return Ok(true);
+    }
+}
+
+///Periodically rereads the manifest of the current model, so a deployed model is noticed without restarting the API
+pub async fn watch_manifest(models: web::Data<ModelVersions>, interval: Duration) {
+    if models.config.manifest_path.is_none() {
+        return;
+    }
+
+    loop {
+        sleep(interval).await;
+
+        match models.reload() {
+            Ok(true) => {
+                let model = models.current();
+                eprintln!("The model manifest changed, results of models other than {} {} are stale now.", model.name, model.version);
+            },
+            Ok(false) => {},
+            Err(err) => eprintln!("Could not reload the model manifest, keeping the previous model. {}", err),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{fmt, str::FromStr};
+use image::{DynamicImage, GenericImageView, Rgba};
+
+///Prefix of the hashes of version 1 of our algorithm. Hashes of other versions get another prefix, so they can never be
+///mistaken for each other.
+pub const VERSION_PREFIX: &str = "dh1_";
+///Columns of the grid an image is reduced to. Neighbouring columns are compared, which gives one bit less per row.
+const GRID_WIDTH: u32 = 9;
+///Rows of the grid an image is reduced to
+const GRID_HEIGHT: u32 = 8;
+
+///Perceptual hash of an image, version 1 of our difference hash (dHash). Images that look alike have hashes that differ in
+///few bits, so the Hamming distance of two hashes tells how alike their images are.
+///
+///The hash is computed from the decoded image, before any of our preprocessing:
+/// 1. The image is turned upright as its EXIF orientation says (1 to 8, as with `preprocessing::read_orientation`).
+/// 2. Every pixel is drawn onto white: `c = (c * a + 255 * (255 - a)) / 255` for every 8 bit channel, rounding down.
+/// 3. Every pixel is converted to its luma: `l = (299 * r + 587 * g + 114 * b) / 1000`, rounding down.
+/// 4. The image is split into a grid of 9 columns and 8 rows. Column `x` covers the pixels from `x * width / 9` to
+///    `max((x + 1) * width / 9, x * width / 9 + 1)`, exclusive and rounding down, and rows are split alike. Columns of images
+///    narrower than the grid share their single pixel column with their neighbours.
+/// 5. Every row yields 8 bits, one for each pair of neighbouring cells, which is set if the mean luma of the left cell is
+///    greater than the one of the right cell.
+/// 6. The 64 bits are ordered by row from top to bottom, then by column from left to right, starting with the most
+///    significant bit, and written as 16 lowercase hex digits after the version prefix, like `dh1_f0e1d2c3b4a59687`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    ///Returns the number of bits the hashes differ in
+    pub fn distance(&self, other: &ImageHash) -> u32 {
+        return (self.0 ^ other.0).count_ones();
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}{:016x}", VERSION_PREFIX, self.0);
+    }
+}
+
+impl FromStr for ImageHash {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<ImageHash, ()> {
+        let hex = value.strip_prefix(VERSION_PREFIX).filter(|hex| hex.len() == 16).ok_or(())?;
+        return u64::from_str_radix(hex, 16).map(ImageHash).map_err(|_| ());
+    }
+}
+
+///Returns the position a pixel ends up at once the image has been turned upright as its EXIF orientation says, the same way
+///`preprocessing` turns images
+fn oriented_position(x: u32, y: u32, width: u32, height: u32, orientation: u16) -> (u32, u32) {
+    return match orientation {
+        2 => (width - 1 - x, y),
+        3 => (width - 1 - x, height - 1 - y),
+        4 => (x, height - 1 - y),
+        5 => (y, x),
+        6 => (height - 1 - y, x),
+        7 => (height - 1 - y, width - 1 - x),
+        8 => (y, width - 1 - x),
+        _ => (x, y),
+    };
+}
+
+///Returns the first pixel of a cell of the grid along one axis, see step 4 of `ImageHash`
+fn cell_start(cell: u32, size: u32, cells: u32) -> u32 {
+    return (cell as u64 * size as u64 / cells as u64) as u32;
+}
+
+///Returns the cell of the grid a pixel falls into along one axis, which is the last cell that starts at or before it. Cells of
+///axes shorter than the grid that start at the same pixel are empty, except for the last of them.
+fn grid_cell(position: u32, size: u32, cells: u32) -> usize {
+    let mut cell = (position as u64 * cells as u64 / size as u64) as u32;
+
+    while cell > 0 && cell_start(cell, size, cells) > position {
+        cell -= 1;
+    }
+
+    while cell + 1 < cells && cell_start(cell + 1, size, cells) <= position {
+        cell += 1;
+    }
+
+    return cell as usize;
+}
+
+///Computes the perceptual hash of a decoded image
+///
+/// # Arguments
+/// image: &DynamicImage - The decoded image, before it has been turned upright
+/// orientation: Option<u16> - The EXIF orientation of the image, if it has one
+///
+/// # Returns
+/// ImageHash - The hash of the image
+pub fn compute(image: &DynamicImage, orientation: Option<u16>) -> ImageHash {
+    let orientation = orientation.unwrap_or(1);
+    let (width, height) = image.dimensions();
+    let (upright_width, upright_height) = if orientation >= 5 { (height, width) } else { (width, height) };
+
+    let mut sums = [[0u64; GRID_WIDTH as usize]; GRID_HEIGHT as usize];
+    let mut counts = [[0u64; GRID_WIDTH as usize]; GRID_HEIGHT as usize];
+
+    if width > 0 && height > 0 {
+        for (x, y, pixel) in image.pixels() {
+            let Rgba([r, g, b, a]) = pixel;
+            let flatten = |channel: u8| (channel as u64 * a as u64 + 255 * (255 - a as u64)) / 255;
+            let luma = (299 * flatten(r) + 587 * flatten(g) + 114 * flatten(b)) / 1000;
+
+            let (upright_x, upright_y) = oriented_position(x, y, width, height, orientation);
+            let (column, row) = (grid_cell(upright_x, upright_width, GRID_WIDTH), grid_cell(upright_y, upright_height, GRID_HEIGHT));
+
+            sums[row][column] += luma;
+            counts[row][column] += 1;
+        }
+
+        //Empty cells of images smaller than the grid take the pixels of the cell that holds the pixel they start at
+        for row in 0..GRID_HEIGHT {
+            for column in 0..GRID_WIDTH {
+                if counts[row as usize][column as usize] == 0 {
+                    let source_column = grid_cell(cell_start(column, upright_width, GRID_WIDTH), upright_width, GRID_WIDTH);
+                    let source_row = grid_cell(cell_start(row, upright_height, GRID_HEIGHT), upright_height, GRID_HEIGHT);
+
+                    sums[row as usize][column as usize] = sums[source_row][source_column];
+                    counts[row as usize][column as usize] = counts[source_row][source_column];
+                }
+            }
+        }
+    }
+
+    let mut hash = 0u64;
+
+    for row in 0..GRID_HEIGHT as usize {
+        for column in 0..GRID_WIDTH as usize - 1 {
+            //Compares the means of both cells without dividing
+            let left = sums[row][column] * counts[row][column + 1];
+            let right = sums[row][column + 1] * counts[row][column];
+
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+
+    return ImageHash(hash);
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Rgba};
+    use super::{compute, ImageHash};
+
+    ///Images our algorithm is checked against, generated from their index, so the check doesn't need any files
+    fn test_vector_image(index: usize) -> DynamicImage {
+        let image = match index {
+            //Gets brighter to the right, so no left cell is brighter than its right one
+            0 => ImageBuffer::from_fn(64, 64, |x, _| Rgba([(x * 4) as u8, (x * 4) as u8, (x * 4) as u8, 255])),
+            //Gets darker to the right
+            1 => ImageBuffer::from_fn(64, 64, |x, _| Rgba([255 - (x * 4) as u8, 255 - (x * 4) as u8, 255 - (x * 4) as u8, 255])),
+            //A colored pattern whose size isn't a multiple of the grid
+            2 => ImageBuffer::from_fn(100, 75, |x, y| Rgba([((x * 7 + y * 13) % 256) as u8, ((x * x + y) % 256) as u8, ((y * y * 3 + x) % 256) as u8, 255])),
+            //A transparent gradient, which is drawn onto white
+            3 => ImageBuffer::from_fn(90, 40, |x, y| Rgba([0, 0, 0, ((x * 2 + y) % 256) as u8])),
+            //An image smaller than the grid
+            _ => ImageBuffer::from_fn(3, 2, |x, y| Rgba([255 - (x * 100) as u8, (y * 200) as u8, 50, 255])),
+        };
+
+        return DynamicImage::ImageRgba8(image);
+    }
+
+    ///Hashes of the test vectors, which pin the output of our algorithm. They must never change for this version, as every
+    ///stored result is keyed by them. (index of the test vector image, EXIF orientation, hash)
+    const TEST_VECTORS: [(usize, Option<u16>, &str); 8] = [
+        (0, None, "dh1_0000000000000000"),
+        (1, None, "dh1_ffffffffffffffff"),
+        (2, None, "dh1_6c59645b66496649"),
+        (2, Some(2), "dh1_cd65c9279b659364"),
+        (2, Some(6), "dh1_b5522bb552abb556"),
+        (3, Some(3), "dh1_0000000000000000"),
+        (3, None, "dh1_ffffffffffffffff"),
+        (4, None, "dh1_2424242424242424"),
+    ];
+
+    //Checks that our algorithm still produces the hashes of its test vectors, so a change to it can't silently change the hashes
+    //our results are stored under
+    #[test]
+    fn test_vectors_keep_their_hashes() {
+        for (index, orientation, expected) in TEST_VECTORS {
+            let hash = compute(&test_vector_image(index), orientation).to_string();
+            assert_eq!(hash, expected, "test vector {} with orientation {:?}", index, orientation);
+        }
+    }
+
+    #[test]
+    fn hashes_are_parsed_from_their_text() {
+        let hash = ImageHash(0x6c59645b66496649);
+
+        assert_eq!(hash.to_string().parse(), Ok(hash));
+        assert_eq!("dh1_6c59645b6649664".parse::<ImageHash>(), Err(()));
+        assert_eq!("dh2_6c59645b66496649".parse::<ImageHash>(), Err(()));
+        assert_eq!("6c59645b66496649".parse::<ImageHash>(), Err(()));
+    }
+
+    #[test]
+    fn distance_counts_the_differing_bits() {
+        assert_eq!(ImageHash(0).distance(&ImageHash(0)), 0);
+        assert_eq!(ImageHash(0).distance(&ImageHash(u64::MAX)), 64);
+        assert_eq!(ImageHash(0b1010).distance(&ImageHash(0b0110)), 2);
+    }
+}
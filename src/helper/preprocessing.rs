@@ -0,0 +1,199 @@
+use std::io::{Cursor, Read};
+use actix_web::web::Bytes;
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use crate::config::{self, OutputEncoding, PipelineConfig, ResizeFilter, ResizeMode};
+use super::upload::Upload;
+
+///Bytes at the start of a JPEG its EXIF data is looked for in, which is as large as the APP1 segment holding it can get
+const EXIF_SEARCH_BYTES: u64 = 64 * 1024 + 1024;
+///EXIF tag of the orientation of an image
+const ORIENTATION_TAG: u16 = 0x0112;
+///Width and height images were resized to before they were run through a pipeline, see `run_legacy`
+const LEGACY_SIZE: u32 = 250;
+
+///Reads the EXIF orientation of an image, if it is a JPEG that has one
+///
+/// # Returns
+/// Option<u16> - The orientation from 1 to 8, as defined by the EXIF standard
+pub fn read_orientation(image: &Upload) -> Option<u16> {
+    let mut data = Vec::new();
+    image.reader().ok()?.take(EXIF_SEARCH_BYTES).read_to_end(&mut data).ok()?;
+
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut position = 2;
+
+    //Walks the segments of the JPEG up to the start of its image data
+    while position + 4 <= data.len() {
+        if data[position] != 0xFF {
+            return None;
+        }
+
+        let marker = data[position + 1];
+
+        //Fill bytes, which may precede any marker
+        if marker == 0xFF {
+            position += 1;
+            continue;
+        }
+
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        let segment = data.get(position + 4..position + 2 + length)?;
+
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return read_tiff_orientation(&segment[6..]);
+        }
+
+        position += 2 + length;
+    }
+
+    return None;
+}
+
+///Reads the orientation from the first IFD of the TIFF structure EXIF data is stored in
+fn read_tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let little_endian = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+
+    let read_u16 = |offset: usize| tiff.get(offset..offset + 2).map(|bytes| {
+        if little_endian { u16::from_le_bytes([bytes[0], bytes[1]]) } else { u16::from_be_bytes([bytes[0], bytes[1]]) }
+    });
+    let read_u32 = |offset: usize| tiff.get(offset..offset + 4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    });
+
+    if read_u16(2)? != 42 {
+        return None;
+    }
+
+    let ifd = read_u32(4)? as usize;
+    let entries = read_u16(ifd)? as usize;
+
+    for entry in (0..entries).map(|index| ifd + 2 + index * 12) {
+        if read_u16(entry)? == ORIENTATION_TAG {
+            return read_u16(entry + 8).filter(|orientation| (1..=8).contains(orientation));
+        }
+    }
+
+    return None;
+}
+
+///Rotates and flips an image, so it is shown upright without its EXIF orientation
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    return match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    };
+}
+
+///Draws a transparent image onto a background color
+fn flatten(image: DynamicImage, background: [u8; 3]) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image;
+    }
+
+    let rgba = image.into_rgba8();
+    let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as u32;
+        let blend = |channel: usize| ((pixel[channel] as u32 * alpha + background[channel] as u32 * (255 - alpha) + 127) / 255) as u8;
+
+        Rgb([blend(0), blend(1), blend(2)])
+    });
+
+    return DynamicImage::ImageRgb8(flattened);
+}
+
+fn filter_type(filter: ResizeFilter) -> FilterType {
+    return match filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Triangle => FilterType::Triangle,
+        ResizeFilter::CatmullRom => FilterType::CatmullRom,
+        ResizeFilter::Gaussian => FilterType::Gaussian,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+    };
+}
+
+///Resizes an image to the target size of a pipeline
+fn resize(image: DynamicImage, pipeline: &PipelineConfig) -> DynamicImage {
+    let filter = filter_type(pipeline.filter);
+
+    //Derived from the aspect ratio of the image, so the resized image keeps it
+    let height = if pipeline.height > 0 {
+        pipeline.height
+    } else {
+        ((pipeline.width as u64 * image.height() as u64) / image.width().max(1) as u64).max(1) as u32
+    };
+
+    return match pipeline.resize {
+        ResizeMode::Fit => image.resize(pipeline.width, height, filter),
+        ResizeMode::Fill => image.resize_exact(pipeline.width, height, filter),
+        ResizeMode::Crop => image.resize_to_fill(pipeline.width, height, filter),
+    };
+}
+
+///Runs a decoded image through a preprocessing pipeline
+///
+/// # Arguments
+/// image: DynamicImage - The decoded image
+/// orientation: Option<u16> - The EXIF orientation of the image, if it has one
+/// pipeline: &PipelineConfig - The pipeline the image is run through
+///
+/// # Returns
+/// Option<Bytes> - The encoded output of the pipeline, or none if it could not be encoded
+pub fn run(mut image: DynamicImage, orientation: Option<u16>, pipeline: &PipelineConfig) -> Option<Bytes> {
+    if pipeline.normalize_orientation {
+        if let Some(orientation) = orientation {
+            image = orient(image, orientation);
+        }
+    }
+
+    if pipeline.flatten_alpha {
+        //Validated when the configuration is loaded
+        image = flatten(image, config::parse_color(&pipeline.background).unwrap_or([255, 255, 255]));
+    }
+
+    image = resize(image, pipeline);
+
+    let (image, format) = match pipeline.encoding {
+        OutputEncoding::Png => (image, ImageOutputFormat::Png),
+        //JPEGs can't be transparent, so transparent pixels lose their alpha if they haven't been flattened
+        OutputEncoding::Jpeg => (DynamicImage::ImageRgb8(image.to_rgb8()), ImageOutputFormat::Jpeg(pipeline.jpeg_quality)),
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut output), format).ok()?;
+
+    return Some(Bytes::from(output));
+}
+
+///Resizes a decoded image the way we did before images were run through a pipeline: to fit 250x250 pixels with the nearest
+///filter, encoded as PNG and without looking at its EXIF orientation. The database API hashed these bytes for the results we
+///stored back then, so they are only needed to look those results up.
+///
+/// # Returns
+/// Option<Bytes> - The resized image, or none if it could not be encoded
+pub fn run_legacy(image: DynamicImage) -> Option<Bytes> {
+    let image = image.resize(LEGACY_SIZE, LEGACY_SIZE, FilterType::Nearest);
+
+    let mut output: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Png).ok()?;
+
+    return Some(Bytes::from(output));
+}
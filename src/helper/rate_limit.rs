@@ -0,0 +1,237 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, body::EitherBody, http::header::{HeaderMap, HeaderName, HeaderValue}, web, Error, HttpMessage, ResponseError};
+use futures::future::LocalBoxFuture;
+use crate::config::{RateLimitBackend, RateLimitConfig};
+use super::auth::AuthenticatedClient;
+use super::rate_limit_store::{BucketLimit, MemoryStore, RateLimitStore, RedisStore};
+use super::scan_error::ScanError;
+
+///Seconds a client is asked to wait after it hit its limit of concurrent scans. Most scans finish within this time.
+const CONCURRENCY_RETRY_AFTER_SECS: u64 = 5;
+
+///Limits of a project, after applying its overrides to the defaults
+struct ProjectLimits {
+    bucket: BucketLimit,
+    max_concurrent_scans: u32,
+}
+
+///State of a project's rate limit, sent to the client in the X-RateLimit-* headers
+pub struct RateLimitStatus {
+    ///Requests the project may send at once
+    limit: u32,
+    ///Requests the project may still send right now
+    remaining: u32,
+    ///Seconds until the project may send limit requests at once again
+    reset: u64,
+}
+
+impl RateLimitStatus {
+    fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(HeaderName::from_static("x-ratelimit-limit"), HeaderValue::from(self.limit));
+        headers.insert(HeaderName::from_static("x-ratelimit-remaining"), HeaderValue::from(self.remaining));
+        headers.insert(HeaderName::from_static("x-ratelimit-reset"), HeaderValue::from(self.reset));
+    }
+}
+
+///One of the concurrent scans a project may have in flight. The slot is freed when this is dropped.
+pub struct ScanSlot {
+    store: Arc<dyn RateLimitStore>,
+    key: String,
+}
+
+impl Drop for ScanSlot {
+    fn drop(&mut self) {
+        let store = self.store.clone();
+        let key = std::mem::take(&mut self.key);
+
+        actix_web::rt::spawn(async move {
+            if let Err(err) = store.release_slot(&key).await {
+                eprintln!("Could not free a scan slot of project {}. {}", key, err);
+            }
+        });
+    }
+}
+
+///Decision on whether a request is within the limits of its project
+pub enum RateLimitDecision {
+    ///The request may be processed. The status is missing if the limits could not be checked.
+    Allowed(Option<RateLimitStatus>, Option<ScanSlot>),
+    ///The request has to be rejected with the given error
+    Rejected(RateLimitStatus, ScanError),
+}
+
+///Limits how fast each project may request scans with a token bucket, and how many of its scans may be in flight at once
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    ///Creates a rate limiter that keeps its counters in the configured backend
+    ///
+    /// # Returns
+    /// Result<RateLimiter, String> - The rate limiter or why its backend could not be reached
+    pub async fn new(config: &RateLimitConfig) -> Result<RateLimiter, String> {
+        let store: Arc<dyn RateLimitStore> = match config.backend {
+            RateLimitBackend::Memory => Arc::new(MemoryStore::new()),
+            RateLimitBackend::Redis => Arc::new(RedisStore::new(&config.redis_url).await?),
+        };
+
+        return Ok(RateLimiter { config: config.clone(), store });
+    }
+
+    fn get_limits(&self, project_id: u64) -> ProjectLimits {
+        let overrides = self.config.projects.get(&project_id.to_string());
+
+        return ProjectLimits {
+            bucket: BucketLimit {
+                requests_per_second: overrides.and_then(|limits| limits.requests_per_second).unwrap_or(self.config.requests_per_second),
+                burst: overrides.and_then(|limits| limits.burst).unwrap_or(self.config.burst),
+            },
+            max_concurrent_scans: overrides.and_then(|limits| limits.max_concurrent_scans).unwrap_or(self.config.max_concurrent_scans),
+        };
+    }
+
+    ///Checks a request of a project against its limits and takes one of its scan slots if it is allowed.
+    ///Requests are let through if the backend can't be reached, so an outage of it doesn't take down our API.
+    pub async fn check(&self, project_id: u64) -> RateLimitDecision {
+        let limits = self.get_limits(project_id);
+        let key = project_id.to_string();
+
+        let bucket = match self.store.take_token(&key, limits.bucket).await {
+            Ok(bucket) => bucket,
+            Err(err) => {
+                eprintln!("Could not check the rate limit of project {}, letting the request through. {}", project_id, err);
+                return RateLimitDecision::Allowed(None, None);
+            }
+        };
+
+        let rate = limits.bucket.requests_per_second;
+        let status = RateLimitStatus {
+            limit: limits.bucket.burst,
+            remaining: bucket.remaining.floor() as u32,
+            reset: ((limits.bucket.burst as f64 - bucket.remaining) / rate).ceil() as u64,
+        };
+
+        if !bucket.allowed {
+            let retry_after = ((1.0 - bucket.remaining) / rate).ceil().max(1.0) as u64;
+            return RateLimitDecision::Rejected(status, ScanError::RateLimited { retry_after });
+        }
+
+        if limits.max_concurrent_scans == 0 {
+            return RateLimitDecision::Allowed(Some(status), None);
+        }
+
+        return match self.store.acquire_slot(&key, limits.max_concurrent_scans).await {
+            Ok(true) => RateLimitDecision::Allowed(Some(status), Some(ScanSlot { store: self.store.clone(), key })),
+            Ok(false) => RateLimitDecision::Rejected(status, ScanError::TooManyScans { retry_after: CONCURRENCY_RETRY_AFTER_SECS }),
+            Err(err) => {
+                eprintln!("Could not check the concurrent scans of project {}, letting the request through. {}", project_id, err);
+                RateLimitDecision::Allowed(Some(status), None)
+            }
+        };
+    }
+}
+
+///Middleware that applies the rate limits of the client's project to every request whose path starts with one of its routes.
+///Has to be wrapped inside of the Authentication middleware, as it limits by the project of the authenticated client.
+///
+/// # Example
+/// ```
+/// App::new()
+///     .wrap(RateLimiting::new().route("/scan/v1/detection/"))
+///     .wrap(Authentication::new())
+/// ```
+pub struct RateLimiting {
+    routes: Rc<Vec<&'static str>>,
+    excluded: Rc<Vec<&'static str>>,
+}
+
+impl RateLimiting {
+    pub fn new() -> RateLimiting {
+        return RateLimiting { routes: Rc::new(Vec::new()), excluded: Rc::new(Vec::new()) };
+    }
+
+    ///Applies the rate limits to every path that starts with the given prefix. Prefixes are matched against the percent-decoded
+    ///path, which is what our routes are matched against as well.
+    pub fn route(mut self, path_prefix: &'static str) -> RateLimiting {
+        Rc::get_mut(&mut self.routes).unwrap().push(path_prefix);
+        return self;
+    }
+
+    ///Skips the rate limits for every path that starts with the given prefix, e.g. for endpoints that charge the limits of the
+    ///project themselves
+    pub fn exclude(mut self, path_prefix: &'static str) -> RateLimiting {
+        Rc::get_mut(&mut self.excluded).unwrap().push(path_prefix);
+        return self;
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(RateLimitingMiddleware { service: Rc::new(service), routes: self.routes.clone(), excluded: self.excluded.clone() }));
+    }
+}
+
+pub struct RateLimitingMiddleware<S> {
+    service: Rc<S>,
+    routes: Rc<Vec<&'static str>>,
+    excluded: Rc<Vec<&'static str>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        //Matched against the decoded path, so percent-encoding a character of the prefix doesn't skip the limits
+        let path = req.match_info().as_str();
+        let is_limited = self.routes.iter().any(|prefix| path.starts_with(prefix)) && !self.excluded.iter().any(|prefix| path.starts_with(prefix));
+        let project_id = req.extensions().get::<AuthenticatedClient>().map(|client| client.project_id);
+
+        return Box::pin(async move {
+            let project_id = match project_id {
+                Some(project_id) if is_limited => project_id,
+                _ => return service.call(req).await.map(ServiceResponse::map_into_left_body),
+            };
+
+            let limiter = req.app_data::<web::Data<RateLimiter>>().expect("The rate limiter has not been registered as app data").clone();
+
+            let (status, _slot) = match limiter.check(project_id).await {
+                RateLimitDecision::Allowed(status, slot) => (status, slot),
+                RateLimitDecision::Rejected(status, err) => {
+                    let mut response = err.error_response();
+                    status.apply(response.headers_mut());
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+            };
+
+            //The scan slot is held until the handler returned its response
+            let mut response = service.call(req).await?;
+
+            if let Some(status) = status {
+                status.apply(response.headers_mut());
+            }
+
+            return Ok(response.map_into_left_body());
+        });
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Mutex, time::{Instant, SystemTime, UNIX_EPOCH}};
+use futures::future::BoxFuture;
+use redis::{aio::ConnectionManager, Script};
+
+///Seconds a scan slot taken in Redis is kept at most, so slots of an instance that died while scanning are freed again
+const REDIS_SLOT_TTL_SECS: u64 = 300;
+
+///Limits of a single token bucket
+#[derive(Clone, Copy)]
+pub struct BucketLimit {
+    ///Tokens that are added to the bucket per second
+    pub requests_per_second: f64,
+    ///Tokens the bucket holds when it is full
+    pub burst: u32,
+}
+
+///State of a token bucket after a request tried to take a token from it
+pub struct BucketState {
+    ///True if the bucket had a token left for the request
+    pub allowed: bool,
+    ///Tokens left in the bucket, including fractions of a token that are still being refilled
+    pub remaining: f64,
+}
+
+///Keeps the counters of our rate limits. Implementations have to be safe to use from every worker at once.
+pub trait RateLimitStore: Send + Sync {
+    ///Takes a token from the bucket with the given key, if it has one left
+    fn take_token<'a>(&'a self, key: &'a str, limit: BucketLimit) -> BoxFuture<'a, Result<BucketState, String>>;
+
+    ///Takes one of the slots with the given key, if less than max of them are taken
+    ///
+    /// # Returns
+    /// Result<bool, String> - False if every slot is taken
+    fn acquire_slot<'a>(&'a self, key: &'a str, max: u32) -> BoxFuture<'a, Result<bool, String>>;
+
+    ///Frees a slot that has been taken with acquire_slot
+    fn release_slot<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>>;
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+///Keeps the counters in the memory of this instance, so every instance limits on its own
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    slots: Mutex<HashMap<String, u32>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        return MemoryStore { buckets: Mutex::new(HashMap::new()), slots: Mutex::new(HashMap::new()) };
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn take_token<'a>(&'a self, key: &'a str, limit: BucketLimit) -> BoxFuture<'a, Result<BucketState, String>> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: limit.burst as f64, updated_at: now });
+
+        let refilled = now.duration_since(bucket.updated_at).as_secs_f64() * limit.requests_per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(limit.burst as f64);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let state = BucketState { allowed, remaining: bucket.tokens };
+        return Box::pin(async move { Ok(state) });
+    }
+
+    fn acquire_slot<'a>(&'a self, key: &'a str, max: u32) -> BoxFuture<'a, Result<bool, String>> {
+        let mut slots = self.slots.lock().unwrap();
+        let taken = slots.entry(key.to_string()).or_insert(0);
+
+        let acquired = *taken < max;
+        if acquired {
+            *taken += 1;
+        }
+
+        return Box::pin(async move { Ok(acquired) });
+    }
+
+    fn release_slot<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        let mut slots = self.slots.lock().unwrap();
+
+        if let Some(taken) = slots.get_mut(key) {
+            *taken = taken.saturating_sub(1);
+
+            if *taken == 0 {
+                slots.remove(key);
+            }
+        }
+
+        return Box::pin(async move { Ok(()) });
+    }
+}
+
+///Keeps the counters in Redis, so every instance of the API shares the same limits
+pub struct RedisStore {
+    connection: ConnectionManager,
+    take_token_script: Script,
+    acquire_slot_script: Script,
+    release_slot_script: Script,
+}
+
+impl RedisStore {
+    ///Connects to the Redis server at the given URL
+    pub async fn new(url: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|err| format!("{} is not a valid Redis URL: {}", url, err))?;
+        let connection = ConnectionManager::new(client).await.map_err(|err| format!("Could not connect to Redis at {}: {}", url, err))?;
+
+        //Refills the bucket for the time passed since it was last used and takes a token if there is one.
+        //Buckets expire once they would be full again, as a full bucket is the same as a missing one.
+        let take_token_script = Script::new(r"
+            local rate = tonumber(ARGV[1])
+            local burst = tonumber(ARGV[2])
+            local now = tonumber(ARGV[3])
+            local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+            local tokens = tonumber(state[1]) or burst
+            local updated_at = tonumber(state[2]) or now
+            tokens = math.min(burst, tokens + math.max(0, now - updated_at) / 1000 * rate)
+            local allowed = 0
+            if tokens >= 1 then
+                tokens = tokens - 1
+                allowed = 1
+            end
+            redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', tostring(now))
+            redis.call('PEXPIRE', KEYS[1], math.ceil((burst - tokens) / rate * 1000) + 1000)
+            return {allowed, tostring(tokens)}
+        ");
+
+        let acquire_slot_script = Script::new(r"
+            local taken = redis.call('INCR', KEYS[1])
+            redis.call('EXPIRE', KEYS[1], ARGV[2])
+            if taken > tonumber(ARGV[1]) then
+                redis.call('DECR', KEYS[1])
+                return 0
+            end
+            return 1
+        ");
+
+        let release_slot_script = Script::new(r"
+            if redis.call('DECR', KEYS[1]) <= 0 then
+                redis.call('DEL', KEYS[1])
+            end
+            return 1
+        ");
+
+        return Ok(RedisStore { connection, take_token_script, acquire_slot_script, release_slot_script });
+    }
+}
+
+impl RateLimitStore for RedisStore {
+    fn take_token<'a>(&'a self, key: &'a str, limit: BucketLimit) -> BoxFuture<'a, Result<BucketState, String>> {
+        return Box::pin(async move {
+            //Every instance uses its own clock, so they have to be kept in sync e.g. with NTP
+            let now_millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+            let (allowed, remaining): (u8, String) = self.take_token_script
+                .key(format!("pamaxie:rate_limit:bucket:{}", key))
+                .arg(limit.requests_per_second)
+                .arg(limit.burst)
+                .arg(now_millis)
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(BucketState { allowed: allowed == 1, remaining: remaining.parse().unwrap_or(0.0) });
+        });
+    }
+
+    fn acquire_slot<'a>(&'a self, key: &'a str, max: u32) -> BoxFuture<'a, Result<bool, String>> {
+        return Box::pin(async move {
+            let acquired: u8 = self.acquire_slot_script
+                .key(format!("pamaxie:rate_limit:slots:{}", key))
+                .arg(max)
+                .arg(REDIS_SLOT_TTL_SECS)
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(acquired == 1);
+        });
+    }
+
+    fn release_slot<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let _: u8 = self.release_slot_script
+                .key(format!("pamaxie:rate_limit:slots:{}", key))
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(());
+        });
+    }
+}
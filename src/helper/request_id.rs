@@ -0,0 +1,79 @@
+use std::{future::{ready, Ready}, rc::Rc};
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::header::{HeaderName, HeaderValue}, Error};
+use futures::future::LocalBoxFuture;
+use rand::Rng;
+
+///Header the ID of a request is read from and returned in
+const REQUEST_ID_HEADER: &str = "x-request-id";
+///Longest request ID we accept from a client or a load balancer in front of us
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+///Returns the ID of the request that is currently being processed, if there is one
+pub fn current() -> Option<String> {
+    return REQUEST_ID.try_with(|request_id| request_id.to_string()).ok();
+}
+
+///Returns the request ID sent with the request, if it is one we can safely log and return, or a new random one
+fn get_or_generate(req: &ServiceRequest) -> String {
+    let sent = req.headers().get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN && value.bytes().all(|byte| byte.is_ascii_graphic()));
+
+    return match sent {
+        Some(request_id) => request_id.to_string(),
+        None => hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+    };
+}
+
+///Middleware that gives every request an ID. The ID is returned in the X-Request-Id header and can be read while the request is
+///processed with `request_id::current()`. Has to be wrapped outside of every other middleware, so their responses carry the ID too.
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(RequestIdMiddleware { service: Rc::new(service) }));
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let request_id = get_or_generate(&req);
+
+        return Box::pin(async move {
+            let mut response = REQUEST_ID.scope(request_id.to_string(), service.call(req)).await?;
+
+            //Only contains visible ASCII characters, so it is always a valid header value
+            response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), HeaderValue::from_str(&request_id).unwrap());
+            return Ok(response);
+        });
+    }
+}
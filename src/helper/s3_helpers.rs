@@ -1,9 +1,7 @@
 use actix_web::web::Bytes;
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
-use crate::helper::misc::get_env_variable;
-
-use super::web_helper::get_pam_url;
+use crate::config::{Config, S3Config};
 
 ///Stores the S3 connection information
 struct Storage {
@@ -12,24 +10,21 @@ struct Storage {
     bucket: String
 }
 
-pub const S3_ACCESS_KEY_ENV: &str = "S3_ACCESS_KEY_ID";
-pub const S3_ACCESS_KEY_SECRET_ENV: &str = "S3_ACCESS_KEY_SECRET";
-
-///Returns the S3 Access Key ID
-pub(crate) fn get_s3_access_key() -> String { return get_env_variable(S3_ACCESS_KEY_ENV.to_string(), "".to_string()); }
-
-///Returns the S3 Secret Access Key
-pub(crate) fn get_s3_secret_key() -> String { return get_env_variable(S3_ACCESS_KEY_SECRET_ENV.to_string(), "".to_string()); }
-
-///Returns the S3 Storage Bucket
-pub(crate) fn get_s3_bucket() -> String { return get_env_variable("S3_BUCKET_NAME".to_string(), "production".to_string()); }
-
-///Returns the S3 Region
-pub(crate) fn get_s3_region() -> String { return get_env_variable("S3_STORAGE_REGION".to_string(), "".to_string()); }
-
-///Returns the S3 Storage URL
-pub(crate) fn get_s3_url() -> String {
-    return get_env_variable("S3_URL".to_string(), "".to_string());
+///Returns the S3 connection information from our configuration
+fn get_storage(config: &S3Config) -> Storage {
+    return Storage {
+        region: Region::Custom {
+            region: config.region.to_string(),
+            endpoint: config.url.to_string()
+        },
+        credentials: Credentials {
+            access_key: Some(config.access_key_id.to_string()),
+            secret_key: Some(config.secret_access_key.get()),
+            security_token: None,
+            session_token: None,
+        },
+        bucket: config.bucket.to_string()
+    };
 }
 
 ///Stores a piece of data in the S3 Storage bucket, and returns the URL to the data
@@ -49,24 +44,16 @@ pub(crate) fn get_s3_url() -> String {
 /// let content_type = "text/plain";
 /// store_s3_data(data, data_extension, content_type).await;
 /// ```
-pub async fn store_s3(data: &Bytes, data_hash: &String, data_extension: &String, content_type: &String) -> Option<String> {
-    let credentials = Credentials::from_env_specific(Some(S3_ACCESS_KEY_ENV), Some(S3_ACCESS_KEY_SECRET_ENV), None, None);
-    let bucket = Storage {
-        region: Region::Custom {
-            region: get_s3_region(),
-            endpoint: get_s3_url()
-        },
-        credentials: credentials.unwrap(),
-        bucket: get_s3_bucket()
-    };
+pub async fn store_s3(config: &Config, data: &Bytes, data_hash: &str, data_extension: &str, content_type: &str) -> Option<String> {
+    let bucket = get_storage(&config.s3);
 
     let path = format!("{}.{}", data_hash, data_extension);
 
     //Store our data in the current bucket
-    for backend in vec![bucket] {
+    for backend in [bucket] {
         // Create Bucket in REGION for BUCKET
         let bucket = Bucket::new_with_path_style(&backend.bucket, backend.region, backend.credentials).unwrap();
-        let store_data = bucket.put_object_with_content_type(&path, &data, &content_type).await;
+        let store_data = bucket.put_object_with_content_type(&path, data, content_type).await;
 
         if store_data.is_err(){
             eprintln!("Error while attempting S3 Storage operation (deletion)");
@@ -75,7 +62,7 @@ pub async fn store_s3(data: &Bytes, data_hash: &String, data_extension: &String,
 
         if store_data.unwrap().1 == 200 {
             //We post the url where our work result will be able to be retrieved by our scan clients.
-            return Some(format!("{}/scan/v1/worker/get_image/{}", get_pam_url(), path));
+            return Some(format!("{}/scan/v1/worker/get_image/{}", config.base_url, path));
         }
     }
 
@@ -97,21 +84,13 @@ pub async fn store_s3(data: &Bytes, data_hash: &String, data_extension: &String,
 /// let content_type = "text/plain";
 /// store_s3_data(data, data_extension, content_type).await;
 /// ```
-pub async fn remove_s3(data_hash: &String, data_extension: &String) -> Result<(), String> {
-    let credentials = Credentials::from_env_specific(Some(S3_ACCESS_KEY_ENV), Some(S3_ACCESS_KEY_SECRET_ENV), None, None);
-    let bucket = Storage {
-        region: Region::Custom {
-            region: get_s3_region(),
-            endpoint: get_s3_url()
-        },
-        credentials: credentials.unwrap(),
-        bucket: get_s3_bucket()
-    };
+pub async fn remove_s3(config: &Config, data_hash: &str, data_extension: &str) -> Result<(), String> {
+    let bucket = get_storage(&config.s3);
 
     let deletion_obj = format!("{}.{}", data_hash, data_extension);
 
     //Store our data in the current bucket
-    for backend in vec![bucket] {
+    for backend in [bucket] {
         // Create Bucket in REGION for BUCKET
         let bucket = Bucket::new_with_path_style(&backend.bucket, backend.region, backend.credentials).unwrap();
 
@@ -153,22 +132,14 @@ pub async fn remove_s3(data_hash: &String, data_extension: &String) -> Result<()
 /// let content_type = "text/plain";
 /// store_s3_data(data, data_extension, content_type).await;
 /// ```
-pub async fn get_s3_item(item_name: &String) -> Option<Bytes> {
-    let credentials = Credentials::from_env_specific(Some(S3_ACCESS_KEY_ENV), Some(S3_ACCESS_KEY_SECRET_ENV), None, None);
-    let bucket = Storage {
-        region: Region::Custom {
-            region: get_s3_region(),
-            endpoint: get_s3_url()
-        },
-        credentials: credentials.unwrap(),
-        bucket: get_s3_bucket()
-    };
+pub async fn get_s3_item(config: &Config, item_name: &str) -> Option<Bytes> {
+    let bucket = get_storage(&config.s3);
 
     //Store our data in the current bucket
-    for backend in vec![bucket] {
+    for backend in [bucket] {
         // Create Bucket in REGION for BUCKET
         let bucket = Bucket::new_with_path_style(&backend.bucket, backend.region, backend.credentials).unwrap();
-        let delete_action = bucket.get_object(&item_name).await;
+        let delete_action = bucket.get_object(item_name).await;
 
         if delete_action.is_err() {
             eprintln!("Error while attempting S3 Storage operation (deletion)");
@@ -188,4 +159,22 @@ pub async fn get_s3_item(item_name: &String) -> Option<Bytes> {
     }
 
    return None;
-}
\ No newline at end of file
+}
+
+///Checks if we can access the configured S3 Storage bucket by listing a single item of it
+/// # Arguments
+/// config: &Config - The configuration of the API
+/// 
+/// # Returns
+/// Result<(), String> - An error message describing why the bucket can not be accessed
+pub async fn check_s3_access(config: &Config) -> Result<(), String> {
+    let backend = get_storage(&config.s3);
+    let bucket = Bucket::new_with_path_style(&backend.bucket, backend.region, backend.credentials)
+        .map_err(|err| format!("Could not create the bucket client: {}", err))?;
+
+    return match bucket.list_page("".to_string(), None, None, None, Some(1)).await {
+        Ok((_, 200)) => Ok(()),
+        Ok((_, status)) => Err(format!("Listing the bucket returned the status code {}", status)),
+        Err(err) => Err(format!("Could not list the bucket: {}", err)),
+    };
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use super::request_id;
+
+///Seconds a client is asked to wait before retrying a scan that did not finish in time
+const SCAN_TIMEOUT_RETRY_AFTER_SECS: u64 = 60;
+
+///Errors our API responds with. Each of them has a stable, machine readable code our clients can branch on.
+#[derive(Debug)]
+pub enum ScanError {
+    ///The request did not contain any data
+    MissingBody,
+    ///The request is malformed, e.g. a parameter has an invalid value
+    InvalidRequest(String),
+    ///The request body is larger than we accept
+    PayloadTooLarge(String),
+    ///The data has a type we can't scan
+    UnsupportedMediaType(String),
+    ///The data claims to be an image, but can't be processed as one
+    InvalidImage(String),
+    ///The image declares dimensions, or needs memory to be decoded, beyond our limits
+    ImageTooLarge(String),
+    ///The image at the URL the client sent us could not be downloaded
+    UrlFetchFailed(String),
+    ///The URL the client sent us points to a host or address we don't download from
+    UrlNotAllowed(String),
+    ///The job the client asked for does not exist, has been forgotten or belongs to another project
+    JobNotFound,
+    ///The batch job the client asked for does not exist, has been forgotten or belongs to another project
+    BatchNotFound,
+    ///The webhook delivery the client asked for does not exist or belongs to another project
+    DeliveryNotFound,
+    ///The hash the client asked for is not on the list
+    ListEntryNotFound,
+    ///The client did not send a valid bearer token
+    Unauthorized(String),
+    ///The client is not allowed to access the route
+    Forbidden(String),
+    ///The project sent more requests than its rate limit allows
+    RateLimited { retry_after: u64 },
+    ///The project has more scans in flight than it is allowed to
+    TooManyScans { retry_after: u64 },
+    ///The project exhausted one of its monthly quotas
+    QuotaExceeded { message: String, retry_after: u64 },
+    ///The scan did not finish in time. Its result can be requested again later.
+    ScanTimeout,
+    ///Our database API could not be reached to authenticate the client
+    AuthUnavailable,
+    ///Our database API could not be reached or failed
+    DatabaseUnavailable(String),
+    ///Our S3 storage could not be reached or failed
+    StorageUnavailable(String),
+    ///Our work queue could not be reached or failed
+    QueueUnavailable(String),
+}
+
+///JSON body of our error responses
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    ///ID of the failed request, which our support can use to find it in our logs
+    request_id: Option<String>,
+}
+
+///Code and message of an error that is reported as part of a successful response, e.g. for a single item of a batch
+#[derive(Serialize, Clone)]
+pub struct ErrorDetail {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl From<&ScanError> for ErrorDetail {
+    fn from(err: &ScanError) -> Self {
+        return ErrorDetail { code: err.code(), message: err.to_string() };
+    }
+}
+
+impl ScanError {
+    ///Returns the machine readable code of the error. Codes are part of our API and must never change.
+    pub fn code(&self) -> &'static str {
+        return match self {
+            ScanError::MissingBody => "missing_body",
+            ScanError::InvalidRequest(_) => "invalid_request",
+            ScanError::PayloadTooLarge(_) => "payload_too_large",
+            ScanError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ScanError::InvalidImage(_) => "invalid_image",
+            ScanError::ImageTooLarge(_) => "image_too_large",
+            ScanError::UrlFetchFailed(_) => "url_fetch_failed",
+            ScanError::UrlNotAllowed(_) => "url_not_allowed",
+            ScanError::JobNotFound => "job_not_found",
+            ScanError::BatchNotFound => "batch_not_found",
+            ScanError::DeliveryNotFound => "delivery_not_found",
+            ScanError::ListEntryNotFound => "list_entry_not_found",
+            ScanError::Unauthorized(_) => "unauthorized",
+            ScanError::Forbidden(_) => "forbidden",
+            ScanError::RateLimited { .. } => "rate_limited",
+            ScanError::TooManyScans { .. } => "too_many_scans",
+            ScanError::QuotaExceeded { .. } => "quota_exceeded",
+            ScanError::ScanTimeout => "scan_timeout",
+            ScanError::AuthUnavailable => "auth_unavailable",
+            ScanError::DatabaseUnavailable(_) => "database_unavailable",
+            ScanError::StorageUnavailable(_) => "storage_unavailable",
+            ScanError::QueueUnavailable(_) => "queue_unavailable",
+        };
+    }
+
+    ///Returns the seconds the client should wait before retrying, if retrying later can succeed
+    fn retry_after(&self) -> Option<u64> {
+        return match self {
+            ScanError::RateLimited { retry_after } | ScanError::TooManyScans { retry_after } | ScanError::QuotaExceeded { retry_after, .. } => Some(*retry_after),
+            ScanError::ScanTimeout => Some(SCAN_TIMEOUT_RETRY_AFTER_SECS),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ScanError::MissingBody => write!(f, "No data has been sent with the request"),
+            ScanError::InvalidRequest(message) => write!(f, "{}", message),
+            ScanError::PayloadTooLarge(message) => write!(f, "{}", message),
+            ScanError::UnsupportedMediaType(message) => write!(f, "{}", message),
+            ScanError::InvalidImage(message) => write!(f, "{}", message),
+            ScanError::ImageTooLarge(message) => write!(f, "{}", message),
+            ScanError::UrlFetchFailed(message) => write!(f, "{}", message),
+            ScanError::UrlNotAllowed(message) => write!(f, "{}", message),
+            ScanError::JobNotFound => write!(f, "We could not find a scan job with this ID for your project. Jobs are only kept for an hour."),
+            ScanError::BatchNotFound => write!(f, "We could not find a batch job with this ID for your project. Batch jobs are only kept for an hour."),
+            ScanError::DeliveryNotFound => write!(f, "We could not find a webhook delivery with this ID for your project."),
+            ScanError::ListEntryNotFound => write!(f, "The hash is not on this list."),
+            ScanError::Unauthorized(message) => write!(f, "{}", message),
+            ScanError::Forbidden(message) => write!(f, "{}", message),
+            ScanError::RateLimited { .. } => write!(f, "You have sent too many requests. Please slow down and try again later."),
+            ScanError::TooManyScans { .. } => write!(f, "You have too many scans in progress. Please wait for them to finish and try again."),
+            ScanError::QuotaExceeded { message, .. } => write!(f, "{}", message),
+            ScanError::ScanTimeout => write!(f, "We could not process your data in a timely manner. Please try again later."),
+            ScanError::AuthUnavailable => write!(f, "We could not reach our authentication service. Please try again later."),
+            ScanError::DatabaseUnavailable(message) => write!(f, "{}", message),
+            ScanError::StorageUnavailable(message) => write!(f, "{}", message),
+            ScanError::QueueUnavailable(message) => write!(f, "{}", message),
+        };
+    }
+}
+
+impl ResponseError for ScanError {
+    fn status_code(&self) -> StatusCode {
+        return match self {
+            ScanError::MissingBody | ScanError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ScanError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ScanError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ScanError::InvalidImage(_) | ScanError::ImageTooLarge(_) | ScanError::UrlFetchFailed(_) | ScanError::UrlNotAllowed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ScanError::JobNotFound | ScanError::BatchNotFound | ScanError::DeliveryNotFound | ScanError::ListEntryNotFound => StatusCode::NOT_FOUND,
+            ScanError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ScanError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ScanError::RateLimited { .. } | ScanError::TooManyScans { .. } | ScanError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ScanError::ScanTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ScanError::AuthUnavailable | ScanError::DatabaseUnavailable(_) | ScanError::StorageUnavailable(_) | ScanError::QueueUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let Some(retry_after) = self.retry_after() {
+            response.insert_header(("Retry-After", retry_after));
+        }
+
+        return response.json(ErrorBody { code: self.code(), message: self.to_string(), request_id: request_id::current() });
+    }
+}
@@ -0,0 +1,122 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+use serde::Serialize;
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use super::ttl_map::TtlMap;
+
+///Events that are buffered for a subscriber that reads slower than they are published
+const CHANNEL_CAPACITY: usize = 16;
+///Time the state of a scan is remembered after its last transition
+const STATE_RETENTION: Duration = Duration::from_secs(3600);
+///Most scans whose state is remembered at once
+const MAX_SCANS: usize = 100000;
+
+///State of a scan, as far as this instance has seen it
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanState {
+    ///The data has been added to our work queue
+    Queued,
+    ///A worker took the data from our work queue
+    Leased,
+    ///The worker downloaded the data and is scanning it
+    Scanning,
+    ///A worker posted the result of the scan
+    Done,
+    ///No result arrived in time
+    Failed,
+}
+
+impl ScanState {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            ScanState::Queued => "queued",
+            ScanState::Leased => "leased",
+            ScanState::Scanning => "scanning",
+            ScanState::Done => "done",
+            ScanState::Failed => "failed",
+        };
+    }
+}
+
+///A transition of the state of a scan
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanEvent {
+    ///Hash of the scanned data, which is the ID of its scan job
+    pub hash: String,
+    pub state: ScanState,
+    ///Unix timestamp of the transition in seconds
+    pub at: i64,
+    ///Result of the scan, once it is done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+}
+
+impl ScanEvent {
+    pub fn new(hash: &str, state: ScanState, result: Option<Value>) -> ScanEvent {
+        return ScanEvent { hash: hash.to_string(), state, at: OffsetDateTime::now_utc().unix_timestamp(), result };
+    }
+
+    ///Returns true if no further event follows this one
+    pub fn is_final(&self) -> bool {
+        return self.state == ScanState::Done || self.state == ScanState::Failed;
+    }
+}
+
+struct Channel {
+    sender: broadcast::Sender<ScanEvent>,
+    last_event: Option<ScanEvent>,
+    updated_at: Instant,
+}
+
+///Returns the channel of a scan, creating it if it doesn't exist yet
+fn get_channel<'a>(channels: &'a mut TtlMap<String, Channel>, hash: &str) -> &'a mut Channel {
+    return channels.get_or_insert_with(hash.to_string(), STATE_RETENTION, || Channel {
+        sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        last_event: None,
+        updated_at: Instant::now(),
+    });
+}
+
+///Publishes the state transitions of our scans to everyone who subscribed to them. Only transitions that happened on this
+///instance are published.
+pub struct ScanEvents {
+    channels: Mutex<TtlMap<String, Channel>>,
+}
+
+impl ScanEvents {
+    pub fn new() -> ScanEvents {
+        return ScanEvents {
+            //Scans that somebody still listens to are remembered past their retention, unless we run out of room
+            channels: Mutex::new(TtlMap::with_keep_expired(MAX_SCANS, |channel| channel.sender.receiver_count() > 0)),
+        };
+    }
+
+    ///Publishes a state transition of the scan of the data with the given hash
+    pub fn publish(&self, event: ScanEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        channels.set_ttl(&event.hash, STATE_RETENTION);
+        let channel = get_channel(&mut channels, &event.hash);
+
+        channel.updated_at = Instant::now();
+        channel.last_event = Some(event.clone());
+
+        //Fails if nobody subscribed, which is fine
+        let _ = channel.sender.send(event);
+    }
+
+    ///Subscribes to the state transitions of the scan of the data with the given hash
+    ///
+    /// # Returns
+    /// (Option<ScanEvent>, broadcast::Receiver<ScanEvent>) - The last transition that has been published, if it is still
+    /// remembered, and the receiver of every transition that follows it
+    pub fn subscribe(&self, hash: &str) -> (Option<ScanEvent>, broadcast::Receiver<ScanEvent>) {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = get_channel(&mut channels, hash);
+
+        let last_event = channel.last_event.clone().filter(|_| channel.updated_at.elapsed() < STATE_RETENTION);
+        return (last_event, channel.sender.subscribe());
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fmt, fs, path::{Path, PathBuf}, sync::{Arc, RwLock}, time::Duration};
+use serde::{Deserialize, Deserializer};
+use tokio::time::sleep;
+
+///A secret configuration value. It can be read from a file, in which case it is reloaded whenever the file changes.
+///Clones share the same value, so every part of the API sees a rotated secret. The value is never printed.
+#[derive(Clone, Default)]
+pub struct Secret {
+    value: Arc<RwLock<String>>,
+    file: Option<PathBuf>,
+}
+
+impl Secret {
+    ///Creates a secret with a fixed value
+    pub fn new(value: String) -> Secret {
+        return Secret { value: Arc::new(RwLock::new(value)), file: None };
+    }
+
+    ///Creates a secret from the contents of a file, like the ones mounted by Docker or Kubernetes
+    ///
+    /// # Arguments
+    /// path: &Path - The file that contains the secret
+    ///
+    /// # Returns
+    /// Result<Secret, String> - The secret or a description of why the file could not be read
+    pub fn from_file(path: &Path) -> Result<Secret, String> {
+        let value = read_secret_file(path)?;
+        return Ok(Secret { value: Arc::new(RwLock::new(value)), file: Some(path.to_path_buf()) });
+    }
+
+    ///Returns the current value of the secret
+    pub fn get(&self) -> String {
+        return self.value.read().unwrap().to_string();
+    }
+
+    ///Rereads the secret from its file
+    ///
+    /// # Returns
+    /// Result<bool, String> - True if the value changed, or a description of why the file could not be read
+    fn reload(&self) -> Result<bool, String> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+
+        let value = read_secret_file(path)?;
+        let mut current = self.value.write().unwrap();
+
+        if *current == value {
+            return Ok(false);
+        }
+
+        *current = value;
+        return Ok(true);
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match &self.file {
+            Some(path) => write!(f, "Secret(file: {})", path.display()),
+            None => write!(f, "Secret(***)"),
+        };
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        return String::deserialize(deserializer).map(Secret::new);
+    }
+}
+
+///Reads a secret file, without the trailing line break most editors and tools add
+fn read_secret_file(path: &Path) -> Result<String, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read the secret file {}: {}", path.display(), err))?;
+
+    return Ok(contents.trim_end_matches(['\r', '\n']).to_string());
+}
+
+///Periodically rereads every secret that was loaded from a file and swaps in the new value when it changed.
+///This is polling based, since secret mounts are usually updated by swapping symlinks which file watchers don't report reliably.
+///
+/// # Arguments
+/// secrets: Vec<(&'static str, Secret)> - The name and value of every secret to watch
+/// interval: Duration - The time to wait between checks
+pub async fn watch_secrets(secrets: Vec<(&'static str, Secret)>, interval: Duration) {
+    let secrets: Vec<(&'static str, Secret)> = secrets.into_iter().filter(|(_, secret)| secret.file.is_some()).collect();
+
+    if secrets.is_empty() {
+        return;
+    }
+
+    loop {
+        sleep(interval).await;
+
+        for (name, secret) in &secrets {
+            match secret.reload() {
+                Ok(true) => eprintln!("The secret {} changed on disk and has been reloaded.", name),
+                Ok(false) => {},
+                Err(err) => eprintln!("Could not reload the secret {}, keeping the previous value. {}", name, err),
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::{path::Path, sync::{Arc, Mutex}};
+use actix_web::web;
+use rusqlite::{Connection, OpenFlags};
+
+///Opens a SQLite database of this instance in WAL mode and creates its tables, if they don't exist yet
+///
+/// # Arguments
+/// path: &Path - The path of the database file
+/// name: &str - What the database holds, e.g. "usage database", for our error messages
+/// schema: &str - The statements that create the tables of the database
+///
+/// # Returns
+/// Result<Connection, String> - The connection or why the database could not be opened
+pub fn open(path: &Path, name: &str, schema: &str) -> Result<Connection, String> {
+    let connection = Connection::open(path).map_err(|err| format!("Could not open the {} {}: {}", name, path.display(), err))?;
+
+    connection.execute_batch(&format!("
+        PRAGMA journal_mode = WAL;
+        PRAGMA synchronous = NORMAL;
+        {}
+    ", schema)).map_err(|err| format!("Could not create the tables of the {} {}: {}", name, path.display(), err))?;
+
+    return Ok(connection);
+}
+
+///Checks that a SQLite database of this instance can be used, without creating or changing it. An existing database is opened
+///read-only and has to be readable, a missing one needs a writable directory to be created in when we start.
+///
+/// # Arguments
+/// path: &Path - The path of the database file
+/// name: &str - What the database holds, e.g. "usage database", for our error messages
+///
+/// # Returns
+/// Result<(), String> - Why the database can't be used, if it can't
+pub fn check(path: &Path, name: &str) -> Result<(), String> {
+    if path.exists() {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .map_err(|err| format!("Could not open the {} {}: {}", name, path.display(), err))?;
+
+        return connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
+            .map_err(|err| format!("Could not read the {} {}: {}", name, path.display(), err));
+    }
+
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let writable = directory.metadata().map(|metadata| metadata.is_dir() && !metadata.permissions().readonly()).unwrap_or(false);
+
+    if !writable {
+        return Err(format!("The {} {} does not exist and its directory {} is not writable", name, path.display(), directory.display()));
+    }
+
+    return Ok(());
+}
+
+///A SQLite database of this instance. Its queries run on the blocking thread pool, so their file I/O doesn't stall the workers
+///that serve our requests.
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn new(connection: Connection) -> Database {
+        return Database { connection: Arc::new(Mutex::new(connection)) };
+    }
+
+    ///Runs a query on the blocking thread pool. Queries run one at a time, as they share a single connection.
+    ///
+    /// # Arguments
+    /// query: impl FnOnce(&mut Connection) -> rusqlite::Result<T> - The query, which takes everything it needs by value
+    ///
+    /// # Returns
+    /// Result<T, String> - The result of the query or why it failed
+    pub async fn run<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        return web::block(move || query(&mut connection.lock().unwrap())).await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string());
+    }
+}
@@ -1,24 +1,23 @@
-use aws_sdk_sqs::{self, Client, Error};
-use super::misc::get_env_variable;
+use aws_sdk_sqs::{self, Client, Credentials, Error, Region};
+use aws_sdk_sqs::model::QueueAttributeName;
+use crate::config::SqsConfig;
 
-///Returns the pamaxie API URL from the environment variable
-pub fn get_aws_access_key() -> String {
-    return get_env_variable("AWS_ACCESS_KEY_ID".to_string(), "".to_string());
-}
-
-///Returns the pamaxie API URL from the environment variable
-pub fn get_aws_secret_access_key() -> String {
-    return get_env_variable("AWS_SECRET_ACCESS_KEY".to_string(), "".to_string());
-}
-
-///Returns the pamaxie API URL from the environment variable
-pub fn get_aws_default_region() -> String {
-    return get_env_variable("AWS_DEFAULT_REGION".to_string(), "us-east-1".to_string());
-}
-
-///Returns the SQS Queue URL from the environment variable
-pub fn get_aws_sqs_queue_url() -> String {
-    return get_env_variable("AWS_SQS_QUEUE_URL_0".to_string(), "".to_string());
+///Creates a new SQS client from our configuration
+/// 
+/// # Arguments
+/// config: &SqsConfig - The SQS configuration
+/// 
+/// # Returns
+/// Client - The SQS client
+pub async fn get_client(config: &SqsConfig) -> Client {
+    let credentials = Credentials::new(&config.access_key_id, config.secret_access_key.get(), None, None, "pamaxie-config");
+    let shared_config = aws_config::from_env()
+        .region(Region::new(config.region.to_string()))
+        .credentials_provider(credentials)
+        .load()
+        .await;
+
+    return Client::new(&shared_config);
 }
 
 ///Posts a message to the SQS queue
@@ -30,7 +29,7 @@ pub fn get_aws_sqs_queue_url() -> String {
 /// 
 /// # Returns
 /// Result<(), Error> - The SQS response
-pub async fn send_message(client: &Client, queue_url: &String, message: &String/*, project_id: &u64*/) -> Result<(), Error> { 
+pub async fn send_message(client: &Client, queue_url: &str, message: &str/*, project_id: &u64*/) -> Result<(), Error> { 
     
     //Put into a customer specific queue. This is disabled for now but will definetly be interesting in the future.
     /*if project_id > 0 {
@@ -64,17 +63,15 @@ pub async fn send_message(client: &Client, queue_url: &String, message: &String/
 /// 
 /// #Returns
 /// String - The message from the SQS queue
-pub async fn get_message(client: &Client, queue_url: &String) -> Result<String, Error> {
+pub async fn get_message(client: &Client, queue_url: &str) -> Result<String, Error> {
 
     
     let rcv_message_output = client.receive_message().queue_url(queue_url).send().await?;
     let mut message_contents: String = "".to_string();
 
     for message in rcv_message_output.messages.unwrap_or_default() {
-        message_contents = message.body().unwrap().to_string();
-
         //Set the message contents to what we wanna return
-        message_contents = String::from(format!("{}", message_contents));
+        message_contents = message.body().unwrap().to_string();
 
         
         //Delete the message from the queue to pass it forward to process the data
@@ -82,4 +79,22 @@ pub async fn get_message(client: &Client, queue_url: &String) -> Result<String,
     }
 
     Ok(message_contents)
-}
\ No newline at end of file
+}
+
+///Checks if the SQS queue exists and we are allowed to access it
+/// 
+/// # Arguments
+/// client: &Client - The SQS client
+/// queue_url: &str - The SQS queue url
+/// 
+/// # Returns
+/// Result<(), Error> - The SQS error if the queue can not be accessed
+pub async fn check_queue(client: &Client, queue_url: &str) -> Result<(), Error> {
+    client.get_queue_attributes()
+    .queue_url(queue_url)
+    .attribute_names(QueueAttributeName::ApproximateNumberOfMessages)
+    .send()
+    .await?;
+
+    Ok(())
+}
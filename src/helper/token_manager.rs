@@ -0,0 +1,205 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use actix_web::web;
+use arc_swap::ArcSwapOption;
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use tokio::{sync::Mutex, time::sleep};
+use crate::config::Config;
+use super::web_helper;
+
+///Seconds before the expiry of our token at which we already get a new one. Tokens that live shorter than twice as long are
+///refreshed halfway through their lifetime instead.
+const REFRESH_AHEAD_SECS: i64 = 60;
+///Time after which we replace a token that doesn't expire
+const REFRESH_INTERVAL_WITHOUT_EXPIRY: Duration = Duration::from_secs(3600);
+///Time we wait before retrying after the first failed refresh. It doubles with every further failure.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+///Longest time we wait before retrying a failed refresh
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+///JWT bearer token we received from the database API
+struct DbApiToken {
+    value: String,
+    ///exp claim of the token, in seconds since the unix epoch, if it has one
+    expires_at: Option<i64>,
+    ///Time we received the token at, in seconds since the unix epoch
+    received_at: i64,
+}
+
+#[derive(Deserialize)]
+struct ExpiryClaims {
+    exp: Option<i64>,
+}
+
+///Keeps the JWT bearer token we use to talk to the database API. Reading the token never waits on a lock. It is refreshed ahead of
+///its expiry, and only one refresh runs at a time while every other caller keeps using the current token.
+pub struct TokenManager {
+    config: Config,
+    http_client: reqwest::Client,
+    token: ArcSwapOption<DbApiToken>,
+    refresh_lock: Mutex<()>,
+    ///False if our last refresh failed and we don't have a token left that we can still use
+    healthy: AtomicBool,
+}
+
+impl TokenManager {
+    pub fn new(config: &Config, http_client: reqwest::Client) -> TokenManager {
+        return TokenManager {
+            config: config.clone(),
+            http_client,
+            token: ArcSwapOption::empty(),
+            refresh_lock: Mutex::new(()),
+            healthy: AtomicBool::new(false),
+        };
+    }
+
+    ///Returns the URL of the given path on the database API
+    pub fn url(&self, path: &str) -> String {
+        return format!("{}{}", self.config.db_api.url, path);
+    }
+
+    ///Returns true if we have a token the database API accepted and it didn't expire yet, so we are ready to serve scans
+    pub fn is_healthy(&self) -> bool {
+        return self.healthy.load(Ordering::Relaxed) && self.token.load().as_ref().is_some_and(|token| !is_expired(token));
+    }
+
+    ///Returns a valid token, getting a new one if we don't have one yet or it is about to expire
+    pub async fn get_token(&self) -> Option<String> {
+        if let Some(token) = self.token.load_full() {
+            if !is_expiring(&token) {
+                return Some(token.value.to_string());
+            }
+        }
+
+        return self.refresh(None).await;
+    }
+
+    ///Gets a new token from the database API. If another caller is already refreshing, we wait for its token instead.
+    ///
+    /// # Arguments
+    /// stale: Option<&str> - A token that has to be replaced even if it didn't expire yet, e.g. because the database API rejected it
+    ///
+    /// # Returns
+    /// Option<String> - The new token, or none if the database API didn't issue one
+    pub async fn refresh(&self, stale: Option<&str>) -> Option<String> {
+        let _refresh_guard = self.refresh_lock.lock().await;
+        let current = self.token.load_full();
+
+        //Someone else refreshed the token while we waited for the lock
+        if let Some(token) = &current {
+            if !is_expiring(token) && stale.is_none_or(|stale| stale != token.value) {
+                return Some(token.value.to_string());
+            }
+        }
+
+        let value = match web_helper::get_pam_token(&self.http_client, &self.config).await {
+            Some(value) => value,
+            None => {
+                //We can keep using the current token until it expires, unless it is the one that has been rejected
+                let usable = current.is_some_and(|token| !is_expired(&token) && stale != Some(token.value.as_str()));
+                self.healthy.store(usable, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        let expires_at = get_expiry(&value);
+        self.token.store(Some(Arc::new(DbApiToken { value: value.to_string(), expires_at, received_at: now_unix() })));
+        self.healthy.store(true, Ordering::Relaxed);
+
+        return Some(value);
+    }
+
+    ///Sends an authenticated request to the database API. If the database API rejects our token, we refresh it once and retry.
+    ///
+    /// # Arguments
+    /// build_request: impl Fn(&reqwest::Client) -> RequestBuilder - Builds the request, without its Authorization header
+    ///
+    /// # Returns
+    /// Result<Response, String> - The response or why the request could not be sent
+    pub async fn send(&self, build_request: impl Fn(&reqwest::Client) -> RequestBuilder) -> Result<Response, String> {
+        let token = self.get_token().await.ok_or("Could not get a token from the database API")?;
+        let response = build_request(&self.http_client).bearer_auth(&token).send().await.map_err(|err| err.to_string())?;
+
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let token = self.refresh(Some(&token)).await.ok_or("Could not get a token from the database API")?;
+        return build_request(&self.http_client).bearer_auth(&token).send().await.map_err(|err| err.to_string());
+    }
+}
+
+fn now_unix() -> i64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+}
+
+fn is_expired(token: &DbApiToken) -> bool {
+    return token.expires_at.is_some_and(|expires_at| expires_at <= now_unix());
+}
+
+fn is_expiring(token: &DbApiToken) -> bool {
+    return get_refresh_at(token).is_some_and(|refresh_at| refresh_at <= now_unix());
+}
+
+///Returns the time a token has to be refreshed at, in seconds since the unix epoch, if it expires. Short-lived tokens are
+///refreshed halfway through their lifetime, so we don't ask the database API for a new one all the time.
+fn get_refresh_at(token: &DbApiToken) -> Option<i64> {
+    let expires_at = token.expires_at?;
+    let refresh_ahead = REFRESH_AHEAD_SECS.min((expires_at - token.received_at).max(0) / 2);
+
+    return Some(expires_at - refresh_ahead);
+}
+
+///Reads the exp claim of a token issued to us by the database API. We don't verify it, the database API verifies it on every request.
+fn get_expiry(token: &str) -> Option<i64> {
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.set_required_spec_claims::<&str>(&[]);
+
+    return decode::<ExpiryClaims>(token, &DecodingKey::from_secret(&[]), &validation).ok()?.claims.exp;
+}
+
+///Keeps the token of a token manager fresh. Gets a new one shortly before the current one expires, or every hour if it doesn't expire.
+///Failed refreshes are retried with exponential backoff and jitter, so a flaky database API doesn't leave us without a token for long.
+pub async fn refresh_periodically(tokens: web::Data<TokenManager>) {
+    let mut failures: u32 = 0;
+
+    loop {
+        let stale = tokens.token.load_full().filter(|token| token.expires_at.is_none()).map(|token| token.value.to_string());
+
+        let delay = match tokens.refresh(stale.as_deref()).await {
+            Some(_) => {
+                failures = 0;
+                next_refresh_in(tokens.token.load().as_ref().and_then(|token| get_refresh_at(token)))
+            }
+            None => {
+                failures = failures.saturating_add(1);
+                let delay = retry_delay(failures);
+                eprintln!("Could not refresh the database API token ({} failed attempts in a row). Retrying in {} ms.", failures, delay.as_millis());
+                delay
+            }
+        };
+
+        sleep(delay).await;
+    }
+}
+
+///Returns the time until a token that has to be refreshed at the given time is refreshed
+fn next_refresh_in(refresh_at: Option<i64>) -> Duration {
+    return match refresh_at {
+        Some(refresh_at) => Duration::from_secs((refresh_at - now_unix()).max(1) as u64),
+        None => REFRESH_INTERVAL_WITHOUT_EXPIRY,
+    };
+}
+
+///Returns the time to wait before retrying after the given number of failed refreshes in a row. The exponential delay is
+///jittered by up to half of it, so our replicas don't all retry at the same moment.
+fn retry_delay(failures: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(failures - 1)).min(RETRY_MAX_DELAY);
+    let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+
+    return delay - Duration::from_millis(jitter);
+}
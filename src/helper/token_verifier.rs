@@ -0,0 +1,167 @@
+use std::{fmt, fs, sync::RwLock, time::Duration};
+use jsonwebtoken::{decode, decode_header, errors::ErrorKind, jwk::JwkSet, DecodingKey, Validation};
+use crate::config::JwtConfig;
+use super::web_helper::PamApiTokenPayload;
+
+///Longest time we wait for the connection to the server of a JWKS document
+const JWKS_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+///Longest time fetching a JWKS document may take in total, so a stalled server can't hang our startup or the refresh of the keys
+const JWKS_TIMEOUT: Duration = Duration::from_secs(15);
+
+///A public key that can verify the signature of a JWT bearer token
+struct VerificationKey {
+    ///Key ID of keys that were loaded from a JWKS document
+    kid: Option<String>,
+    key: DecodingKey,
+}
+
+///Reasons why a JWT bearer token was rejected
+#[derive(Debug)]
+pub enum TokenError {
+    Missing,
+    Malformed,
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    InvalidIssuer,
+    MissingClaim(String),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TokenError::Missing => write!(f, "No bearer token has been sent with the request"),
+            TokenError::Malformed => write!(f, "The bearer token is not a valid JWT"),
+            TokenError::InvalidSignature => write!(f, "The signature of the bearer token could not be verified"),
+            TokenError::Expired => write!(f, "The bearer token has expired"),
+            TokenError::NotYetValid => write!(f, "The bearer token is not valid yet"),
+            TokenError::InvalidIssuer => write!(f, "The bearer token has not been issued by a trusted issuer"),
+            TokenError::MissingClaim(claim) => write!(f, "The bearer token is missing the required claim {}", claim),
+        };
+    }
+}
+
+///Verifies the signature and the validity of JWT bearer tokens sent in by our clients
+pub struct TokenVerifier {
+    config: JwtConfig,
+    ///Keys loaded from PEM files and the JWKS document. The JWKS keys are replaced whenever the document is refreshed.
+    keys: RwLock<Vec<VerificationKey>>,
+}
+
+impl TokenVerifier {
+    ///Creates a verifier and loads every key named in the configuration
+    ///
+    /// # Arguments
+    /// config: &JwtConfig - The JWT configuration
+    ///
+    /// # Returns
+    /// Result<TokenVerifier, String> - The verifier or a description of the key that could not be loaded
+    pub async fn new(config: &JwtConfig) -> Result<TokenVerifier, String> {
+        let verifier = TokenVerifier { config: config.clone(), keys: RwLock::new(Vec::new()) };
+        let keys = verifier.load_keys().await?;
+        *verifier.keys.write().unwrap() = keys;
+
+        return Ok(verifier);
+    }
+
+    ///Reloads the JWKS document, so rotated keys of the issuer are picked up. Keeps the current keys if the document can't be loaded.
+    pub async fn refresh_keys(&self) {
+        if self.config.jwks.is_empty() {
+            return;
+        }
+
+        match self.load_keys().await {
+            Ok(keys) => *self.keys.write().unwrap() = keys,
+            Err(err) => eprintln!("Could not refresh the JWKS document, keeping the previous keys. {}", err),
+        }
+    }
+
+    async fn load_keys(&self) -> Result<Vec<VerificationKey>, String> {
+        let mut keys = Vec::new();
+
+        for path in &self.config.public_key_files {
+            let pem = fs::read(path).map_err(|err| format!("Could not read the public key file {}: {}", path.display(), err))?;
+            let key = DecodingKey::from_rsa_pem(&pem)
+                .or_else(|_| DecodingKey::from_ec_pem(&pem))
+                .map_err(|_| format!("The public key file {} does not contain a PEM encoded RSA or EC public key", path.display()))?;
+
+            keys.push(VerificationKey { kid: None, key });
+        }
+
+        if !self.config.jwks.is_empty() {
+            for jwk in load_jwks(&self.config.jwks).await?.keys {
+                let key = DecodingKey::from_jwk(&jwk)
+                    .map_err(|err| format!("The JWKS document {} contains an invalid key: {}", self.config.jwks, err))?;
+
+                keys.push(VerificationKey { kid: jwk.common.key_id, key });
+            }
+        }
+
+        return Ok(keys);
+    }
+
+    ///Verifies the signature of a token and validates its exp and iss claims, which are required, and its nbf claim if it has one
+    ///
+    /// # Arguments
+    /// token: &str - The encoded JWT bearer token
+    ///
+    /// # Returns
+    /// Result<PamApiTokenPayload, TokenError> - The verified payload of the token or the reason it was rejected
+    pub fn verify(&self, token: &str) -> Result<PamApiTokenPayload, TokenError> {
+        let header = decode_header(token).map_err(|_| TokenError::Malformed)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.config.leeway;
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss"]);
+
+        let hmac_secret = self.config.hmac_secret.get();
+        let hmac_key = if hmac_secret.is_empty() { None } else { Some(DecodingKey::from_secret(hmac_secret.as_bytes())) };
+
+        let keys = self.keys.read().unwrap();
+        let kid_matches = header.kid.is_some() && keys.iter().any(|key| key.kid == header.kid);
+        let candidates = keys.iter()
+            .filter(|key| !kid_matches || key.kid == header.kid)
+            .map(|key| &key.key)
+            .chain(hmac_key.iter());
+
+        for key in candidates {
+            match decode::<PamApiTokenPayload>(token, key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                //The key is of a different type than the token's algorithm or did not sign the token, so we try the next one
+                Err(err) if matches!(err.kind(), ErrorKind::InvalidAlgorithm | ErrorKind::InvalidSignature | ErrorKind::InvalidRsaKey(_) | ErrorKind::InvalidEcdsaKey) => continue,
+                Err(err) => return Err(match err.kind() {
+                    ErrorKind::ExpiredSignature => TokenError::Expired,
+                    ErrorKind::ImmatureSignature => TokenError::NotYetValid,
+                    ErrorKind::InvalidIssuer => TokenError::InvalidIssuer,
+                    ErrorKind::MissingRequiredClaim(claim) => TokenError::MissingClaim(claim.to_string()),
+                    _ => TokenError::Malformed,
+                }),
+            }
+        }
+
+        return Err(TokenError::InvalidSignature);
+    }
+}
+
+///Loads a JWKS document from a file or a http(s) URL
+async fn load_jwks(location: &str) -> Result<JwkSet, String> {
+    let contents = if location.starts_with("http://") || location.starts_with("https://") {
+        let client = reqwest::Client::builder()
+            .connect_timeout(JWKS_CONNECT_TIMEOUT)
+            .timeout(JWKS_TIMEOUT)
+            .build()
+            .map_err(|err| format!("Could not create the client to fetch the JWKS document {}: {}", location, err))?;
+
+        let response = client.get(location).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Could not fetch the JWKS document {}: {}", location, err))?;
+
+        response.text().await.map_err(|err| format!("Could not read the JWKS document {}: {}", location, err))?
+    } else {
+        fs::read_to_string(location).map_err(|err| format!("Could not read the JWKS document {}: {}", location, err))?
+    };
+
+    return serde_json::from_str(&contents).map_err(|err| format!("Could not parse the JWKS document {}: {}", location, err));
+}
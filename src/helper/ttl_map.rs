@@ -0,0 +1,162 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, time::{Duration, Instant}};
+
+struct TtlEntry<V> {
+    value: V,
+    expires_at: Instant,
+}
+
+///Map whose entries expire after a time to live and which never holds more than a fixed number of entries. When it is full, a new
+///entry first takes the place of the expired ones and otherwise of the entry that would expire next. It isn't synchronized, so
+///its users keep it behind a Mutex.
+pub struct TtlMap<K, V> {
+    entries: HashMap<K, TtlEntry<V>>,
+    capacity: usize,
+    ///Entries for which this returns true are kept past their expiry until the map runs out of room
+    keep_expired: fn(&V) -> bool,
+}
+
+impl<K: Hash + Eq + Clone, V> TtlMap<K, V> {
+    pub fn new(capacity: usize) -> TtlMap<K, V> {
+        return TtlMap::with_keep_expired(capacity, |_| false);
+    }
+
+    ///Creates a map that keeps expired entries as long as keep_expired returns true for them and there is room for them
+    pub fn with_keep_expired(capacity: usize, keep_expired: fn(&V) -> bool) -> TtlMap<K, V> {
+        return TtlMap { entries: HashMap::new(), capacity, keep_expired };
+    }
+
+    fn is_live(&self, entry: &TtlEntry<V>) -> bool {
+        return entry.expires_at > Instant::now() || (self.keep_expired)(&entry.value);
+    }
+
+    ///Returns the value of the key, unless it has expired
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        return self.entries.get(key).filter(|entry| self.is_live(entry)).map(|entry| &entry.value);
+    }
+
+    ///Returns the value of the key for modification, unless it has expired
+    pub fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q> {
+        let keep_expired = self.keep_expired;
+
+        return self.entries.get_mut(key)
+            .filter(|entry| entry.expires_at > Instant::now() || keep_expired(&entry.value))
+            .map(|entry| &mut entry.value);
+    }
+
+    ///Inserts a value that expires after the ttl, replacing the value the key had
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration) {
+        self.make_room(&key);
+        self.entries.insert(key, TtlEntry { value, expires_at: Instant::now() + ttl });
+    }
+
+    ///Returns the value of the key for modification. If it has none or it has expired, a value created by the function and
+    ///expiring after the ttl is inserted first.
+    pub fn get_or_insert_with(&mut self, key: K, ttl: Duration, create: impl FnOnce() -> V) -> &mut V {
+        if self.get(&key).is_none() {
+            self.insert(key.clone(), create(), ttl);
+        }
+
+        return &mut self.entries.get_mut(&key).unwrap().value;
+    }
+
+    ///Lets the value of the key expire after the ttl, counted from now
+    pub fn set_ttl<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, ttl: Duration) where K: Borrow<Q> {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.expires_at = Instant::now() + ttl;
+        }
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        return self.entries.remove(key).map(|entry| entry.value);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    ///Makes room for the key if the map is full and doesn't hold it yet, by dropping the expired entries or, if none expired,
+    ///the entry that would expire next
+    fn make_room(&mut self, key: &K) {
+        if self.entries.len() < self.capacity || self.entries.contains_key(key) {
+            return;
+        }
+
+        let keep_expired = self.keep_expired;
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires_at > now || keep_expired(&entry.value));
+
+        //Still full, so we drop the entry that would expire next
+        if self.entries.len() >= self.capacity {
+            let next_expiring = self.entries.iter().min_by_key(|(_, entry)| entry.expires_at).map(|(key, _)| key.clone());
+
+            if let Some(next_expiring) = next_expiring {
+                self.entries.remove(&next_expiring);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::TtlMap;
+
+    const LONG: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let mut map = TtlMap::new(10);
+        map.insert("a", 1, Duration::ZERO);
+        map.insert("b", 2, LONG);
+
+        assert_eq!(map.get(&"a"), None);
+        assert_eq!(map.get(&"b"), Some(&2));
+    }
+
+    #[test]
+    fn full_map_drops_expired_entries_first() {
+        let mut map = TtlMap::new(2);
+        map.insert("expired", 1, Duration::ZERO);
+        map.insert("live", 2, LONG);
+        map.insert("new", 3, LONG);
+
+        assert_eq!(map.get(&"live"), Some(&2));
+        assert_eq!(map.get(&"new"), Some(&3));
+        assert_eq!(map.entries.len(), 2);
+    }
+
+    #[test]
+    fn full_map_drops_the_entry_that_expires_next() {
+        let mut map = TtlMap::new(2);
+        map.insert("late", 1, LONG * 2);
+        map.insert("early", 2, LONG);
+        map.insert("new", 3, LONG);
+
+        assert_eq!(map.get(&"late"), Some(&1));
+        assert_eq!(map.get(&"early"), None);
+        assert_eq!(map.get(&"new"), Some(&3));
+    }
+
+    #[test]
+    fn replacing_a_key_of_a_full_map_keeps_the_other_entries() {
+        let mut map = TtlMap::new(2);
+        map.insert("a", 1, LONG);
+        map.insert("b", 2, LONG);
+        map.insert("a", 3, LONG);
+
+        assert_eq!(map.get(&"a"), Some(&3));
+        assert_eq!(map.get(&"b"), Some(&2));
+    }
+
+    #[test]
+    fn kept_entries_outlive_their_expiry() {
+        let mut map = TtlMap::with_keep_expired(10, |value: &i32| *value > 0);
+        map.insert("kept", 1, Duration::ZERO);
+        map.insert("dropped", 0, Duration::ZERO);
+
+        assert_eq!(map.get(&"kept"), Some(&1));
+        assert_eq!(map.get(&"dropped"), None);
+        assert_eq!(*map.get_or_insert_with("kept", LONG, || 2), 1);
+        assert_eq!(*map.get_or_insert_with("dropped", LONG, || 2), 2);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fs::File, io::{self, BufRead, BufReader, Cursor, Seek}, path::PathBuf, sync::Arc};
+use actix_web::web::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use rand::Rng;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use crate::config::UploadConfig;
+use super::scan_error::ScanError;
+
+///Bytes at the start of an upload its type is determined from
+pub const SNIFF_BYTES: usize = 8192;
+
+///Checks the start of an upload, so uploads of a type we don't accept are rejected before they have been read completely
+pub type Sniff = fn(&[u8]) -> Result<(), ScanError>;
+
+///Accepts every upload, whatever its type
+pub fn sniff_any(_: &[u8]) -> Result<(), ScanError> {
+    return Ok(());
+}
+
+///Data that can be read like a file, whether it is held in memory or in a file
+pub trait UploadReader: BufRead + Seek {}
+impl<T: BufRead + Seek> UploadReader for T {}
+
+///An upload that has been too large to hold in memory. The file is removed once it is dropped.
+pub struct SpilledFile {
+    path: PathBuf,
+    size: usize,
+    ///Start of the upload, so its type can be determined without reading the file
+    head: Bytes,
+}
+
+impl Drop for SpilledFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            eprintln!("Could not remove the upload {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+///Data a client sent us. Small uploads are held in memory, larger ones are spilled to a temporary file.
+#[derive(Clone)]
+pub enum Upload {
+    Memory(Bytes),
+    File(Arc<SpilledFile>),
+}
+
+impl From<Bytes> for Upload {
+    fn from(data: Bytes) -> Upload {
+        return Upload::Memory(data);
+    }
+}
+
+impl Upload {
+    pub fn len(&self) -> usize {
+        return match self {
+            Upload::Memory(data) => data.len(),
+            Upload::File(file) => file.size,
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    ///Returns the start of the upload, which is enough to determine its type
+    pub fn head(&self) -> &[u8] {
+        return match self {
+            Upload::Memory(data) => &data[..data.len().min(SNIFF_BYTES)],
+            Upload::File(file) => &file.head,
+        };
+    }
+
+    ///Returns a reader of the whole upload
+    pub fn reader(&self) -> io::Result<Box<dyn UploadReader>> {
+        return Ok(match self {
+            Upload::Memory(data) => Box::new(Cursor::new(data.clone())),
+            Upload::File(file) => Box::new(BufReader::new(File::open(&file.path)?)),
+        });
+    }
+}
+
+///Creates a new temporary file for an upload
+async fn create_spill_file(config: &UploadConfig) -> Result<(PathBuf, tokio::fs::File), ScanError> {
+    let dir = config.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("pamaxie-upload-{}", hex::encode(rand::thread_rng().gen::<[u8; 16]>())));
+
+    return match OpenOptions::new().write(true).create_new(true).open(&path).await {
+        Ok(file) => Ok((path, file)),
+        Err(err) => {
+            eprintln!("Could not create the upload {}: {}", path.display(), err);
+            Err(ScanError::StorageUnavailable("We could not store the data temporarily. Please try again later".to_string()))
+        }
+    };
+}
+
+///Reads an upload from a stream, like the body of a request or a field of a multipart body. The start of the upload is checked
+///as soon as it arrived, and reading stops as soon as the upload gets larger than we accept. Uploads larger than
+///`memory_threshold` are written to a temporary file instead of being held in memory.
+///
+/// # Arguments
+/// stream: S - The stream the upload is read from
+/// max_bytes: usize - The largest upload that is accepted
+/// config: &UploadConfig - The settings of our uploads
+/// sniff: Sniff - The check of the start of the upload
+///
+/// # Returns
+/// Result<Upload, ScanError> - The upload, or why it has been rejected
+pub async fn read_stream<S, E>(mut stream: S, max_bytes: usize, config: &UploadConfig, sniff: Sniff) -> Result<Upload, ScanError>
+    where S: Stream<Item = Result<Bytes, E>> + Unpin {
+    let write_failed = |err: io::Error| {
+        eprintln!("Could not write an upload to its temporary file: {}", err);
+        ScanError::StorageUnavailable("We could not store the data temporarily. Please try again later".to_string())
+    };
+
+    //The start of the upload has to stay in memory until it has been checked
+    let memory_threshold = config.memory_threshold.max(SNIFF_BYTES);
+    let mut buffer = BytesMut::new();
+    let mut spilled: Option<(SpilledFile, tokio::fs::File)> = None;
+    let mut size = 0;
+    let mut sniffed = false;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| ScanError::InvalidRequest("The body of the request could not be read.".to_string()))?;
+        size += chunk.len();
+
+        if size > max_bytes {
+            return Err(ScanError::PayloadTooLarge(format!("The data may be at most {} bytes large.", max_bytes)));
+        }
+
+        match spilled.as_mut() {
+            Some((_, file)) => file.write_all(&chunk).await.map_err(write_failed)?,
+            None => buffer.extend_from_slice(&chunk),
+        }
+
+        if !sniffed && size >= SNIFF_BYTES {
+            sniff(&buffer[..SNIFF_BYTES])?;
+            sniffed = true;
+        }
+
+        if spilled.is_none() && buffer.len() > memory_threshold {
+            let (path, mut file) = create_spill_file(config).await?;
+            let head = Bytes::copy_from_slice(&buffer[..SNIFF_BYTES]);
+            //Created before anything is written, so the file is removed if writing fails
+            let spilled_file = SpilledFile { path, size: 0, head };
+
+            file.write_all(&buffer).await.map_err(write_failed)?;
+            buffer.clear();
+            spilled = Some((spilled_file, file));
+        }
+    }
+
+    if !sniffed && size > 0 {
+        sniff(&buffer)?;
+    }
+
+    return match spilled {
+        Some((mut spilled_file, mut file)) => {
+            file.flush().await.map_err(write_failed)?;
+            spilled_file.size = size;
+            Ok(Upload::File(Arc::new(spilled_file)))
+        },
+        None => Ok(Upload::Memory(buffer.freeze())),
+    };
+}
+
+///Reads a stream that is always held in memory, like a JSON body or a text field of a multipart body
+///
+/// # Arguments
+/// stream: S - The stream that is read
+/// max_bytes: usize - The most bytes that are accepted
+///
+/// # Returns
+/// Result<Bytes, ScanError> - The data of the stream
+pub async fn read_bytes<S, E>(mut stream: S, max_bytes: usize) -> Result<Bytes, ScanError>
+    where S: Stream<Item = Result<Bytes, E>> + Unpin {
+    let mut body = BytesMut::new();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| ScanError::InvalidRequest("The body of the request could not be read.".to_string()))?;
+
+        if body.len() + chunk.len() > max_bytes {
+            return Err(ScanError::PayloadTooLarge(format!("The data may be at most {} bytes large.", max_bytes)));
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    return Ok(body.freeze());
+}
@@ -0,0 +1,210 @@
+use std::{net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}, str::FromStr, time::{Duration, Instant}};
+use actix_web::web::{Bytes, BytesMut};
+use ipnet::IpNet;
+use reqwest::{header, redirect, Url};
+use crate::config::UrlFetchConfig;
+use super::scan_error::ScanError;
+
+///Networks we never download from unless they are explicitly allowed: loopback, private, link-local (which holds the metadata
+///services of the cloud providers), shared, reserved, documentation and multicast ranges
+const BLOCKED_NETWORKS: [&str; 22] = [
+    "0.0.0.0/8", "10.0.0.0/8", "100.64.0.0/10", "127.0.0.0/8", "169.254.0.0/16", "172.16.0.0/12", "192.0.0.0/24", "192.0.2.0/24",
+    "192.88.99.0/24", "192.168.0.0/16", "198.18.0.0/15", "198.51.100.0/24", "203.0.113.0/24", "224.0.0.0/4", "240.0.0.0/4",
+    "::/128", "::1/128", "100::/64", "2001:db8::/32", "fc00::/7", "fe80::/10", "ff00::/8",
+];
+
+///Parses a network in CIDR notation, or a single address
+pub fn parse_network(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+    return IpNet::from_str(value).ok().or_else(|| IpAddr::from_str(value).ok().map(IpNet::from));
+}
+
+///Returns the IPv4 address an IPv6 address wraps or routes to: IPv4-mapped (::ffff:0:0/96) and IPv4-compatible (::/96)
+///addresses, NAT64 addresses (64:ff9b::/96) and 6to4 addresses (2002::/16). The unspecified and loopback addresses :: and ::1
+///are left to our IPv6 networks.
+fn get_embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = address.octets();
+
+    return match address.segments() {
+        [0, 0, 0, 0, 0, 0, 0, 0 | 1] => None,
+        [0, 0, 0, 0, 0, 0xffff | 0, _, _] | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15])),
+        [0x2002, _, _, _, _, _, _, _] => Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5])),
+        _ => None,
+    };
+}
+
+///Returns true if the host is one of the given hosts or a subdomain of one of them
+fn matches_host(hosts: &[String], host: &str) -> bool {
+    return hosts.iter().any(|pattern| {
+        let pattern = pattern.trim().trim_start_matches("*.").to_ascii_lowercase();
+        host == pattern || host.ends_with(&format!(".{}", pattern))
+    });
+}
+
+///Downloads the images our clients send us the URLs of. As those URLs are chosen by our clients, every address a download
+///connects to is checked, so they can't make us reach our own services or the metadata services of our cloud provider.
+///The host of every hop is resolved by us and the connection is pinned to the checked address, so a second DNS answer can't
+///sneak around the check.
+pub struct UrlFetcher {
+    config: UrlFetchConfig,
+    blocked_networks: Vec<IpNet>,
+    allowed_networks: Vec<IpNet>,
+    denied_networks: Vec<IpNet>,
+}
+
+impl UrlFetcher {
+    pub fn new(config: &UrlFetchConfig) -> UrlFetcher {
+        //Invalid networks are reported when the configuration is validated
+        let parse_all = |networks: &[String]| networks.iter().filter_map(|network| parse_network(network)).collect();
+
+        return UrlFetcher {
+            config: config.clone(),
+            blocked_networks: BLOCKED_NETWORKS.iter().map(|network| IpNet::from_str(network).unwrap()).collect(),
+            allowed_networks: parse_all(&config.allowed_networks),
+            denied_networks: parse_all(&config.denied_networks),
+        };
+    }
+
+    ///Returns true if we may connect to the address
+    fn is_allowed_address(&self, address: IpAddr) -> bool {
+        //IPv6 addresses that wrap an IPv4 address or are translated to one are checked like that IPv4 address
+        let address = match address {
+            IpAddr::V6(v6) => get_embedded_ipv4(v6).map_or(address, IpAddr::V4),
+            IpAddr::V4(_) => address,
+        };
+
+        if self.denied_networks.iter().any(|network| network.contains(&address)) {
+            return false;
+        }
+
+        return !self.blocked_networks.iter().any(|network| network.contains(&address))
+            || self.allowed_networks.iter().any(|network| network.contains(&address));
+    }
+
+    ///Checks a URL we are about to download from and resolves the address we connect to for it
+    ///
+    /// # Returns
+    /// Result<(String, SocketAddr), ScanError> - The host of the URL and the address we connect to, or why we must not
+    async fn check_url(&self, url: &Url) -> Result<(String, SocketAddr), ScanError> {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+        if matches_host(&self.config.denied_hosts, &host) || (!self.config.allowed_hosts.is_empty() && !matches_host(&self.config.allowed_hosts, &host)) {
+            return Err(ScanError::UrlNotAllowed("Images can't be downloaded from this host.".to_string()));
+        }
+
+        return self.resolve_public(url).await;
+    }
+
+    ///Resolves the address we connect to for a URL chosen by one of our clients, as long as it is a http(s) URL and every
+    ///address of its host is allowed by our network rules. Callers have to pin their connection to the returned address.
+    ///
+    /// # Returns
+    /// Result<(String, SocketAddr), ScanError> - The host of the URL and the address we connect to, or why we must not
+    pub async fn resolve_public(&self, url: &Url) -> Result<(String, SocketAddr), ScanError> {
+        let not_allowed = |message: &str| ScanError::UrlNotAllowed(message.to_string());
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(not_allowed("Only http and https urls can be scanned."));
+        }
+
+        let host = url.host_str().ok_or_else(|| not_allowed("The url has to contain a host."))?.to_ascii_lowercase();
+        let port = url.port_or_known_default().ok_or_else(|| not_allowed("The url has to contain a port."))?;
+
+        let addresses: Vec<SocketAddr> = match IpAddr::from_str(host.trim_start_matches('[').trim_end_matches(']')) {
+            Ok(address) => vec![SocketAddr::new(address, port)],
+            Err(_) => tokio::net::lookup_host((host.as_str(), port)).await
+                .map_err(|_| ScanError::UrlFetchFailed("The host of the url could not be resolved.".to_string()))?
+                .collect(),
+        };
+
+        //Every address has to be allowed, as we can't tell which one a client expects us to use
+        if addresses.is_empty() || !addresses.iter().all(|address| self.is_allowed_address(address.ip())) {
+            return Err(not_allowed("Images can't be downloaded from private or internal addresses."));
+        }
+
+        return Ok((host, addresses[0]));
+    }
+
+    ///Downloads the image at the URL, following up to `max_redirects` redirects. Fails if the download takes longer than the
+    ///configured timeout, the image is larger than `max_bytes` or the response isn't an image.
+    pub async fn fetch(&self, url: &str) -> Result<Bytes, ScanError> {
+        let timeout = Duration::from_secs(self.config.timeout);
+
+        return match tokio::time::timeout(timeout, self.fetch_within(url, Instant::now() + timeout)).await {
+            Ok(result) => result,
+            Err(_) => Err(ScanError::UrlFetchFailed(format!("The image could not be downloaded within {} seconds.", self.config.timeout))),
+        };
+    }
+
+    async fn fetch_within(&self, url: &str, deadline: Instant) -> Result<Bytes, ScanError> {
+        let fetch_failed = || ScanError::UrlFetchFailed("Could not fetch the image from the given url. Please make sure the url is correct.".to_string());
+        let mut url = Url::parse(url).map_err(|_| ScanError::InvalidRequest("Please ensure you specify a valid url to detect from".to_string()))?;
+        let mut redirects = 0;
+
+        loop {
+            let (host, address) = self.check_url(&url).await?;
+
+            //Redirects are followed by us, so every hop is checked and pinned to the address we checked
+            let client = reqwest::Client::builder()
+                .redirect(redirect::Policy::none())
+                .no_proxy()
+                .timeout(deadline.saturating_duration_since(Instant::now()))
+                .resolve(&host, address)
+                .build()
+                .map_err(|_| fetch_failed())?;
+
+            let response = client.get(url.clone()).send().await.map_err(|_| fetch_failed())?;
+
+            if response.status().is_redirection() {
+                redirects += 1;
+
+                if redirects > self.config.max_redirects {
+                    return Err(ScanError::UrlFetchFailed(format!("The url redirected more than {} times.", self.config.max_redirects)));
+                }
+
+                let location = response.headers().get(header::LOCATION).and_then(|location| location.to_str().ok()).ok_or_else(fetch_failed)?;
+                url = url.join(location).map_err(|_| fetch_failed())?;
+                continue;
+            }
+
+            if !response.status().is_success() {
+                return Err(ScanError::UrlFetchFailed(format!("The url responded with status {}.", response.status().as_u16())));
+            }
+
+            return self.read_image(response).await;
+        }
+    }
+
+    ///Reads the body of a response, as long as it is an image that isn't larger than we accept
+    async fn read_image(&self, mut response: reqwest::Response) -> Result<Bytes, ScanError> {
+        let not_an_image = || ScanError::UnsupportedMediaType("The url does not point to an image.".to_string());
+        let too_large = || ScanError::PayloadTooLarge(format!("Images downloaded from a url may be at most {} bytes large.", self.config.max_bytes));
+
+        //Servers that don't know the type of a file often send it as application/octet-stream, which is checked by its content
+        let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|content_type| content_type.to_str().ok()).unwrap_or_default().to_ascii_lowercase();
+        if !content_type.is_empty() && !content_type.starts_with("image/") && !content_type.starts_with("application/octet-stream") {
+            return Err(not_an_image());
+        }
+
+        if response.content_length().is_some_and(|length| length > self.config.max_bytes) {
+            return Err(too_large());
+        }
+
+        let mut body = BytesMut::new();
+
+        while let Some(chunk) = response.chunk().await
+            .map_err(|_| ScanError::UrlFetchFailed("Error while trying to load the image as bytes. Please ensure the url is correct and we can get images from it.".to_string()))? {
+            if (body.len() + chunk.len()) as u64 > self.config.max_bytes {
+                return Err(too_large());
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        if !infer::is_image(&body) {
+            return Err(not_an_image());
+        }
+
+        return Ok(body.freeze());
+    }
+}
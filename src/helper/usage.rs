@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use serde::Serialize;
+use time::{Date, Month, OffsetDateTime, Time};
+use crate::config::{UsageBackend, UsageConfig};
+use super::scan_error::ScanError;
+use super::usage_store::{RedisStore, SqliteStore, Usage, UsageStore};
+
+///Monthly quotas of a project. Quotas that are none are unlimited.
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Quota {
+    pub monthly_requests: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+///Meters the usage of our projects per month and enforces their monthly quotas
+pub struct UsageMeter {
+    config: UsageConfig,
+    store: Arc<dyn UsageStore>,
+}
+
+///Returns the current period, which is the current month in UTC formatted as YYYY-MM
+pub fn current_period() -> String {
+    let now = OffsetDateTime::now_utc();
+    return format!("{:04}-{:02}", now.year(), now.month() as u8);
+}
+
+///Returns true if the period is formatted as YYYY-MM
+pub fn is_valid_period(period: &str) -> bool {
+    let (year, month) = match period.split_once('-') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    return year.len() == 4 && year.parse::<u16>().is_ok()
+        && month.len() == 2 && month.parse::<u8>().is_ok_and(|month| (1..=12).contains(&month));
+}
+
+///Returns the seconds until the next month starts in UTC
+fn seconds_until_next_period() -> u64 {
+    let now = OffsetDateTime::now_utc();
+    let year = if now.month() == Month::December { now.year() + 1 } else { now.year() };
+    let next_period = Date::from_calendar_date(year, now.month().next(), 1).unwrap().with_time(Time::MIDNIGHT).assume_utc();
+
+    return (next_period - now).whole_seconds().max(1) as u64;
+}
+
+impl UsageMeter {
+    ///Creates a usage meter that records into the configured backend
+    ///
+    /// # Returns
+    /// Result<UsageMeter, String> - The usage meter or why its backend could not be opened
+    pub async fn new(config: &UsageConfig) -> Result<UsageMeter, String> {
+        let store: Arc<dyn UsageStore> = match config.backend {
+            UsageBackend::Sqlite => Arc::new(SqliteStore::new(&config.database_path)?),
+            UsageBackend::Redis => Arc::new(RedisStore::new(&config.redis_url).await?),
+        };
+
+        return Ok(UsageMeter { config: config.clone(), store });
+    }
+
+    ///Returns the monthly quotas of a project, after applying its overrides to the defaults
+    pub fn get_quota(&self, project_id: u64) -> Quota {
+        let overrides = self.config.projects.get(&project_id.to_string());
+        let monthly_requests = overrides.and_then(|quota| quota.monthly_requests).unwrap_or(self.config.monthly_requests);
+        let monthly_bytes = overrides.and_then(|quota| quota.monthly_bytes).unwrap_or(self.config.monthly_bytes);
+
+        return Quota {
+            monthly_requests: if monthly_requests == 0 { None } else { Some(monthly_requests) },
+            monthly_bytes: if monthly_bytes == 0 { None } else { Some(monthly_bytes) },
+        };
+    }
+
+    ///Returns the usage of a project in the given period
+    pub async fn get_usage(&self, project_id: u64, period: &str) -> Result<Usage, String> {
+        return self.store.get(project_id, period).await;
+    }
+
+    ///Adds usage to the current period of a project. Failures are only logged, as they must not fail the request of the project.
+    pub async fn record(&self, project_id: u64, usage: Usage) {
+        if let Err(err) = self.store.add(project_id, &current_period(), usage).await {
+            eprintln!("Could not record the usage of project {}. {}", project_id, err);
+        }
+    }
+
+    ///Checks that a project has not exhausted one of its quotas in the current period.
+    ///Requests are let through if the usage can't be read, so an outage of the backend doesn't take down our API.
+    pub async fn check_quota(&self, project_id: u64) -> Result<(), ScanError> {
+        let quota = self.get_quota(project_id);
+
+        if quota.monthly_requests.is_none() && quota.monthly_bytes.is_none() {
+            return Ok(());
+        }
+
+        let usage = match self.store.get(project_id, &current_period()).await {
+            Ok(usage) => usage,
+            Err(err) => {
+                eprintln!("Could not check the quota of project {}, letting the request through. {}", project_id, err);
+                return Ok(());
+            }
+        };
+
+        if quota.monthly_requests.is_some_and(|monthly_requests| usage.requests >= monthly_requests) {
+            return Err(ScanError::QuotaExceeded { message: "Your project has used up its monthly request quota.".to_string(), retry_after: seconds_until_next_period() });
+        }
+
+        if quota.monthly_bytes.is_some_and(|monthly_bytes| usage.bytes >= monthly_bytes) {
+            return Err(ScanError::QuotaExceeded { message: "Your project has used up its monthly upload quota.".to_string(), retry_after: seconds_until_next_period() });
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, path::Path};
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use super::sqlite::{self, Database};
+
+///Usage of a project in a single period, or an amount of usage that is added to it
+#[derive(Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    ///Detection requests the project sent
+    pub requests: u64,
+    ///Bytes the project uploaded with its requests
+    pub bytes: u64,
+    ///Scans that were answered with a result from our database
+    pub cache_hits: u64,
+    ///Scans that had to be processed by our workers
+    pub fresh_scans: u64,
+    ///Scans that did not finish in time
+    pub timeouts: u64,
+}
+
+///Records the usage of our projects. Implementations have to be safe to use from every worker at once.
+pub trait UsageStore: Send + Sync {
+    ///Adds the given usage to the usage of a project in a period
+    fn add<'a>(&'a self, project_id: u64, period: &'a str, usage: Usage) -> BoxFuture<'a, Result<(), String>>;
+
+    ///Returns the usage of a project in a period
+    fn get<'a>(&'a self, project_id: u64, period: &'a str) -> BoxFuture<'a, Result<Usage, String>>;
+}
+
+///Records the usage in a SQLite database of this instance
+pub struct SqliteStore {
+    database: Database,
+}
+
+impl SqliteStore {
+    ///Opens the database at the given path and creates its table, if it doesn't exist yet
+    pub fn new(path: &Path) -> Result<SqliteStore, String> {
+        let connection = sqlite::open(path, "usage database", "
+            CREATE TABLE IF NOT EXISTS usage (
+                project_id INTEGER NOT NULL,
+                period TEXT NOT NULL,
+                requests INTEGER NOT NULL DEFAULT 0,
+                bytes INTEGER NOT NULL DEFAULT 0,
+                cache_hits INTEGER NOT NULL DEFAULT 0,
+                fresh_scans INTEGER NOT NULL DEFAULT 0,
+                timeouts INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (project_id, period)
+            );
+        ")?;
+
+        return Ok(SqliteStore { database: Database::new(connection) });
+    }
+}
+
+impl UsageStore for SqliteStore {
+    fn add<'a>(&'a self, project_id: u64, period: &'a str, usage: Usage) -> BoxFuture<'a, Result<(), String>> {
+        let period = period.to_string();
+
+        return Box::pin(self.database.run(move |connection| connection.execute("
+            INSERT INTO usage (project_id, period, requests, bytes, cache_hits, fresh_scans, timeouts) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (project_id, period) DO UPDATE SET
+                requests = requests + excluded.requests,
+                bytes = bytes + excluded.bytes,
+                cache_hits = cache_hits + excluded.cache_hits,
+                fresh_scans = fresh_scans + excluded.fresh_scans,
+                timeouts = timeouts + excluded.timeouts",
+            params![project_id as i64, period, usage.requests as i64, usage.bytes as i64, usage.cache_hits as i64, usage.fresh_scans as i64, usage.timeouts as i64])
+            .map(|_| ())));
+    }
+
+    fn get<'a>(&'a self, project_id: u64, period: &'a str) -> BoxFuture<'a, Result<Usage, String>> {
+        let period = period.to_string();
+
+        return Box::pin(self.database.run(move |connection| connection.query_row(
+            "SELECT requests, bytes, cache_hits, fresh_scans, timeouts FROM usage WHERE project_id = ?1 AND period = ?2",
+            params![project_id as i64, period],
+            |row| Ok(Usage {
+                requests: row.get::<_, i64>(0)? as u64,
+                bytes: row.get::<_, i64>(1)? as u64,
+                cache_hits: row.get::<_, i64>(2)? as u64,
+                fresh_scans: row.get::<_, i64>(3)? as u64,
+                timeouts: row.get::<_, i64>(4)? as u64,
+            })).optional().map(Option::unwrap_or_default)));
+    }
+}
+
+///Records the usage in Redis, so every instance of the API shares it
+pub struct RedisStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStore {
+    ///Connects to the Redis server at the given URL
+    pub async fn new(url: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|err| format!("{} is not a valid Redis URL: {}", url, err))?;
+        let connection = ConnectionManager::new(client).await.map_err(|err| format!("Could not connect to Redis at {}: {}", url, err))?;
+
+        return Ok(RedisStore { connection });
+    }
+}
+
+fn redis_key(project_id: u64, period: &str) -> String {
+    return format!("pamaxie:usage:{}:{}", project_id, period);
+}
+
+impl UsageStore for RedisStore {
+    fn add<'a>(&'a self, project_id: u64, period: &'a str, usage: Usage) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let key = redis_key(project_id, period);
+
+            return redis::pipe().atomic()
+                .hincr(&key, "requests", usage.requests).ignore()
+                .hincr(&key, "bytes", usage.bytes).ignore()
+                .hincr(&key, "cache_hits", usage.cache_hits).ignore()
+                .hincr(&key, "fresh_scans", usage.fresh_scans).ignore()
+                .hincr(&key, "timeouts", usage.timeouts).ignore()
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string());
+        });
+    }
+
+    fn get<'a>(&'a self, project_id: u64, period: &'a str) -> BoxFuture<'a, Result<Usage, String>> {
+        return Box::pin(async move {
+            let fields: HashMap<String, u64> = redis::cmd("HGETALL").arg(redis_key(project_id, period))
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            let field = |name: &str| fields.get(name).copied().unwrap_or(0);
+
+            return Ok(Usage {
+                requests: field("requests"),
+                bytes: field("bytes"),
+                cache_hits: field("cache_hits"),
+                fresh_scans: field("fresh_scans"),
+                timeouts: field("timeouts"),
+            });
+        });
+    }
+}
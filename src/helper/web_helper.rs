@@ -1,9 +1,9 @@
 use actix_web::http::header::AUTHORIZATION;
 use actix_web::HttpRequest;
-use jwt::{Token, Header};
 use serde::{Serialize, Deserialize};
 use serde_json::{Value};
-use super::misc::get_env_variable;
+use crate::config::Config;
+use super::token_verifier::{TokenError, TokenVerifier};
 
 #[derive(Serialize, Deserialize)]
 #[allow(non_snake_case)]
@@ -12,109 +12,68 @@ pub struct PamApiTokenPayload{
     pub isApiToken: bool,
     pub apiTokenMachineGuid: String,
     pub projectId: u64,
-    pub nbf: i32,
-    pub exp: i32,
-    pub iat: i32,
+    ///Optional, as RFC 7519 doesn't require it. It is validated when it is set.
+    #[serde(default)]
+    pub nbf: Option<i64>,
+    pub exp: i64,
+    pub iat: i64,
     pub iss: String,
 }
 
-///Returns the pamaxie API URL from the environment variable
-pub fn get_pam_url() -> String {
-    return get_env_variable("PAM_BASE_URL".to_string(), "https://api.pamaxie.com".to_string());
-}
-
-///Returns the database API Address from the environment variable
-pub fn get_pam_db_url() -> String {
-    return get_env_variable("DB_API_URL".to_string(), "".to_string());
-}
+//Turns the response of our Database API to a yes or no question into its answer. Only a success or a client error is an
+//answer, as server errors and failed requests say nothing about the token and must not be cached as a rejection.
+fn get_answer(response: Result<reqwest::Response, reqwest::Error>) -> Option<bool> {
+    let status = response.ok()?.status();
 
-///Returns the pamaxie authorization token from the environment variable, to interact with the Database API
-pub fn get_pam_auth_token() -> String {
-    return get_env_variable("PAM_AUTH_TOKEN".to_string(), "".to_string());
+    return match status {
+        status if status.is_success() => Some(true),
+        status if status.is_client_error() => Some(false),
+        _ => None,
+    };
 }
 
-//Checks if we can connect to our Database API with the set pamaxie authorization token
-pub(crate) async fn check_auth(req: &HttpRequest) -> bool{
-    let auth = req.head().headers.get("Authorization");
-
-    if auth.is_none()
-    {
-        return false;
-    }
-    
-    let client = reqwest::Client::new();
+//Checks if our Database API allows the bearer token to authenticate with us
+//Returns None if the Database API could not be reached or failed to answer
+pub(crate) async fn check_auth(client: &reqwest::Client, config: &Config, token: &str) -> Option<bool>{
     let response = client
-            .get([get_pam_db_url(), "/db/v1/scan/CanAuthenticate".to_string()].join(""))
-            .header(AUTHORIZATION, auth.unwrap().to_str().unwrap().to_string())
+            .get(format!("{}{}", config.db_api.url, "/db/v1/scan/CanAuthenticate"))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
             .send()
             .await;
 
-    if response.is_err(){
-        return false;
-    }
-
-    let status = response.as_ref().unwrap().status();
-
-    return status.is_success();
+    return get_answer(response);
 }
 
 //Checks if the authentication is issued via Pamaxie's internal tokens / projects
-pub(crate) async fn is_internal_auth(req: &HttpRequest) -> bool{
-    let auth = req.head().headers.get("Authorization");
-
-    if auth.is_none()
-    {
-        return false;
-    }
-
-    let client = reqwest::Client::new();
+//Returns None if the Database API could not be reached or failed to answer
+pub(crate) async fn is_internal_auth(client: &reqwest::Client, config: &Config, token: &str) -> Option<bool>{
     let response = client
-            .get([get_pam_db_url(), "/db/v1/scan/IsInternalToken".to_string()].join(""))
-            .header(AUTHORIZATION, auth.unwrap().to_str().unwrap().to_string())
+            .get(format!("{}{}", config.db_api.url, "/db/v1/scan/IsInternalToken"))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
             .send()
             .await;
 
-    if response.is_err(){
-        return false;
-    }
-
-    return response.as_ref().unwrap().status().is_success();
+    return get_answer(response);
 }
 
-///Gets the payload from JWT bearer's token 
-pub(crate) fn get_scan_token_payload(req: &HttpRequest) -> std::option::Option<PamApiTokenPayload>{
-    let auth = req.head().headers.get("Authorization");
-
-    if auth.is_none()
-    {
-        return None;
-    }
-
-    let auth_credential = String::from(auth.unwrap().to_str().unwrap().strip_prefix("Bearer ").unwrap());
-
-    let unverified: Token<Header, PamApiTokenPayload, _> = Token::parse_unverified(auth_credential.as_ref()).expect("We were unable to pase a reached in JWT token");
-    
-    let payload = PamApiTokenPayload{
-        ownerId: unverified.claims().ownerId,
-        isApiToken: false,
-        apiTokenMachineGuid: String::from(&unverified.claims().apiTokenMachineGuid),
-        projectId: unverified.claims().projectId,
-        nbf: unverified.claims().nbf,
-        exp: unverified.claims().exp,
-        iat: unverified.claims().iat,
-        iss: String::from(&unverified.claims().iss),
-    };
+///Gets the JWT bearer token sent in the Authorization header of the request
+pub(crate) fn get_bearer_token(req: &HttpRequest) -> Option<&str> {
+    let auth = req.head().headers.get(AUTHORIZATION)?;
+    return auth.to_str().ok()?.strip_prefix("Bearer ");
+}
 
-    return Some(payload);
+///Gets the payload from JWT bearer's token, after verifying its signature and validity
+pub(crate) fn get_scan_token_payload(req: &HttpRequest, verifier: &TokenVerifier) -> Result<PamApiTokenPayload, TokenError>{
+    let auth_credential = get_bearer_token(req).ok_or(TokenError::Missing)?;
+    return verifier.verify(auth_credential);
 }
 
 ///Gets a new pamaxie authorization token from the database API
-pub async fn get_pam_token() -> Option<String> {
+pub async fn get_pam_token(client: &reqwest::Client, config: &Config) -> Option<String> {
     eprintln!("Refreshing auth token now");
-    let client = reqwest::Client::new();
     let response = client
-            .get(format!("{}{}", get_pam_db_url(), "/db/v1/scan/login"))
-            .header("Authorization", format!("Token {}", get_pam_auth_token()))
+            .get(format!("{}{}", config.db_api.url, "/db/v1/scan/login"))
+            .header("Authorization", format!("Token {}", config.db_api.auth_token.get()))
             .send()
             .await;
 
@@ -123,14 +82,21 @@ pub async fn get_pam_token() -> Option<String> {
         return None;
     }
 
-    let response_body = response.unwrap().text().await;
+    let response = response.unwrap();
+
+    if !response.status().is_success() {
+        eprintln!("The database API rejected the access token with status code {}. Please validate it is correct.", response.status());
+        return None;
+    }
+
+    let response_body = response.text().await.unwrap_or_default();
 
     //empty response body
-    if response_body.as_ref().unwrap().is_empty(){
+    if response_body.is_empty(){
         return None;
     }
 
-    let json_val: Value = serde_json::from_str(response_body.as_ref().unwrap().as_str()).unwrap();
-    let json_token = &json_val["Token"]["Token"].as_str();
-    return Some(json_token.unwrap().to_string());
+    let json_val: Value = serde_json::from_str(&response_body).ok()?;
+    let json_token = json_val["Token"]["Token"].as_str()?;
+    return Some(json_token.to_string());
 }
\ No newline at end of file
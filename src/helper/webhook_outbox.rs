@@ -0,0 +1,501 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+use futures::future::BoxFuture;
+use redis::{aio::ConnectionManager, Script};
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+use serde_json::Value;
+use super::sqlite::{self, Database};
+
+///State of a webhook delivery
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    ///The delivery is waiting for its next attempt
+    Pending,
+    ///The callback accepted the delivery
+    Delivered,
+    ///Every attempt of the delivery failed
+    Failed,
+}
+
+impl DeliveryStatus {
+    fn as_str(&self) -> &'static str {
+        return match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        };
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(()),
+        };
+    }
+}
+
+///A scan result that is, or has been, sent to a callback. Timestamps are unix timestamps in seconds.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub id: i64,
+    pub project_id: u64,
+    ///ID of the scan job whose result is delivered
+    pub job_id: String,
+    pub url: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: i64,
+    ///HTTP status the callback responded with on the last attempt, if it responded
+    pub last_status_code: Option<u16>,
+    ///Why the last attempt failed
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+    ///Body that is posted to the callback
+    pub payload: Value,
+}
+
+const DELIVERY_COLUMNS: &str = "id, project_id, job_id, url, status, attempts, next_attempt_at, last_status_code, last_error, created_at, delivered_at, payload";
+
+fn read_delivery(row: &Row) -> rusqlite::Result<Delivery> {
+    let status: String = row.get(4)?;
+    let payload: String = row.get(11)?;
+
+    return Ok(Delivery {
+        id: row.get(0)?,
+        project_id: row.get::<_, i64>(1)? as u64,
+        job_id: row.get(2)?,
+        url: row.get(3)?,
+        status: status.parse().unwrap_or(DeliveryStatus::Failed),
+        attempts: row.get(5)?,
+        next_attempt_at: row.get(6)?,
+        last_status_code: row.get(7)?,
+        last_error: row.get(8)?,
+        created_at: row.get(9)?,
+        delivered_at: row.get(10)?,
+        payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+    });
+}
+
+///Builds the body that is posted to the callbacks of a project
+pub type Payload = Box<dyn Fn(u64) -> String + Send>;
+
+///Persists the callbacks that wait for the result of a scan job and the outbox of deliveries to them, so no delivery is lost when
+///the API restarts. Implementations have to be safe to use from every worker at once.
+pub trait Outbox: Send + Sync {
+    ///Remembers that a callback waits for the result of a scan job
+    fn add_callback<'a>(&'a self, job_id: &'a str, project_id: u64, url: &'a str, now: i64) -> BoxFuture<'a, Result<(), String>>;
+
+    ///Turns every callback that waits for the result of a scan job into a pending delivery. A callback is only turned into a
+    ///delivery once, even if the result is stored twice.
+    ///
+    /// # Arguments
+    /// job_id: &str - The scan job that completed
+    /// payload: Payload - Builds the body that is posted to the callbacks of a project
+    /// now: i64 - The current unix timestamp
+    ///
+    /// # Returns
+    /// Result<usize, String> - The number of deliveries that have been queued
+    fn enqueue<'a>(&'a self, job_id: &'a str, payload: Payload, now: i64) -> BoxFuture<'a, Result<usize, String>>;
+
+    ///Forgets callbacks that have been waiting since before the given timestamp, as their scan job is never going to complete
+    fn prune_callbacks<'a>(&'a self, before: i64) -> BoxFuture<'a, Result<(), String>>;
+
+    ///Returns the pending deliveries whose next attempt is due, oldest first, and postpones their next attempt to lease_until, so
+    ///no other delivery loop attempts them in the meantime. A delivery whose attempt is never recorded is attempted again then.
+    fn claim_due<'a>(&'a self, now: i64, limit: u32, lease_until: i64) -> BoxFuture<'a, Result<Vec<Delivery>, String>>;
+
+    ///Records an attempt of a delivery
+    ///
+    /// # Arguments
+    /// id: i64 - The delivery that has been attempted
+    /// status: DeliveryStatus - The state of the delivery after the attempt
+    /// status_code: Option<u16> - The HTTP status the callback responded with, if it responded
+    /// error: Option<String> - Why the attempt failed, if it did
+    /// next_attempt_at: i64 - When the delivery is attempted again, if it is still pending
+    /// now: i64 - The current unix timestamp
+    fn record_attempt<'a>(&'a self, id: i64, status: DeliveryStatus, status_code: Option<u16>, error: Option<String>, next_attempt_at: i64, now: i64) -> BoxFuture<'a, Result<(), String>>;
+
+    ///Returns the most recent deliveries of a project, newest first
+    fn list<'a>(&'a self, project_id: u64, status: Option<DeliveryStatus>, limit: u32) -> BoxFuture<'a, Result<Vec<Delivery>, String>>;
+
+    ///Makes a delivery pending again, so it is attempted right away with a fresh number of attempts
+    ///
+    /// # Returns
+    /// Result<Option<Delivery>, String> - The replayed delivery, or none if the project has no delivery with the ID
+    fn replay<'a>(&'a self, id: i64, project_id: u64, now: i64) -> BoxFuture<'a, Result<Option<Delivery>, String>>;
+}
+
+///Keeps the callbacks and the outbox in a SQLite database of this instance
+pub struct SqliteStore {
+    database: Database,
+}
+
+impl SqliteStore {
+    ///Opens the database at the given path and creates its tables, if they don't exist yet
+    pub fn new(path: &Path) -> Result<SqliteStore, String> {
+        let connection = sqlite::open(path, "webhook database", "
+            CREATE TABLE IF NOT EXISTS callbacks (
+                job_id TEXT NOT NULL,
+                project_id INTEGER NOT NULL,
+                url TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (job_id, project_id, url)
+            );
+            CREATE TABLE IF NOT EXISTS deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL,
+                job_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at INTEGER NOT NULL,
+                last_status_code INTEGER,
+                last_error TEXT,
+                created_at INTEGER NOT NULL,
+                delivered_at INTEGER,
+                payload TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS deliveries_due ON deliveries (status, next_attempt_at);
+            CREATE INDEX IF NOT EXISTS deliveries_project ON deliveries (project_id, id);
+        ")?;
+
+        return Ok(SqliteStore { database: Database::new(connection) });
+    }
+}
+
+impl Outbox for SqliteStore {
+    fn add_callback<'a>(&'a self, job_id: &'a str, project_id: u64, url: &'a str, now: i64) -> BoxFuture<'a, Result<(), String>> {
+        let (job_id, url) = (job_id.to_string(), url.to_string());
+
+        return Box::pin(self.database.run(move |connection| connection
+            .execute("INSERT OR IGNORE INTO callbacks (job_id, project_id, url, created_at) VALUES (?1, ?2, ?3, ?4)", params![job_id, project_id as i64, url, now])
+            .map(|_| ())));
+    }
+
+    fn enqueue<'a>(&'a self, job_id: &'a str, payload: Payload, now: i64) -> BoxFuture<'a, Result<usize, String>> {
+        let job_id = job_id.to_string();
+
+        return Box::pin(self.database.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let callbacks = {
+                let mut statement = transaction.prepare("SELECT project_id, url FROM callbacks WHERE job_id = ?1")?;
+                let rows = statement.query_map(params![job_id], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, String>(1)?)))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+
+            for (project_id, url) in &callbacks {
+                transaction.execute(
+                    "INSERT INTO deliveries (project_id, job_id, url, status, next_attempt_at, created_at, payload) VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?6)",
+                    params![*project_id as i64, job_id, url, DeliveryStatus::Pending.as_str(), now, payload(*project_id)])?;
+            }
+
+            transaction.execute("DELETE FROM callbacks WHERE job_id = ?1", params![job_id])?;
+            transaction.commit()?;
+
+            Ok(callbacks.len())
+        }));
+    }
+
+    fn prune_callbacks<'a>(&'a self, before: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(self.database.run(move |connection| connection
+            .execute("DELETE FROM callbacks WHERE created_at < ?1", params![before])
+            .map(|_| ())));
+    }
+
+    fn claim_due<'a>(&'a self, now: i64, limit: u32, lease_until: i64) -> BoxFuture<'a, Result<Vec<Delivery>, String>> {
+        return Box::pin(self.database.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let due = {
+                let mut statement = transaction.prepare(&format!("SELECT {} FROM deliveries WHERE status = ?1 AND next_attempt_at <= ?2 ORDER BY next_attempt_at LIMIT ?3", DELIVERY_COLUMNS))?;
+                let rows = statement.query_map(params![DeliveryStatus::Pending.as_str(), now, limit], read_delivery)?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+
+            for delivery in &due {
+                transaction.execute("UPDATE deliveries SET next_attempt_at = ?2 WHERE id = ?1", params![delivery.id, lease_until])?;
+            }
+
+            transaction.commit()?;
+            Ok(due)
+        }));
+    }
+
+    fn record_attempt<'a>(&'a self, id: i64, status: DeliveryStatus, status_code: Option<u16>, error: Option<String>, next_attempt_at: i64, now: i64) -> BoxFuture<'a, Result<(), String>> {
+        let delivered_at = if status == DeliveryStatus::Delivered { Some(now) } else { None };
+
+        return Box::pin(self.database.run(move |connection| connection
+            .execute("UPDATE deliveries SET status = ?2, attempts = attempts + 1, last_status_code = ?3, last_error = ?4, next_attempt_at = ?5, delivered_at = ?6 WHERE id = ?1",
+                params![id, status.as_str(), status_code, error, next_attempt_at, delivered_at])
+            .map(|_| ())));
+    }
+
+    fn list<'a>(&'a self, project_id: u64, status: Option<DeliveryStatus>, limit: u32) -> BoxFuture<'a, Result<Vec<Delivery>, String>> {
+        return Box::pin(self.database.run(move |connection| {
+            let mut statement = connection.prepare(&format!("SELECT {} FROM deliveries WHERE project_id = ?1 AND (?2 IS NULL OR status = ?2) ORDER BY id DESC LIMIT ?3", DELIVERY_COLUMNS))?;
+            let rows = statement.query_map(params![project_id as i64, status.map(|status| status.as_str()), limit], read_delivery)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        }));
+    }
+
+    fn replay<'a>(&'a self, id: i64, project_id: u64, now: i64) -> BoxFuture<'a, Result<Option<Delivery>, String>> {
+        return Box::pin(self.database.run(move |connection| {
+            connection.execute("UPDATE deliveries SET status = ?3, attempts = 0, next_attempt_at = ?4, delivered_at = NULL WHERE id = ?1 AND project_id = ?2",
+                params![id, project_id as i64, DeliveryStatus::Pending.as_str(), now])?;
+
+            connection.query_row(&format!("SELECT {} FROM deliveries WHERE id = ?1 AND project_id = ?2", DELIVERY_COLUMNS), params![id, project_id as i64], read_delivery)
+                .optional()
+        }));
+    }
+}
+
+const REDIS_CALLBACK_JOBS_KEY: &str = "pamaxie:webhooks:callback_jobs";
+const REDIS_CALLBACKS_PREFIX: &str = "pamaxie:webhooks:callbacks:";
+const REDIS_DELIVERY_IDS_KEY: &str = "pamaxie:webhooks:delivery_ids";
+const REDIS_DELIVERY_PREFIX: &str = "pamaxie:webhooks:delivery:";
+const REDIS_DUE_KEY: &str = "pamaxie:webhooks:due";
+const REDIS_PROJECT_PREFIX: &str = "pamaxie:webhooks:project:";
+
+///Keeps the callbacks and the outbox in Redis, so every instance of the API shares them. The result of a scan is then delivered
+///by whichever instance our workers post it to, and every instance attempts the deliveries that are due.
+///
+///Callbacks of a job are a hash of the JSON encoded [project ID, url] to the time they have been added, and the jobs that have
+///callbacks are a sorted set scored by that time. Deliveries are hashes of their fields, pending ones are a sorted set scored by
+///their next attempt and the deliveries of a project a sorted set scored by their ID.
+pub struct RedisStore {
+    connection: ConnectionManager,
+    enqueue_script: Script,
+    prune_script: Script,
+    claim_script: Script,
+}
+
+impl RedisStore {
+    ///Connects to the Redis server at the given URL
+    pub async fn new(url: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|err| format!("{} is not a valid Redis URL: {}", url, err))?;
+        let connection = ConnectionManager::new(client).await.map_err(|err| format!("Could not connect to Redis at {}: {}", url, err))?;
+
+        //Turns the callbacks it is passed into deliveries, unless another instance took them already. Callbacks are passed as
+        //(field, project ID, url, payload) after the job ID and the current time.
+        let enqueue_script = Script::new(r"
+            local queued = 0
+            for i = 3, #ARGV, 4 do
+                if redis.call('HDEL', KEYS[1], ARGV[i]) == 1 then
+                    local id = redis.call('INCR', KEYS[3])
+                    redis.call('HSET', KEYS[4] .. id, 'project_id', ARGV[i + 1], 'job_id', ARGV[1], 'url', ARGV[i + 2],
+                        'status', 'pending', 'attempts', 0, 'next_attempt_at', ARGV[2], 'created_at', ARGV[2], 'payload', ARGV[i + 3])
+                    redis.call('ZADD', KEYS[5], ARGV[2], id)
+                    redis.call('ZADD', KEYS[6] .. ARGV[i + 1], id, id)
+                    queued = queued + 1
+                end
+            end
+            if redis.call('HLEN', KEYS[1]) == 0 then
+                redis.call('ZREM', KEYS[2], ARGV[1])
+            end
+            return queued
+        ");
+
+        let prune_script = Script::new(r"
+            local jobs = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', '(' .. ARGV[1])
+            for _, job in ipairs(jobs) do
+                redis.call('DEL', KEYS[2] .. job)
+                redis.call('ZREM', KEYS[1], job)
+            end
+            return #jobs
+        ");
+
+        let claim_script = Script::new(r"
+            local ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+            for _, id in ipairs(ids) do
+                redis.call('ZADD', KEYS[1], ARGV[3], id)
+            end
+            return ids
+        ");
+
+        return Ok(RedisStore { connection, enqueue_script, prune_script, claim_script });
+    }
+
+    ///Reads the deliveries with the given IDs, skipping the ones that don't exist or belong to another project
+    async fn get_deliveries(&self, ids: &[i64], project_id: Option<u64>) -> Result<Vec<Delivery>, String> {
+        let mut pipe = redis::pipe();
+
+        for id in ids {
+            pipe.hgetall(format!("{}{}", REDIS_DELIVERY_PREFIX, id));
+        }
+
+        let fields: Vec<HashMap<String, String>> = pipe.query_async(&mut self.connection.clone()).await.map_err(|err| err.to_string())?;
+
+        return Ok(ids.iter().zip(fields)
+            .filter_map(|(id, fields)| read_redis_delivery(*id, &fields))
+            .filter(|delivery| project_id.is_none_or(|project_id| delivery.project_id == project_id))
+            .collect());
+    }
+}
+
+fn read_redis_delivery(id: i64, fields: &HashMap<String, String>) -> Option<Delivery> {
+    let number = |name: &str| fields.get(name).and_then(|value| value.parse::<i64>().ok());
+
+    return Some(Delivery {
+        id,
+        project_id: fields.get("project_id")?.parse().ok()?,
+        job_id: fields.get("job_id")?.to_string(),
+        url: fields.get("url")?.to_string(),
+        status: fields.get("status")?.parse().unwrap_or(DeliveryStatus::Failed),
+        attempts: number("attempts").unwrap_or(0) as u32,
+        next_attempt_at: number("next_attempt_at").unwrap_or(0),
+        last_status_code: number("last_status_code").map(|status_code| status_code as u16),
+        last_error: fields.get("last_error").cloned(),
+        created_at: number("created_at").unwrap_or(0),
+        delivered_at: number("delivered_at"),
+        payload: fields.get("payload").and_then(|payload| serde_json::from_str(payload).ok()).unwrap_or(Value::Null),
+    });
+}
+
+impl Outbox for RedisStore {
+    fn add_callback<'a>(&'a self, job_id: &'a str, project_id: u64, url: &'a str, now: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let field = serde_json::to_string(&(project_id, url)).map_err(|err| err.to_string())?;
+
+            return redis::pipe().atomic()
+                .cmd("HSETNX").arg(format!("{}{}", REDIS_CALLBACKS_PREFIX, job_id)).arg(field).arg(now).ignore()
+                .zadd(REDIS_CALLBACK_JOBS_KEY, job_id, now).ignore()
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string());
+        });
+    }
+
+    fn enqueue<'a>(&'a self, job_id: &'a str, payload: Payload, now: i64) -> BoxFuture<'a, Result<usize, String>> {
+        return Box::pin(async move {
+            let callbacks_key = format!("{}{}", REDIS_CALLBACKS_PREFIX, job_id);
+            let fields: Vec<String> = redis::cmd("HKEYS").arg(&callbacks_key)
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            let mut invocation = self.enqueue_script.prepare_invoke();
+            invocation.key(&callbacks_key).key(REDIS_CALLBACK_JOBS_KEY).key(REDIS_DELIVERY_IDS_KEY)
+                .key(REDIS_DELIVERY_PREFIX).key(REDIS_DUE_KEY).key(REDIS_PROJECT_PREFIX)
+                .arg(job_id).arg(now);
+
+            for field in &fields {
+                let (project_id, url): (u64, String) = match serde_json::from_str(field) {
+                    Ok(callback) => callback,
+                    Err(_) => continue,
+                };
+
+                invocation.arg(field).arg(project_id).arg(url).arg(payload(project_id));
+            }
+
+            return invocation.invoke_async(&mut self.connection.clone()).await.map_err(|err| err.to_string());
+        });
+    }
+
+    fn prune_callbacks<'a>(&'a self, before: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let _: u64 = self.prune_script.key(REDIS_CALLBACK_JOBS_KEY).key(REDIS_CALLBACKS_PREFIX).arg(before)
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(());
+        });
+    }
+
+    fn claim_due<'a>(&'a self, now: i64, limit: u32, lease_until: i64) -> BoxFuture<'a, Result<Vec<Delivery>, String>> {
+        return Box::pin(async move {
+            let ids: Vec<i64> = self.claim_script.key(REDIS_DUE_KEY).arg(now).arg(limit).arg(lease_until)
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            let deliveries = self.get_deliveries(&ids, None).await?;
+            return Ok(deliveries.into_iter().filter(|delivery| delivery.status == DeliveryStatus::Pending).collect());
+        });
+    }
+
+    fn record_attempt<'a>(&'a self, id: i64, status: DeliveryStatus, status_code: Option<u16>, error: Option<String>, next_attempt_at: i64, now: i64) -> BoxFuture<'a, Result<(), String>> {
+        return Box::pin(async move {
+            let key = format!("{}{}", REDIS_DELIVERY_PREFIX, id);
+            let mut pipe = redis::pipe();
+            pipe.atomic()
+                .hincr(&key, "attempts", 1).ignore()
+                .hset(&key, "status", status.as_str()).ignore()
+                .hset(&key, "next_attempt_at", next_attempt_at).ignore();
+
+            match status_code {
+                Some(status_code) => pipe.hset(&key, "last_status_code", status_code).ignore(),
+                None => pipe.hdel(&key, "last_status_code").ignore(),
+            };
+
+            match error {
+                Some(error) => pipe.hset(&key, "last_error", error).ignore(),
+                None => pipe.hdel(&key, "last_error").ignore(),
+            };
+
+            match status {
+                DeliveryStatus::Pending => pipe.zadd(REDIS_DUE_KEY, id, next_attempt_at).ignore(),
+                _ => pipe.zrem(REDIS_DUE_KEY, id).ignore(),
+            };
+
+            match status {
+                DeliveryStatus::Delivered => pipe.hset(&key, "delivered_at", now).ignore(),
+                _ => pipe.hdel(&key, "delivered_at").ignore(),
+            };
+
+            return pipe.query_async(&mut self.connection.clone()).await.map_err(|err| err.to_string());
+        });
+    }
+
+    fn list<'a>(&'a self, project_id: u64, status: Option<DeliveryStatus>, limit: u32) -> BoxFuture<'a, Result<Vec<Delivery>, String>> {
+        return Box::pin(async move {
+            let key = format!("{}{}", REDIS_PROJECT_PREFIX, project_id);
+            let mut deliveries = Vec::new();
+            let mut offset: u64 = 0;
+
+            //Deliveries aren't indexed by their status, so we page through the newest ones until enough of them have it
+            while deliveries.len() < limit as usize {
+                let ids: Vec<i64> = redis::cmd("ZREVRANGE").arg(&key).arg(offset).arg(offset + limit as u64 - 1)
+                    .query_async(&mut self.connection.clone()).await
+                    .map_err(|err| err.to_string())?;
+
+                if ids.is_empty() {
+                    break;
+                }
+
+                offset += ids.len() as u64;
+                deliveries.extend(self.get_deliveries(&ids, Some(project_id)).await?.into_iter()
+                    .filter(|delivery| status.is_none_or(|status| delivery.status == status)));
+            }
+
+            deliveries.truncate(limit as usize);
+            return Ok(deliveries);
+        });
+    }
+
+    fn replay<'a>(&'a self, id: i64, project_id: u64, now: i64) -> BoxFuture<'a, Result<Option<Delivery>, String>> {
+        return Box::pin(async move {
+            if self.get_deliveries(&[id], Some(project_id)).await?.is_empty() {
+                return Ok(None);
+            }
+
+            let key = format!("{}{}", REDIS_DELIVERY_PREFIX, id);
+            let _: () = redis::pipe().atomic()
+                .hset(&key, "status", DeliveryStatus::Pending.as_str()).ignore()
+                .hset(&key, "attempts", 0).ignore()
+                .hset(&key, "next_attempt_at", now).ignore()
+                .hdel(&key, "delivered_at").ignore()
+                .zadd(REDIS_DUE_KEY, id, now).ignore()
+                .query_async(&mut self.connection.clone()).await
+                .map_err(|err| err.to_string())?;
+
+            return Ok(self.get_deliveries(&[id], Some(project_id)).await?.pop());
+        });
+    }
+}
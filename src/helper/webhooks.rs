@@ -0,0 +1,245 @@
+use std::{sync::Arc, time::Duration};
+use actix_web::web;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use time::OffsetDateTime;
+use reqwest::{redirect, Url};
+use tokio::{sync::Notify, time::sleep};
+use crate::config::{self, WebhookBackend, WebhookConfig};
+use super::scan_error::ScanError;
+use super::url_fetcher::UrlFetcher;
+use super::webhook_outbox::{Delivery, DeliveryStatus, Outbox, RedisStore, SqliteStore};
+
+///Event that is sent when the result of a scan job has been stored
+const SCAN_COMPLETED_EVENT: &str = "scan.completed";
+///Time between checks for deliveries whose next attempt is due
+const DELIVERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+///Most deliveries that are attempted at once
+const DELIVERY_BATCH_SIZE: u32 = 20;
+///Seconds a callback waits for the result of its scan job before it is forgotten
+const CALLBACK_RETENTION_SECS: i64 = 24 * 3600;
+///Seconds a delivery loop may take to attempt a delivery beyond the timeout of the callback, before the delivery is attempted again
+const DELIVERY_LEASE_MARGIN_SECS: i64 = 60;
+
+///Returns the current unix timestamp in seconds
+fn now() -> i64 {
+    return OffsetDateTime::now_utc().unix_timestamp();
+}
+
+///Signs the body of a delivery. Callbacks verify the signature by computing the HMAC-SHA256 of "{timestamp}.{body}" with the
+///signing secret, comparing it to the v1 value of the X-Pamaxie-Signature header and rejecting old timestamps.
+///
+/// # Returns
+/// String - The value of the X-Pamaxie-Signature header, formatted as t={timestamp},v1={hex encoded signature}
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    return format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()));
+}
+
+///Posts the results of completed scan jobs to the callbacks that wait for them. Deliveries are signed with HMAC-SHA256, kept in a
+///persistent outbox and retried with an exponential backoff until the callback accepts them or they run out of attempts. With the
+///redis backend the outbox is shared, so callbacks registered on one instance are delivered when the result is posted to another.
+///
+///As callback URLs are chosen by our clients, they are held to the network rules of our URL fetcher, both when they are
+///registered and when a delivery is sent. Deliveries connect to the address that has been checked and don't follow redirects.
+pub struct Webhooks {
+    config: WebhookConfig,
+    outbox: Arc<dyn Outbox>,
+    fetcher: web::Data<UrlFetcher>,
+    ///Wakes the delivery loop up when new deliveries have been queued
+    queued: Notify,
+}
+
+impl Webhooks {
+    ///Creates the webhooks and opens their outbox
+    ///
+    /// # Returns
+    /// Result<Webhooks, String> - The webhooks or why their outbox could not be opened
+    pub async fn new(config: &WebhookConfig, fetcher: web::Data<UrlFetcher>) -> Result<Webhooks, String> {
+        let outbox: Arc<dyn Outbox> = match config.backend {
+            WebhookBackend::Sqlite => Arc::new(SqliteStore::new(&config.database_path)?),
+            WebhookBackend::Redis => Arc::new(RedisStore::new(&config.redis_url).await?),
+        };
+
+        return Ok(Webhooks { config: config.clone(), outbox, fetcher, queued: Notify::new() });
+    }
+
+    ///Returns true if a signing secret has been configured, without which we don't send any webhooks
+    pub fn is_enabled(&self) -> bool {
+        return !self.config.signing_secret.get().is_empty();
+    }
+
+    ///Returns the outbox of our deliveries
+    pub fn outbox(&self) -> &dyn Outbox {
+        return self.outbox.as_ref();
+    }
+
+    ///Checks a callback URL sent by a client, which has to be a http(s) URL whose host only resolves to public addresses
+    pub async fn check_callback_url(&self, callback_url: &str) -> Result<(), ScanError> {
+        if !self.is_enabled() {
+            return Err(ScanError::InvalidRequest("Callbacks are not enabled on this API.".to_string()));
+        }
+
+        let url = Url::parse(callback_url).ok().filter(|_| config::is_http_url(callback_url))
+            .ok_or_else(|| ScanError::InvalidRequest("Please specify the callback_url as an absolute http(s) URL.".to_string()))?;
+
+        self.fetcher.resolve_public(&url).await
+            .map_err(|_| ScanError::UrlNotAllowed("Results can't be posted to the callback_url, as its host can't be resolved or resolves to a private or internal address.".to_string()))?;
+
+        return Ok(());
+    }
+
+    ///Registers the callbacks that wait for the result of a scan job: the one passed with the request and the one configured
+    ///for the project. Failures are only logged, as they must not fail the scan.
+    pub async fn register(&self, job_id: &str, project_id: u64, callback_url: Option<&str>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let project_url = self.config.projects.get(&project_id.to_string()).map(|project| project.callback_url.as_str());
+
+        for url in callback_url.into_iter().chain(project_url) {
+            if let Err(err) = self.outbox.add_callback(job_id, project_id, url, now()).await {
+                eprintln!("Could not register the callback of project {} for job {}. {}", project_id, job_id, err);
+            }
+        }
+    }
+
+    ///Queues a delivery of the result of a scan job to every callback that waits for it
+    pub async fn scan_completed(&self, job_id: &str, result: &Value) {
+        let (payload_job_id, result) = (job_id.to_string(), result.clone());
+        let payload = Box::new(move |project_id: u64| json!({
+            "event": SCAN_COMPLETED_EVENT,
+            "jobId": payload_job_id,
+            "projectId": project_id,
+            "result": result,
+        }).to_string());
+
+        match self.outbox.enqueue(job_id, payload, now()).await {
+            Ok(0) => {},
+            Ok(_) => self.queued.notify_one(),
+            Err(err) => eprintln!("Could not queue the webhook deliveries of job {}. {}", job_id, err),
+        }
+    }
+
+    ///Wakes the delivery loop up, e.g. after a delivery has been replayed
+    pub fn wake(&self) {
+        self.queued.notify_one();
+    }
+
+    ///Returns the time to wait before the next attempt after the given number of failed attempts. The exponential delay is
+    ///jittered by up to a tenth of it, so deliveries that failed together are not retried at the same moment.
+    fn retry_delay(&self, attempts: u32) -> i64 {
+        let delay = self.config.retry_base_delay.saturating_mul(2u64.saturating_pow(attempts.saturating_sub(1))).min(self.config.retry_max_delay);
+        let jitter = rand::thread_rng().gen_range(0..=delay / 10);
+
+        return (delay + jitter) as i64;
+    }
+
+    ///Posts a delivery to its callback. The address of the callback is checked again, as its host might resolve to another address
+    ///by now, and the connection is pinned to the checked address.
+    ///
+    /// # Returns
+    /// (Option<u16>, Option<String>) - The HTTP status the callback responded with, if it responded, and why the attempt failed.
+    /// The reason never contains what the callback sent or the errors of our HTTP client, as clients read it back.
+    async fn post(&self, delivery: &Delivery) -> (Option<u16>, Option<String>) {
+        let url = match Url::parse(&delivery.url) {
+            Ok(url) => url,
+            Err(_) => return (None, Some("The callback url is not a valid url.".to_string())),
+        };
+
+        let (host, address) = match self.fetcher.resolve_public(&url).await {
+            Ok(resolved) => resolved,
+            Err(ScanError::UrlNotAllowed(_)) => return (None, Some("The callback url resolves to a private or internal address.".to_string())),
+            Err(_) => return (None, Some("The host of the callback url could not be resolved.".to_string())),
+        };
+
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .no_proxy()
+            .timeout(Duration::from_secs(self.config.timeout))
+            .resolve(&host, address)
+            .build();
+
+        let client = match client {
+            Ok(client) => client,
+            Err(_) => return (None, Some("The callback could not be reached.".to_string())),
+        };
+
+        let body = delivery.payload.to_string();
+        let signature = sign(&self.config.signing_secret.get(), now(), &body);
+
+        let response = client.post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Pamaxie-Event", SCAN_COMPLETED_EVENT)
+            .header("X-Pamaxie-Delivery", delivery.id)
+            .header("X-Pamaxie-Signature", signature)
+            .body(body)
+            .send().await;
+
+        return match response {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("The callback responded with status {}.", response.status().as_u16()))),
+            Err(err) if err.is_timeout() => (None, Some(format!("The callback did not respond within {} seconds.", self.config.timeout))),
+            Err(err) if err.is_connect() => (None, Some("Could not connect to the callback.".to_string())),
+            Err(_) => (None, Some("The callback could not be reached.".to_string())),
+        };
+    }
+
+    ///Attempts a single delivery and records its outcome in the outbox
+    async fn deliver(&self, delivery: Delivery) {
+        let (status_code, error) = self.post(&delivery).await;
+
+        let attempts = delivery.attempts + 1;
+        let status = match error {
+            None => DeliveryStatus::Delivered,
+            Some(_) if attempts >= self.config.max_attempts => DeliveryStatus::Failed,
+            Some(_) => DeliveryStatus::Pending,
+        };
+
+        if status == DeliveryStatus::Failed {
+            eprintln!("Giving up on webhook delivery {} to {} after {} attempts.", delivery.id, delivery.url, attempts);
+        }
+
+        if let Err(err) = self.outbox.record_attempt(delivery.id, status, status_code, error, now() + self.retry_delay(attempts), now()).await {
+            eprintln!("Could not record an attempt of webhook delivery {}. {}", delivery.id, err);
+        }
+    }
+}
+
+///Attempts the deliveries of the outbox whose next attempt is due, until the API shuts down
+pub async fn deliver_periodically(webhooks: web::Data<Webhooks>) {
+    loop {
+        if let Err(err) = webhooks.outbox.prune_callbacks(now() - CALLBACK_RETENTION_SECS).await {
+            eprintln!("Could not forget expired webhook callbacks. {}", err);
+        }
+
+        let lease_until = now() + webhooks.config.timeout as i64 + DELIVERY_LEASE_MARGIN_SECS;
+
+        let due = match webhooks.outbox.claim_due(now(), DELIVERY_BATCH_SIZE, lease_until).await {
+            Ok(due) => due,
+            Err(err) => {
+                eprintln!("Could not read the due webhook deliveries. {}", err);
+                Vec::new()
+            }
+        };
+
+        let is_full_batch = due.len() as u32 == DELIVERY_BATCH_SIZE;
+        join_all(due.into_iter().map(|delivery| webhooks.deliver(delivery))).await;
+
+        //More deliveries might be due already
+        if is_full_batch {
+            continue;
+        }
+
+        tokio::select! {
+            _ = sleep(DELIVERY_POLL_INTERVAL) => {},
+            _ = webhooks.queued.notified() => {},
+        }
+    }
+}
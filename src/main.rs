@@ -1,13 +1,41 @@
+#![allow(clippy::needless_return)]
+
 pub(crate) use actix_web::{App, HttpServer, web};
-use helper::sqs_helpers;
+use config::{CliArgs, Command, Config};
+use structopt::StructOpt;
 use tokio::time::sleep;
-use std::{thread, process::exit, string::String, time::{Duration, Instant}, sync::{Mutex}};
-use crate::helper::{s3_helpers, web_helper};
-use lazy_static::lazy_static;
+use std::{process::exit, time::Duration};
+use crate::helper::secrets;
+use crate::helper::token_verifier::TokenVerifier;
+use crate::helper::auth::{AuthLevel, Authentication};
+use crate::helper::auth_cache::AuthCache;
+use crate::helper::rate_limit::{RateLimiter, RateLimiting};
+use crate::helper::usage::UsageMeter;
+use crate::helper::request_id::RequestId;
+use crate::helper::job_registry::JobRegistry;
+use crate::helper::scan_events::ScanEvents;
+use crate::helper::batch_registry::BatchRegistry;
+use crate::helper::url_fetcher::UrlFetcher;
+use crate::helper::image_decoder::ImageDecoder;
+use crate::helper::hash_index::{self, HashIndex};
+use crate::helper::hash_lists::{self, HashLists};
+use crate::helper::model_versions::{self, ModelVersions};
+use crate::helper::webhooks::{self, Webhooks};
+use crate::helper::token_manager::{self, TokenManager};
+
+mod config;
+mod check_config;
 
 mod services {
     pub mod file_recognition_service;
     pub mod worker_service;
+    pub mod admin_service;
+    pub mod usage_service;
+    pub mod webhook_service;
+    pub mod scan_stream_service;
+    pub mod batch_service;
+    pub mod lookup_service;
+    pub mod hash_list_service;
 }
 
 mod helper {
@@ -16,156 +44,190 @@ mod helper {
     pub mod s3_helpers;
     pub mod db_api_helper;
     pub mod sqs_helpers;
+    pub mod secrets;
+    pub mod token_verifier;
+    pub mod auth;
+    pub mod ttl_map;
+    pub mod auth_cache;
+    pub mod token_manager;
+    pub mod rate_limit;
+    pub mod rate_limit_store;
+    pub mod usage;
+    pub mod usage_store;
+    pub mod scan_error;
+    pub mod request_id;
+    pub mod job_registry;
+    pub mod webhook_outbox;
+    pub mod webhooks;
+    pub mod scan_events;
+    pub mod batch_registry;
+    pub mod detection_body;
+    pub mod url_fetcher;
+    pub mod upload;
+    pub mod image_decoder;
+    pub mod preprocessing;
+    pub mod perceptual_hash;
+    pub mod bk_tree;
+    pub mod hash_index;
+    pub mod hash_index_store;
+    pub mod hash_lists;
+    pub mod hash_list_store;
+    pub mod model_versions;
+    pub mod sqlite;
 }
 
-lazy_static! {
-    pub static ref JWT_TOKEN: Mutex<String> = Mutex::new("".to_string());
-}
-
-///Retrieves the Refresh Token from the Database API
-fn get_refresh_token() {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async {
-        let wait_time = Duration::from_secs(3600);
-        loop  {
-            let start = Instant::now();
-            eprintln!("Token Refresh Scheduler starting at {:?}", start);
-
-            if web_helper::get_pam_auth_token().is_empty() {
-                panic!("We encountered an empty API token. This should normally never happen and should've been caught at the start of the application.\
-                Please ensure that the Environment variables are not changed while the application is running to prevent data loss");
-            }
-
-            let mut lock = JWT_TOKEN.try_lock();
-            if let Ok(ref mut mutex) = lock {
-                let token = web_helper::get_pam_token().await;
-
-                if token.is_some() {
-                    mutex.clear();
-                    mutex.push_str(token.unwrap().as_str());
-                    eprintln!("JWT was {}. \rWe successfully set it to the global value.", mutex.to_string());
-                }else{
-                    eprintln!("We could not successfully get a token. Please ensure all environment variables are set correctly.");
-                }
-
-                std::mem::drop(lock);
-            } else {
-                eprintln!("Could not lock JWT token. Retrying later.");
-            }
-
-
-
-            let runtime = start.elapsed();
-            if let Some(remaining) = wait_time.checked_sub(runtime) {
-                
-                eprintln!(
-                    "JWT refresh schedule slice has time left over; sleeping for {:?} seconds",
-                    remaining.as_secs()
-                );
-
-                sleep(remaining).await;
-            }
-        }
-    });
+///Periodically reloads the keys used to verify our clients' JWT bearer tokens
+async fn refresh_jwt_keys(verifier: web::Data<TokenVerifier>, interval: Duration) {
+    loop {
+        sleep(interval).await;
+        verifier.refresh_keys().await;
+    }
 }
 
 ///Starts the application
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    validate_client_configuration();
-    let port: u16 = std::env::var("SCAN_API_PORT").unwrap_or("8080".to_string()).parse().unwrap();
+    let args = CliArgs::from_args();
+
+    if let Some(Command::CheckConfig) = args.command {
+        exit(check_config::run(&args).await);
+    }
+
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_CONFIG_INVALID);
+        }
+    };
 
-    let _scheduler = thread::spawn(|| { get_refresh_token()});
+    let verifier = match TokenVerifier::new(&config.jwt).await {
+        Ok(verifier) => web::Data::new(verifier),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_JWT_KEYS_INVALID);
+        }
+    };
+
+    let rate_limiter = match RateLimiter::new(&config.rate_limit).await {
+        Ok(rate_limiter) => web::Data::new(rate_limiter),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_RATE_LIMIT_STORE_UNREACHABLE);
+        }
+    };
 
-    HttpServer::new(|| {
-        App::new().app_data(web::PayloadConfig::new(1000000 * 250))
+    let usage = match UsageMeter::new(&config.usage).await {
+        Ok(usage) => web::Data::new(usage),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_USAGE_STORE_UNAVAILABLE);
+        }
+    };
+
+    let port = config.port;
+    actix_web::rt::spawn(secrets::watch_secrets(config.secrets(), Duration::from_secs(config.secret_reload_interval)));
+    actix_web::rt::spawn(refresh_jwt_keys(verifier.clone(), Duration::from_secs(config.jwt.jwks_refresh_interval)));
+
+    let auth_cache = web::Data::new(AuthCache::new(&config.auth_cache));
+    let http_client = web::Data::new(reqwest::Client::new());
+    let tokens = web::Data::new(TokenManager::new(&config, http_client.get_ref().clone()));
+    actix_web::rt::spawn(token_manager::refresh_periodically(tokens.clone()));
+
+    let fetcher = web::Data::new(UrlFetcher::new(&config.url_fetch));
+    let webhooks = match Webhooks::new(&config.webhooks, fetcher.clone()).await {
+        Ok(webhooks) => web::Data::new(webhooks),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_WEBHOOK_OUTBOX_UNAVAILABLE);
+        }
+    };
+    actix_web::rt::spawn(webhooks::deliver_periodically(webhooks.clone()));
+
+    let hashes = match HashIndex::new(&config.hashing).await {
+        Ok(hashes) => web::Data::new(hashes),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_HASH_INDEX_UNAVAILABLE);
+        }
+    };
+    actix_web::rt::spawn(hash_index::watch_changes(hashes.clone(), Duration::from_secs(config.hashing.reload_interval)));
+
+    let lists = match HashLists::new(&config.hash_lists).await {
+        Ok(lists) => web::Data::new(lists),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_HASH_LISTS_UNAVAILABLE);
+        }
+    };
+    actix_web::rt::spawn(hash_lists::watch_changes(lists.clone(), Duration::from_secs(config.hash_lists.reload_interval)));
+
+    let models = match ModelVersions::new(&config.models) {
+        Ok(models) => web::Data::new(models),
+        Err(err) => {
+            println!("{}", err);
+            exit(check_config::EXIT_MODEL_MANIFEST_INVALID);
+        }
+    };
+    actix_web::rt::spawn(model_versions::watch_manifest(models.clone(), Duration::from_secs(config.models.manifest_reload_interval)));
+
+    let jobs = web::Data::new(JobRegistry::new());
+    let events = web::Data::new(ScanEvents::new());
+    let batches = web::Data::new(BatchRegistry::new());
+    let decoder = web::Data::new(ImageDecoder::new(&config.images));
+    let config = web::Data::new(config);
+
+    HttpServer::new(move || {
+        App::new().app_data(web::PayloadConfig::new(config.uploads.default_max_bytes))
+                .app_data(config.clone())
+                .app_data(verifier.clone())
+                .app_data(auth_cache.clone())
+                .app_data(http_client.clone())
+                .app_data(tokens.clone())
+                .app_data(rate_limiter.clone())
+                .app_data(usage.clone())
+                .app_data(jobs.clone())
+                .app_data(webhooks.clone())
+                .app_data(events.clone())
+                .app_data(batches.clone())
+                .app_data(fetcher.clone())
+                .app_data(decoder.clone())
+                .app_data(hashes.clone())
+                .app_data(lists.clone())
+                .app_data(models.clone())
+                //Registered before Authentication so it runs after it, as it needs the authenticated client
+                //Batches charge every item to the rate limits themselves, so a single request can't scan hundreds of images
+                .wrap(RateLimiting::new()
+                    .route("/scan/v1/detection/")
+                    .exclude("/scan/v1/detection/batch"))
+                .wrap(Authentication::new()
+                    .route("/scan/v1/status", AuthLevel::Public)
+                    .route("/scan/v1/ready", AuthLevel::Public)
+                    .route("/scan/v1/detection/", AuthLevel::Authenticated)
+                    .route("/scan/v1/worker/", AuthLevel::Internal)
+                    .route("/scan/v1/admin/", AuthLevel::Internal))
+                //Registered last so it runs first and every response, including rejections, carries the request ID
+                .wrap(RequestId)
                 .service(services::file_recognition_service::check_api)
+                .service(services::file_recognition_service::check_ready)
                 .service(services::file_recognition_service::detect)
                 .service(services::file_recognition_service::detect_image)
                 .service(services::file_recognition_service::detect_img_from_url)
+                .service(services::file_recognition_service::get_result)
+                .service(services::scan_stream_service::stream_status)
+                .service(services::batch_service::detect_batch)
+                .service(services::batch_service::get_batch)
+                .service(services::lookup_service::lookup)
                 .service(services::worker_service::get_work)
                 .service(services::worker_service::post_work)
                 .service(services::worker_service::get_image)
+                .service(services::admin_service::evict_auth_cache)
+                .service(services::admin_service::clear_auth_cache)
+                .service(services::usage_service::get_usage)
+                .service(services::webhook_service::get_deliveries)
+                .service(services::webhook_service::replay_delivery)
+                .service(services::hash_list_service::get_entries)
+                .service(services::hash_list_service::add_entries)
+                .service(services::hash_list_service::remove_entry)
     }).bind(("0.0.0.0", port))?.run().await
 }
-
-///Validates the client configuration
-fn validate_client_configuration() {
-    let mut error_data = format!("");
-    let mut has_error = false;
-
-    if s3_helpers::get_s3_access_key().is_empty() {
-        has_error = true;
-        error_data = format!("{}The S3_ACCESS_KEY_ID enviorement variable has not been set. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\n", error_data)
-    }
-
-    if s3_helpers::get_s3_secret_key().is_empty() {
-        has_error = true;
-        error_data = format!("{}The S3_ACCESS_KEY_SECRET enviorement variable has not been set. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if s3_helpers::get_s3_bucket().is_empty() {
-        has_error = true;
-        error_data = format!("{}The S3_BUCKET_NAME enviorement variable has not been set. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if s3_helpers::get_s3_url().is_empty() {
-        has_error = true;
-        error_data = format!("{}The S3_URL enviorement variable has not been set. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if s3_helpers::get_s3_region().is_empty() {
-        println!("{}The S3_STORAGE_REGION enviorement variable has not been set. If this was intentional you can ignore this warning.\n", error_data)
-    }
-
-    if web_helper::get_pam_auth_token().is_empty() {
-        has_error = true;
-        error_data = format!("{}The PAM_AUTH_TOKEN enviorement variable is empty. This enviorement variable is required to be set, for our API.\
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if web_helper::get_pam_url().is_empty() {
-        has_error = true;
-        error_data = format!("{}The BASE_URL URL enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if web_helper::get_pam_db_url().is_empty(){
-        has_error = true;
-        error_data = format!("{}The DB_API_URL enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if sqs_helpers::get_aws_access_key().is_empty() {
-        has_error = true;
-        error_data = format!("{}The AWS_ACCESS_KEY_ID enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if sqs_helpers::get_aws_secret_access_key().is_empty() {
-        has_error = true;
-        error_data = format!("{}The AWS_SECRET_ACCESS_KEY enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if sqs_helpers::get_aws_sqs_queue_url().is_empty() {
-        has_error = true;
-        error_data = format!("{}The AWS_SQS_QUEUE_URL_0 enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if sqs_helpers::get_aws_default_region().is_empty() {
-        has_error = true;
-        error_data = format!("{}The AWS_DEFAULT_REGION enviorement variable is empty. This enviorement variable is required to be set, for our API. \
-        Please refer to our documentation to see how to set this environment variable.\r\n", error_data);
-    }
-
-    if has_error {
-        println!("{}", error_data);
-        exit(-501);
-    }
-}
@@ -0,0 +1,43 @@
+use actix_web::{delete, HttpResponse, web};
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::auth_cache::{AuthCache, TokenHash};
+use crate::helper::scan_error::ScanError;
+
+///Removes the cached authentication decision of a token, so a revoked token is rejected right away. Every instance caches
+///decisions on its own and this only evicts the decision of the instance that receives the request, so it has to be sent to
+///every instance. Instances it doesn't reach keep accepting the token for up to `auth_cache.positive_ttl` seconds.
+/// 
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request, which has to be one of pamaxie's internal clients
+/// token_hash: web::Path<String> - The hex encoded SHA-256 hash of the revoked bearer token
+/// 
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[delete("scan/v1/admin/auth_cache/{token_hash}")]
+pub async fn evict_auth_cache(client: AuthenticatedClient, auth_cache: web::Data<AuthCache>, token_hash: web::Path<String>) -> Result<HttpResponse, ScanError> {
+    client.require_internal()?;
+
+    let mut hash: TokenHash = [0; 32];
+
+    if hex::decode_to_slice(token_hash.as_str(), &mut hash).is_err() {
+        return Err(ScanError::InvalidRequest("Please specify the token as the hex encoded SHA-256 hash of the bearer token.".to_string()));
+    }
+
+    let evicted = auth_cache.evict(&hash);
+    return Ok(HttpResponse::Ok().json(serde_json::json!({ "evicted": evicted })));
+}
+
+///Removes every cached authentication decision of the instance that receives the request, see `evict_auth_cache`
+/// 
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request, which has to be one of pamaxie's internal clients
+/// 
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[delete("scan/v1/admin/auth_cache")]
+pub async fn clear_auth_cache(client: AuthenticatedClient, auth_cache: web::Data<AuthCache>) -> Result<HttpResponse, ScanError> {
+    client.require_internal()?;
+
+    auth_cache.clear();
+    return Ok(HttpResponse::Ok().finish());
+}
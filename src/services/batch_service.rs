@@ -0,0 +1,286 @@
+use std::{future::ready, time::Duration};
+use actix_multipart::Multipart;
+use actix_web::{get, post, HttpMessage, HttpRequest, HttpResponse, web};
+use actix_web::web::Bytes;
+use futures::{StreamExt, TryStreamExt, stream};
+use serde::Deserialize;
+use crate::helper::misc;
+use crate::helper::auth::AuthenticatedClient;
+use crate::config::UploadConfig;
+use crate::helper::detection_body::{DetectionInput, RequestOptions};
+use crate::helper::upload::{self, Upload};
+use crate::helper::batch_registry::{BatchItem, BatchRegistry, BatchStatus, ItemStatus};
+use crate::helper::scan_error::{ErrorDetail, ScanError};
+use crate::helper::rate_limit::{RateLimitDecision, RateLimiter, ScanSlot};
+
+use super::file_recognition_service::{self, ResponseMode, ScanOptions, ScanOutcome, ScanServices};
+use super::worker_service::MAX_RESULT_WAIT;
+
+///Seconds a client is asked to wait before polling a batch job again
+const BATCH_POLL_RETRY_AFTER_SECS: u64 = 5;
+
+///A single item of a batch, as sent by the client
+struct BatchEntry {
+    id: Option<String>,
+    input: DetectionInput,
+    ///The bytes the client sent for the item, which are metered like the body of a single detection request
+    size: usize,
+}
+
+///A single line of an NDJSON batch. Either the URL of an image or the base64 encoded image has to be set.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NdjsonItem {
+    id: Option<String>,
+    url: Option<String>,
+    data: Option<String>,
+}
+
+///API endpoint, that scans many images with a single request. Each item is scanned like a single image sent to our other
+///detection endpoints, a number of them at once, and the result or error of every item is returned in the order they have
+///been sent in.
+///
+///The batch is either sent as multipart/form-data, where every `url` field holds the URL of an image and every other field holds
+///an image named by its filename, or as NDJSON, where every line is an object with the `url` or the base64 encoded `data` of an
+///image and an optional `id`.
+///
+///Batches with more than `batch.max_sync_items` items, or sent with `Prefer: respond-async`, are processed in the background.
+///They are answered with 202 Accepted and a batch job, whose results can be polled at the URL in the Location header.
+///
+///Every item is charged to the rate limits, concurrent scans and usage of the project like a single detection request. Items of
+///synchronous batches fail with the error of the limit they exceed, while background batches wait until the limits allow them.
+///
+/// # Arguments
+/// req: HttpRequest - The request, whose headers and query hold the options of the scans
+/// client: AuthenticatedClient - The client that sent the request
+/// services: ScanServices - The services the items are scanned and metered with
+/// batches: web::Data<BatchRegistry> - The registry batch jobs are submitted to
+/// payload: web::Payload - The body of the request
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[post("scan/v1/detection/batch")]
+pub async fn detect_batch(req: HttpRequest, client: AuthenticatedClient, services: ScanServices, batches: web::Data<BatchRegistry>, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let options = file_recognition_service::get_scan_options(&req, &services.webhooks, RequestOptions::default()).await?;
+    let config = services.config.batch.clone();
+    let entries = read_batch(&req, payload, &services.config.uploads, config.max_items).await?;
+
+    if entries.is_empty() {
+        return Err(ScanError::MissingBody);
+    }
+
+    let respond_async = matches!(options.mode, ResponseMode::Async(_));
+
+    if !respond_async && entries.len() <= config.max_sync_items {
+        let item_options = ScanOptions { mode: ResponseMode::Async(Duration::from_secs(config.item_wait).min(MAX_RESULT_WAIT)), callback_url: options.callback_url };
+
+        let items: Vec<BatchItem> = stream::iter(entries.into_iter().enumerate())
+            .map(|(index, entry)| scan_entry(&services, &client, &item_options, index, entry, false))
+            .buffered(config.concurrency)
+            .collect().await;
+
+        return Ok(HttpResponse::Ok().json(BatchStatus::new(None, items)));
+    }
+
+    let queued: Vec<BatchItem> = entries.iter().enumerate().map(|(index, entry)| BatchItem::new(index, entry.id.clone())).collect();
+    let batch_id = batches.submit(client.project_id, queued.clone());
+
+    let item_options = ScanOptions { mode: ResponseMode::Async(MAX_RESULT_WAIT), callback_url: options.callback_url };
+    let background_batch_id = batch_id.to_string();
+    let background_services = services.clone();
+    let background_batches = batches.clone();
+
+    actix_web::rt::spawn(async move {
+        stream::iter(entries.into_iter().enumerate())
+            .map(|(index, entry)| scan_entry(&background_services, &client, &item_options, index, entry, true))
+            .buffer_unordered(config.concurrency)
+            .for_each(|item| {
+                background_batches.complete_item(&background_batch_id, item);
+                ready(())
+            })
+            .await;
+    });
+
+    let mut response = HttpResponse::Accepted();
+    response.append_header(("Location", format!("{}/scan/v1/detection/batch/{}", services.config.base_url, batch_id)))
+        .append_header(("Retry-After", BATCH_POLL_RETRY_AFTER_SECS));
+
+    if respond_async {
+        response.append_header(("Preference-Applied", "respond-async"));
+    }
+
+    return Ok(response.json(BatchStatus::new(Some(batch_id), queued)));
+}
+
+///API endpoint, that returns the status of a batch job and the outcome of every item that has been scanned so far
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// batches: web::Data<BatchRegistry> - The registry the batch job has been submitted to
+/// batch_id: web::Path<String> - The ID of the batch job, as returned in the Location header of our 202 Accepted responses
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/detection/batch/{batch_id}")]
+pub async fn get_batch(client: AuthenticatedClient, batches: web::Data<BatchRegistry>, batch_id: web::Path<String>) -> Result<HttpResponse, ScanError> {
+    let status = batches.get_status(&batch_id, client.project_id).ok_or(ScanError::BatchNotFound)?;
+
+    let mut response = HttpResponse::Ok();
+
+    if status.completed < status.total {
+        response.append_header(("Retry-After", BATCH_POLL_RETRY_AFTER_SECS));
+    }
+
+    return Ok(response.json(status));
+}
+
+///Scans a single item of a batch, while holding a scan slot of the project
+async fn scan_entry(services: &ScanServices, client: &AuthenticatedClient, options: &ScanOptions, index: usize, entry: BatchEntry, wait: bool) -> BatchItem {
+    let mut item = BatchItem::new(index, entry.id);
+
+    let outcome = match take_scan_slot(&services.limiter, client.project_id, wait).await {
+        Ok(_slot) => scan_input(services, client, options, entry.input, entry.size).await,
+        Err(err) => Err(err),
+    };
+
+    match outcome {
+        Ok(ScanOutcome::Done(result, _)) => {
+            item.status = ItemStatus::Done;
+            item.result = misc::get_json_value(&result);
+        },
+        Ok(ScanOutcome::Pending(job_id)) => {
+            item.status = ItemStatus::Pending;
+            item.job_id = Some(job_id);
+        },
+        Err(err) => {
+            item.status = ItemStatus::Failed;
+            item.error = Some(ErrorDetail::from(&err));
+        },
+    }
+
+    return item;
+}
+
+///Takes a token of the rate limit of the project and one of its concurrent scans for an item of a batch
+///
+/// # Arguments
+/// limiter: &RateLimiter - The rate limiter of the API
+/// project_id: u64 - The project that sent the batch
+/// wait: bool - Whether to wait until the limits allow the item instead of failing it
+///
+/// # Returns
+/// Result<Option<ScanSlot>, ScanError> - The slot, which has to be held while the item is scanned, or the limit the item exceeds
+async fn take_scan_slot(limiter: &RateLimiter, project_id: u64, wait: bool) -> Result<Option<ScanSlot>, ScanError> {
+    loop {
+        match limiter.check(project_id).await {
+            RateLimitDecision::Allowed(_, slot) => return Ok(slot),
+            RateLimitDecision::Rejected(_, ScanError::RateLimited { retry_after } | ScanError::TooManyScans { retry_after }) if wait => {
+                actix_web::rt::time::sleep(Duration::from_secs(retry_after.max(1))).await;
+            },
+            RateLimitDecision::Rejected(_, err) => return Err(err),
+        }
+    }
+}
+
+///Meters and scans the data of a single item of a batch, like our single image detection endpoints do. The item is metered
+///before its URL is fetched, so projects over their quota can't make us download images.
+async fn scan_input(services: &ScanServices, client: &AuthenticatedClient, options: &ScanOptions, input: DetectionInput, size: usize) -> Result<ScanOutcome, ScanError> {
+    file_recognition_service::meter_request(&services.usage, client, size).await?;
+    let image = file_recognition_service::resolve_input(&services.fetcher, input).await?;
+
+    if image.is_empty() {
+        return Err(ScanError::MissingBody);
+    }
+
+    if !infer::is_image(image.head()) {
+        return Err(ScanError::UnsupportedMediaType("Only images can be scanned in a batch.".to_string()));
+    }
+
+    return file_recognition_service::run_scan(services, client.project_id, options, &image).await;
+}
+
+///Reads the items of a batch from the body of the request, as multipart/form-data or NDJSON
+async fn read_batch(req: &HttpRequest, payload: web::Payload, uploads: &UploadConfig, max_items: usize) -> Result<Vec<BatchEntry>, ScanError> {
+    let too_large = |err| match err {
+        ScanError::PayloadTooLarge(_) => ScanError::PayloadTooLarge(format!("A batch may be at most {} bytes large.", uploads.batch_max_bytes)),
+        err => err,
+    };
+    let too_many = || ScanError::InvalidRequest(format!("A batch may contain at most {} items.", max_items));
+
+    return match req.content_type() {
+        "multipart/form-data" => read_multipart(req, payload, uploads, max_items, too_many).await.map_err(too_large),
+        //NDJSON batches have to be held in memory to be parsed, so they are limited more tightly than multipart ones
+        "application/x-ndjson" | "application/ndjson" | "application/jsonl" => {
+            let ndjson_max_bytes = uploads.batch_max_bytes.min(uploads.json_max_bytes);
+            let body = upload::read_bytes(payload, ndjson_max_bytes).await.map_err(|err| match err {
+                ScanError::PayloadTooLarge(_) => ScanError::PayloadTooLarge(format!("NDJSON batches may be at most {} bytes large. Larger batches can be sent as multipart/form-data.", ndjson_max_bytes)),
+                err => err,
+            })?;
+            read_ndjson(&body, max_items, too_many)
+        },
+        _ => Err(ScanError::UnsupportedMediaType("Please send the batch as multipart/form-data or application/x-ndjson.".to_string())),
+    };
+}
+
+async fn read_multipart(req: &HttpRequest, payload: web::Payload, uploads: &UploadConfig, max_items: usize, too_many: impl Fn() -> ScanError) -> Result<Vec<BatchEntry>, ScanError> {
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut entries = Vec::new();
+    let mut size = 0;
+
+    while let Some(field) = multipart.try_next().await.map_err(|_| ScanError::InvalidRequest("The multipart body of the batch is malformed.".to_string()))? {
+        if entries.len() >= max_items {
+            return Err(too_many());
+        }
+
+        let name = field.content_disposition().get_name().map(str::to_string);
+        let filename = field.content_disposition().get_filename().map(str::to_string);
+
+        //Every field may only take what is left of the limit of the whole batch
+        let data = upload::read_stream(field, uploads.batch_max_bytes - size, uploads, upload::sniff_any).await?;
+        let field_size = data.len();
+        size += field_size;
+
+        let entry = match (name.as_deref(), data) {
+            (Some("url"), Upload::Memory(data)) => BatchEntry {
+                id: None,
+                input: DetectionInput::Url(String::from_utf8(data.to_vec()).map_err(|_| ScanError::InvalidRequest("Please ensure every url field holds a valid url.".to_string()))?),
+                size: field_size,
+            },
+            (Some("url"), Upload::File(_)) => return Err(ScanError::InvalidRequest("Please ensure every url field holds a valid url.".to_string())),
+            (_, data) => BatchEntry { id: filename, input: DetectionInput::Data(data), size: field_size },
+        };
+
+        entries.push(entry);
+    }
+
+    return Ok(entries);
+}
+
+fn read_ndjson(body: &[u8], max_items: usize, too_many: impl Fn() -> ScanError) -> Result<Vec<BatchEntry>, ScanError> {
+    let mut entries = Vec::new();
+
+    for (line_number, line) in body.split(|byte| *byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        if entries.len() >= max_items {
+            return Err(too_many());
+        }
+
+        let invalid = || ScanError::InvalidRequest(format!("Line {} of the batch has to be an object with either the url or the base64 encoded data of an image.", line_number + 1));
+        let item: NdjsonItem = serde_json::from_slice(line).map_err(|_| invalid())?;
+
+        let input = match (item.url, item.data) {
+            (Some(url), None) => DetectionInput::Url(url),
+            (None, Some(data)) => DetectionInput::Data(Upload::from(Bytes::from(base64::decode(&data).map_err(|_| invalid())?))),
+            _ => return Err(invalid()),
+        };
+
+        entries.push(BatchEntry { id: item.id, input, size: line.len() });
+    }
+
+    return Ok(entries);
+}
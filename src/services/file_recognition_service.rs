@@ -1,9 +1,123 @@
-use actix_web::{get, post, HttpResponse, HttpRequest, web};
-use actix_web::web::Bytes;
-use crate::helper::{misc, db_api_helper};
-use crate::{s3_helpers, web_helper};
+use std::{future::{ready, Ready}, time::Duration};
+use actix_web::{dev::Payload, get, post, Error, FromRequest, HttpRequest, HttpResponse, web};
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::config::{Config, PipelineConfig, StalePolicy};
+use crate::helper::{misc, db_api_helper, s3_helpers};
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::detection_body::{self, DetectionInput, RawBody, RequestOptions};
+use crate::helper::hash_index::HashIndex;
+use crate::helper::hash_lists::{HashLists, ListMatch};
+use crate::helper::image_decoder::ImageDecoder;
+use crate::helper::job_registry::{JobRegistry, JobState};
+use crate::helper::model_versions::ModelVersions;
+use crate::helper::perceptual_hash::ImageHash;
+use crate::helper::rate_limit::RateLimiter;
+use crate::helper::scan_error::{ErrorDetail, ScanError};
+use crate::helper::scan_events::ScanEvents;
+use crate::helper::token_manager::TokenManager;
+use crate::helper::usage::UsageMeter;
+use crate::helper::upload::Upload;
+use crate::helper::url_fetcher::UrlFetcher;
+use crate::helper::usage_store::Usage;
+use crate::helper::webhooks::Webhooks;
+
+use super::worker_service::{self, MAX_RESULT_WAIT};
+
+///Seconds a client is asked to wait before polling the result of a pending scan job again
+const JOB_POLL_RETRY_AFTER_SECS: u64 = 2;
+///Data type of the images we scan, which selects their preprocessing pipeline and is recorded in their scan jobs
+const IMAGE_DATA_TYPE: &str = "image";
+
+///Everything a scan needs from our app data, so handlers that scan don't have to extract each of it on their own
+#[derive(Clone)]
+pub struct ScanServices {
+    pub config: web::Data<Config>,
+    pub tokens: web::Data<TokenManager>,
+    pub usage: web::Data<UsageMeter>,
+    pub jobs: web::Data<JobRegistry>,
+    pub webhooks: web::Data<Webhooks>,
+    pub events: web::Data<ScanEvents>,
+    pub fetcher: web::Data<UrlFetcher>,
+    pub decoder: web::Data<ImageDecoder>,
+    pub hashes: web::Data<HashIndex>,
+    pub lists: web::Data<HashLists>,
+    pub models: web::Data<ModelVersions>,
+    pub limiter: web::Data<RateLimiter>,
+}
+
+///Returns app data that has to be registered for the API to work
+fn get_app_data<T: 'static>(req: &HttpRequest) -> web::Data<T> {
+    return req.app_data::<web::Data<T>>().unwrap_or_else(|| panic!("{} has not been registered as app data", std::any::type_name::<T>())).clone();
+}
+
+impl FromRequest for ScanServices {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        return ready(Ok(ScanServices {
+            config: get_app_data(req),
+            tokens: get_app_data(req),
+            usage: get_app_data(req),
+            jobs: get_app_data(req),
+            webhooks: get_app_data(req),
+            events: get_app_data(req),
+            fetcher: get_app_data(req),
+            decoder: get_app_data(req),
+            hashes: get_app_data(req),
+            lists: get_app_data(req),
+            models: get_app_data(req),
+            limiter: get_app_data(req),
+        }));
+    }
+}
+
+///Where the result of a scan came from
+pub enum ScanSource {
+    ///Our database already had a result for the data
+    Cache,
+    ///The data has been scanned by one of our workers
+    Worker,
+}
+
+///Outcome of a scan
+pub enum ScanOutcome {
+    ///The scan finished with the given result
+    Done(String, ScanSource),
+    ///The scan is still being processed. Contains the ID of its job, which is the hash of the data.
+    Pending(String),
+}
+
+///How long a client waits for the result of its scan
+#[derive(Clone, Copy)]
+pub enum ResponseMode {
+    ///Wait up to MAX_RESULT_WAIT and fail with scan_timeout if the result did not arrive in time
+    Sync,
+    ///Wait up to the given time and respond with 202 Accepted and the job to poll if the result did not arrive in time
+    Async(Duration),
+}
+
+///Options a client can pass with its detection requests
+#[derive(Clone)]
+pub struct ScanOptions {
+    pub mode: ResponseMode,
+    ///URL the result is posted to once our workers scanned the data
+    pub callback_url: Option<String>,
+}
 
-use super::worker_service;
+///Status of a scan job, as returned by our result endpoint
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobStatus {
+    id: String,
+    ///pending, done or failed
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorDetail>,
+}
 
 ///Returns if our API is operable or not
 /// 
@@ -13,179 +127,281 @@ use super::worker_service;
 /// # Returns
 /// Responder - The response object
 #[get("scan/v1/status")]
-pub async fn check_api() -> impl actix_web::Responder {
-    return if db_api_helper::check_db_connection().await
+pub async fn check_api(config: web::Data<Config>) -> impl actix_web::Responder {
+    return if db_api_helper::check_db_connection(&config).await
     {
         HttpResponse::Ok().body("{\"SCAN_STATUS\": \"Ok\", \"DB_STATUS\": \"Ok\"}")
-    }else
-    {
+    }else{
         HttpResponse::Ok().body("{\"SCAN_STATUS\": \"Ok\", \"DB_STATUS\": \"Unavailable\"}")
     }
 }
 
+///Returns if we are ready to serve scans, which requires a valid token for our Database API
+/// 
+/// # Arguments
+/// tokens: web::Data<TokenManager> - The token manager used to authenticate with our Database API
+/// 
+/// # Returns
+/// Responder - The response object, with status 503 while we are not ready
+#[get("scan/v1/ready")]
+pub async fn check_ready(tokens: web::Data<TokenManager>) -> impl actix_web::Responder {
+    return if tokens.is_healthy()
+    {
+        HttpResponse::Ok().body("{\"SCAN_STATUS\": \"Ready\", \"DB_TOKEN_STATUS\": \"Ok\"}")
+    }else{
+        HttpResponse::ServiceUnavailable().body("{\"SCAN_STATUS\": \"NotReady\", \"DB_TOKEN_STATUS\": \"Unavailable\"}")
+    }
+}
+
 ///API endpoint, that detects the type of the data and returns the scan result, appropriate for the data type
 /// 
+///The data can be sent as the raw body, as multipart/form-data or as JSON, see `detection_body::parse`. Clients can ask to be
+///handed a job to poll instead of waiting for a result that takes long with the `Prefer: respond-async` header or the `wait`
+///option, and to have the result posted to a `callback_url` once it is ready. See `get_scan_options`.
+/// 
 /// # Arguments
-/// req: HttpRequest - The request object
-/// body: Bytes - The body of the request
+/// req: HttpRequest - The request, whose headers and query hold the options of the scan
+/// client: AuthenticatedClient - The client that sent the request
+/// services: ScanServices - The services the data is scanned and metered with
+/// payload: web::Payload - The body of the request, which is streamed
 /// 
 /// # Returns
-/// HttpResponse - The response object
+/// Result<HttpResponse, ScanError> - The response object
 #[post("scan/v1/detection/detect")]
-pub async fn detect(req: HttpRequest, body: Bytes) -> HttpResponse {
-    if !web_helper::check_auth(&req).await{
-        return HttpResponse::Unauthorized().finish();
-    }
+pub async fn detect(req: HttpRequest, client: AuthenticatedClient, services: ScanServices, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let uploads = &services.config.uploads;
+    let request = detection_body::parse(&req, payload, uploads, uploads.max_bytes, RawBody::Data, sniff_detectable).await?;
+    let options = get_scan_options(&req, &services.webhooks, request.options).await?;
+    meter_request(&services.usage, &client, request.size).await?;
 
-    if infer::is_image(&body){
-        let json = serde_json::to_string(&get_image_recognition_result(&body).await);
-        let response = HttpResponse::Ok().body(json.unwrap());
-        return response;
-    }
-    else if infer::is_video(&body){
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
-    }
-    else if infer::is_app(&body) {
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
-    }
-    else if infer::is_audio(&body) {
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
+    let data = resolve_input(&services.fetcher, request.input).await?;
+
+    if data.is_empty() {
+        return Err(ScanError::MissingBody);
     }
-    else if infer::is_archive(&body) {
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
+
+    return scan_image(&services, &client, &options, &data).await;
+}
+
+///Accepts the types of data the detect endpoint can scan
+fn sniff_detectable(head: &[u8]) -> Result<(), ScanError> {
+    if infer::is_image(head){
+        return Ok(());
     }
-    else if infer::is_document(&body){
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
+    else if infer::is_video(head) ||
+    infer::is_app(head) ||
+    infer::is_audio(head) ||
+    infer::is_archive(head) ||
+    infer::is_document(head) ||
+    infer::is_font(head) {
+        return Err(ScanError::UnsupportedMediaType("We do not support this media type yet.".to_string()));
     }
-    else if infer::is_font(&body){
-        return HttpResponse::from(HttpResponse::NotImplemented().body("We do not support this media type yet."));
+
+    return Err(ScanError::UnsupportedMediaType("We could not determine the media type of the data.".to_string()));
+}
+
+///Accepts images only
+fn sniff_image(head: &[u8]) -> Result<(), ScanError> {
+    if !infer::is_image(head) {
+        return Err(ScanError::UnsupportedMediaType("Only images can be scanned with this endpoint.".to_string()));
     }
 
-    let json = serde_json::to_string("Incorrect Result");
-    let response = HttpResponse::Ok().body(json.unwrap());
-    return response;
+    return Ok(());
 }
 
 ///API endpoint, that scans the data, if it is an image, and returns the scan result
 /// 
+///The data can be sent as the raw body, as multipart/form-data or as JSON, see `detection_body::parse`. Clients can ask to be
+///handed a job to poll instead of waiting for a result that takes long with the `Prefer: respond-async` header or the `wait`
+///option, and to have the result posted to a `callback_url` once it is ready. See `get_scan_options`.
+/// 
 /// # Arguments
-/// req: HttpRequest - The request object
-/// body: Bytes - The body of the request
+/// req: HttpRequest - The request, whose headers and query hold the options of the scan
+/// client: AuthenticatedClient - The client that sent the request
+/// services: ScanServices - The services the data is scanned and metered with
+/// payload: web::Payload - The body of the request, which is streamed
 /// 
 /// # Returns
-/// HttpResponse - The response object
+/// Result<HttpResponse, ScanError> - The response object
 #[post("scan/v1/detection/detectImage")]
-pub async fn detect_image(req: HttpRequest, body: Bytes) -> HttpResponse {
-    if !web_helper::check_auth(&req).await{
-        return HttpResponse::Unauthorized().finish();
-    }
+pub async fn detect_image(req: HttpRequest, client: AuthenticatedClient, services: ScanServices, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let uploads = &services.config.uploads;
+    let request = detection_body::parse(&req, payload, uploads, uploads.image_max_bytes, RawBody::Data, sniff_image).await?;
+    let options = get_scan_options(&req, &services.webhooks, request.options).await?;
+    meter_request(&services.usage, &client, request.size).await?;
 
-    if body.len() == 0 {
-        return HttpResponse::from(HttpResponse::BadRequest().body("No data provided"));
-    }
+    let image = resolve_input(&services.fetcher, request.input).await?;
 
-    let result = get_image_recognition_result(&body).await;
-
-    if result.is_ok(){
-        return HttpResponse::Ok().body(result.unwrap());
+    if image.is_empty() {
+        return Err(ScanError::MissingBody);
     }
-    else{
-        let result_code = result.err().unwrap();
-
-        if result_code.0 == 500{
-            return HttpResponse::InternalServerError().body(result_code.1);
-        }
-        if result_code.0 == 400{
-            return HttpResponse::BadRequest().body(result_code.1);
-        }
-        if result_code.0 == 301{
-            //We issue a 301 if the request takes too long to process but direct them to the same URL with a 60 second wait time
-
-            let moved_response =  
-                HttpResponse::Ok()
-                .append_header(("Retry-After", "60"))
-                .body(result_code.1);
-            return moved_response;
-        }
 
-        return HttpResponse::BadRequest().body("Could not process the request that has been sent to the server.".to_string());
-    }
+    return scan_image(&services, &client, &options, &image).await;
 }
 
 ///API endpoint, that detects the type of the data given by the URL in it's body and returns the scan result, appropriate for the data type
 /// 
+///The data can be sent as the raw body, as multipart/form-data or as JSON, see `detection_body::parse`. Clients can ask to be
+///handed a job to poll instead of waiting for a result that takes long with the `Prefer: respond-async` header or the `wait`
+///option, and to have the result posted to a `callback_url` once it is ready. See `get_scan_options`.
+/// 
 /// # Arguments
-/// req: HttpRequest - The request object
-/// body: Bytes - The body of the request
+/// req: HttpRequest - The request, whose headers and query hold the options of the scan
+/// client: AuthenticatedClient - The client that sent the request
+/// services: ScanServices - The services the data is scanned and metered with
+/// payload: web::Payload - The body of the request, which is streamed
 /// 
 /// # Returns
-/// HttpResponse - The response object
+/// Result<HttpResponse, ScanError> - The response object
 #[post("scan/v1/detection/detectImageFromUrl")]
-pub async fn detect_img_from_url(req: HttpRequest, body: Bytes) -> HttpResponse {
-    if !web_helper::check_auth(&req).await{
-        return HttpResponse::Unauthorized().finish();
-    }
+pub async fn detect_img_from_url(req: HttpRequest, client: AuthenticatedClient, services: ScanServices, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let uploads = &services.config.uploads;
+    let request = detection_body::parse(&req, payload, uploads, uploads.url_max_bytes, RawBody::Url, sniff_image).await?;
+    let options = get_scan_options(&req, &services.webhooks, request.options).await?;
+    meter_request(&services.usage, &client, request.size).await?;
+
+    let image_bytes = resolve_input(&services.fetcher, request.input).await?;
+    return scan_image(&services, &client, &options, &image_bytes).await;
+}
 
-    let image_url = String::from_utf8(body.to_vec());
+///Returns the data a client wants to have scanned, downloading it first if the client sent its URL
+pub async fn resolve_input(fetcher: &UrlFetcher, input: DetectionInput) -> Result<Upload, ScanError> {
+    return match input {
+        DetectionInput::Data(data) => Ok(data),
+        DetectionInput::Url(url) if url.trim().is_empty() => Err(ScanError::MissingBody),
+        DetectionInput::Url(url) => Ok(Upload::from(fetcher.fetch(url.trim()).await?)),
+    };
+}
 
-    if image_url.is_err(){
-        return HttpResponse::from(HttpResponse::BadRequest().body("Please ensure you specify a valid url to detect from"))
-    }
+///Scans an image and counts the outcome in the usage of the project
+/// 
+/// # Arguments
+/// services: &ScanServices - The services the image is scanned and metered with
+/// project_id: u64 - The project that requested the scan
+/// options: &ScanOptions - How long to wait for the result of our workers and where to post it
+/// image: &Upload - The image to scan
+/// 
+/// # Returns
+/// Result<ScanOutcome, ScanError> - The scan result of the image, or the job that is still being processed
+pub async fn run_scan(services: &ScanServices, project_id: u64, options: &ScanOptions, image: &Upload) -> Result<ScanOutcome, ScanError> {
+    let result = get_image_recognition_result(services, project_id, image, options).await;
+
+    let scan_usage = match (&result, options.mode) {
+        (Ok(ScanOutcome::Done(_, ScanSource::Cache)), _) => Usage { cache_hits: 1, ..Usage::default() },
+        (Ok(ScanOutcome::Done(_, ScanSource::Worker)), _) | (Ok(ScanOutcome::Pending(_)), ResponseMode::Async(_)) => Usage { fresh_scans: 1, ..Usage::default() },
+        (Ok(ScanOutcome::Pending(_)), ResponseMode::Sync) => Usage { timeouts: 1, ..Usage::default() },
+        (Err(_), _) => Usage::default(),
+    };
+    services.usage.record(project_id, scan_usage).await;
+
+    return result;
+}
 
-    let unwrapped_url = image_url.unwrap();
+///Scans an image, counts the outcome in the usage of the client's project and returns the scan result as the response
+async fn scan_image(services: &ScanServices, client: &AuthenticatedClient, options: &ScanOptions, image: &Upload) -> Result<HttpResponse, ScanError> {
+    let mode = options.mode;
+    let result = run_scan(services, client.project_id, options, image).await;
+
+    return match (result?, mode) {
+        (ScanOutcome::Done(result, _), _) => Ok(HttpResponse::Ok().content_type("application/json").body(result)),
+        (ScanOutcome::Pending(job_id), ResponseMode::Async(_)) => Ok(HttpResponse::Accepted()
+            .append_header(("Location", format!("{}/scan/v1/detection/result/{}", services.config.base_url, job_id)))
+            .append_header(("Retry-After", JOB_POLL_RETRY_AFTER_SECS))
+            .append_header(("Preference-Applied", "respond-async"))
+            .json(JobStatus { id: job_id, status: "pending", result: None, error: None })),
+        (ScanOutcome::Pending(_), ResponseMode::Sync) => Err(ScanError::ScanTimeout),
+    };
+}
 
-    if unwrapped_url.is_empty(){
-        return HttpResponse::from(HttpResponse::BadRequest().body("Please ensure you specify a valid url to detect from"))
+///API endpoint, that returns the status of a scan job and its result once it is done. It never queues the job again.
+///
+///Jobs are only registered on the instance they were submitted to. Jobs of other instances are found by their stored result:
+///a current result is returned and a stale one is reported as pending, as its rescan replaces it.
+/// 
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// tokens: web::Data<TokenManager> - The token manager used to look the result up in our Database API
+/// jobs: web::Data<JobRegistry> - The registry the scan job has been submitted to
+/// models: web::Data<ModelVersions> - The models that tell a stale result, whose rescan is still pending, from a current one
+/// job_id: web::Path<String> - The ID of the job, as returned in the Location header of our 202 Accepted responses
+/// 
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/detection/result/{job_id}")]
+pub async fn get_result(client: AuthenticatedClient, tokens: web::Data<TokenManager>, jobs: web::Data<JobRegistry>, models: web::Data<ModelVersions>, job_id: web::Path<String>) -> Result<HttpResponse, ScanError> {
+    let job_id = job_id.into_inner();
+    let state = jobs.get_state(&job_id, client.project_id);
+
+    if let Some(result) = worker_service::get_work_result(&tokens, &models, &job_id, Duration::ZERO).await {
+        return Ok(HttpResponse::Ok().json(JobStatus { id: job_id, status: "done", result: misc::get_json_value(&result), error: None }));
     }
 
-    let image_data = reqwest::get(unwrapped_url).await;
+    let state = match state {
+        Some(state) => state,
+        //Not submitted to us, so it is only pending if another instance is scanning a stale result of it again
+        None => {
+            let stored = db_api_helper::get_scan(&tokens, &job_id).await;
 
-    if image_data.is_err(){
-        return HttpResponse::from(HttpResponse::BadRequest().body("Could not fetch the image from the given url. Please make sure the url is correct."))
-    }
+            match stored.as_deref().and_then(misc::get_json_value).filter(misc::is_valid_recognition_result) {
+                Some(_) => JobState::Pending,
+                None => return Err(ScanError::JobNotFound),
+            }
+        },
+    };
+
+    return Ok(match state {
+        JobState::Pending => HttpResponse::Ok()
+            .append_header(("Retry-After", JOB_POLL_RETRY_AFTER_SECS))
+            .json(JobStatus { id: job_id, status: "pending", result: None, error: None }),
+        JobState::Failed => HttpResponse::Ok()
+            .json(JobStatus { id: job_id, status: "failed", result: None, error: Some(ErrorDetail::from(&ScanError::ScanTimeout)) }),
+    });
+}
 
-    let image_byte_result = image_data.unwrap().bytes().await;
-    
-    if image_byte_result.is_err(){
-        return HttpResponse::from(HttpResponse::BadRequest().body("Error while trying to load the image as bytes. Please ensure the url is correct and we can get images from it."))
+///Reads the options of a scan from the request. Options that have been sent in the body replace the ones of the query.
+///
+///How long the client wants to wait for the result: clients that send `Prefer: respond-async` are handed the job right away,
+///unless they also send a `wait` preference. The `wait` query parameter overrides both. Waits are capped at MAX_RESULT_WAIT.
+///
+///The `callback_url` option names an http(s) URL the result is posted to once our workers scanned the data. Its host may not
+///resolve to private or internal addresses.
+pub async fn get_scan_options(req: &HttpRequest, webhooks: &Webhooks, body_options: RequestOptions) -> Result<ScanOptions, ScanError> {
+    let query = web::Query::<RequestOptions>::from_query(req.query_string())
+        .map_err(|_| ScanError::InvalidRequest("Please specify the wait parameter as a whole number of seconds.".to_string()))?
+        .into_inner()
+        .merge(body_options);
+
+    if let Some(callback_url) = &query.callback_url {
+        webhooks.check_callback_url(callback_url).await?;
     }
 
-    let image_bytes = image_byte_result.unwrap();
+    let preferences: Vec<String> = req.headers().get_all("Prefer")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|preference| preference.trim().to_ascii_lowercase())
+        .collect();
 
-    let result = get_image_recognition_result(&image_bytes).await;
+    let respond_async = preferences.iter().any(|preference| preference == "respond-async");
+    let preferred_wait = preferences.iter().find_map(|preference| preference.strip_prefix("wait=")?.parse::<u64>().ok());
 
-    if result.is_ok(){
-        return HttpResponse::Ok().body(result.unwrap());
-    }
-    else{
-        let result_code = result.err().unwrap();
+    let mode = match (query.wait.or(preferred_wait), respond_async) {
+        (Some(wait), _) => ResponseMode::Async(Duration::from_secs(wait).min(MAX_RESULT_WAIT)),
+        (None, true) => ResponseMode::Async(Duration::ZERO),
+        (None, false) => ResponseMode::Sync,
+    };
 
-        if result_code.0 == 500{
-            return HttpResponse::InternalServerError().body(result_code.1);
-        }
-        if result_code.0 == 400{
-            return HttpResponse::BadRequest().body(result_code.1);
-        }
-        if result_code.0 == 301{
-            //We issue a 301 if the request takes too long to process but direct them to the same URL with a 60 second wait time
-
-            let moved_response =  
-                HttpResponse::Ok()
-                .append_header(("Retry-After", "60"))
-                .body(result_code.1);
-            return moved_response;
-        }
-
-        return HttpResponse::BadRequest().body("Could not process the request that has been sent to the server.".to_string());
-    }
+    return Ok(ScanOptions { mode, callback_url: query.callback_url });
 }
 
 ///Gets the scan result of the data, either from our database or from scanning the data via our scanning nodes
 /// # Arguments
+/// * `services` - The services used to look up and scan the data, and to register its job and callbacks with
+/// * `project_id` - The project that requested the scan
 /// * `image` - The image to scan
+/// * `options` - How long to wait for the result of our workers and where to post it
 /// 
 /// # Returns
-/// * `String` - The scan result of the data
+/// * `ScanOutcome` - The scan result of the data and where it came from, or the job that is still being processed
 /// 
 /// # Example
 /// ```
@@ -194,77 +410,240 @@ pub async fn detect_img_from_url(req: HttpRequest, body: Bytes) -> HttpResponse
 /// let image = Bytes::from(File::open("/home/pamaxie/Desktop/test.png").unwrap());
 /// let result = get_image_recognition_result(Bytes::from(image)).await;
 /// ```
-async fn get_image_recognition_result(image: &Bytes) -> Result<String, (i16, String)>{
-    let resized_image = misc::resize_image(image, &250, &250).await;
+async fn get_image_recognition_result(services: &ScanServices, project_id: u64, image: &Upload, options: &ScanOptions) -> Result<ScanOutcome, ScanError>{
+    let (config, tokens) = (services.config.get_ref(), services.tokens.get_ref());
 
-    if resized_image.is_none(){
-        return Err((500, "Encountered an issue while attempting to process your image. Please validate it's data type is correct.".to_string()));
-    }
+    let pipeline = config.preprocessing.pipeline(IMAGE_DATA_TYPE);
+    let (unwrapped_image, image_hash) = services.decoder.preprocess(image, pipeline).await?;
+    let unwrapped_image_hash = image_hash.to_string();
 
-    let unwrapped_image = resized_image.unwrap();
+    //Images on our hash lists get the verdict of their list, without being stored or scanned
+    if let Some(list_match) = services.lists.find_match(project_id, &image_hash) {
+        return Ok(ScanOutcome::Done(list_verdict(&unwrapped_image_hash, &list_match), ScanSource::Cache));
+    }
 
-    let image_hash = db_api_helper::get_image_hash(&unwrapped_image).await;
+    let mut db_item = db_api_helper::get_scan(tokens, &unwrapped_image_hash).await;
 
-    if image_hash.is_none(){
-        return Err((500, "We could not determine the hash of the image that was sent in please try again later".to_string()));
+    if db_item.is_none() && config.hashing.legacy_lookup {
+        db_item = migrate_legacy_result(tokens, &services.decoder, image, &unwrapped_image_hash).await;
     }
 
-    let unwrapped_image_hash = image_hash.unwrap();
-
-    let db_item = db_api_helper::get_scan(&unwrapped_image_hash).await;
+    //Set if the image has a result of another model and has to be scanned again right away
+    let mut rescan_stale = false;
 
     //Check if we could find an item in our database.
-    if db_item.is_some(){
-
-        let db_item = db_item.unwrap();
+    if let Some(db_item) = db_item {
         let db_item_json = misc::get_json_value(&db_item);
 
         //Check our db item is valid json, otherwise we just rescan the item.
-        if db_item_json.is_some() {
+        if let Some(db_item_json) = db_item_json {
             //Check if the data stored is valid
-            let validation_result = misc::is_valid_recognition_result(&db_item_json.unwrap());
+            let validation_result = misc::is_valid_recognition_result(&db_item_json);
 
             if validation_result {
-                //TODO: Add check where we poll our Github to check if new neural network version is available and to see which one this one was scanned on.
-                return Ok(db_item.to_string());
+                services.hashes.add(image_hash).await;
+
+                if !services.models.is_stale(&db_item_json) {
+                    return Ok(ScanOutcome::Done(db_item, ScanSource::Cache));
+                }
+
+                //Results of another model are either returned while the image is scanned again in the background, or the
+                //image is scanned again right away, depending on the project
+                match services.models.stale_policy(project_id) {
+                    StalePolicy::Background => {
+                        if let Err(err) = queue_scan(services, project_id, &unwrapped_image, &unwrapped_image_hash, pipeline, None).await {
+                            eprintln!("Could not queue the rescan of the stale result {}. {}", unwrapped_image_hash, err);
+                        }
+
+                        return Ok(ScanOutcome::Done(mark_stale(db_item_json), ScanSource::Cache));
+                    },
+                    StalePolicy::Rescan => rescan_stale = true,
+                }
+            } else {
+                //If the data stored is not valid, we delete it from our database and rescan the data.
+                let removal_result = db_api_helper::remove_scan(tokens, &unwrapped_image_hash).await;
+
+                if removal_result.is_err(){
+                    eprintln!("Could not remove an invalid scan result from our databse. Please ensure connection parameters are correct.");
+                }
             }
+        }
+    }
+
+    //Re-compressed or slightly cropped copies of images we know get the result of the image we know. Images with a stale result
+    //of their own don't, as the result of a near duplicate may be stale as well and they have to be scanned anyway.
+    if !rescan_stale {
+        if let Some(result) = find_near_duplicate(services, &image_hash).await {
+            return Ok(ScanOutcome::Done(result, ScanSource::Cache));
+        }
+    }
 
-            //If the data stored is not valid, we delete it from our database and rescan the data.
-            let removal_result = db_api_helper::remove_scan(&unwrapped_image_hash).await;
+    queue_scan(services, project_id, &unwrapped_image, &unwrapped_image_hash, pipeline, options.callback_url.as_deref()).await?;
 
-            if removal_result.is_err(){
-                eprintln!("Could not remove an invalid scan result from our databse. Please ensure connection parameters are correct.");
-            }
+    //We could not poll a result in time, so the client has to come back for it
+    let wait = match options.mode {
+        ResponseMode::Sync => MAX_RESULT_WAIT,
+        ResponseMode::Async(wait) => wait,
+    };
+
+    return Ok(match worker_service::get_work_result(tokens, &services.models, &unwrapped_image_hash, wait).await {
+        Some(result) => ScanOutcome::Done(result, ScanSource::Worker),
+        None => ScanOutcome::Pending(unwrapped_image_hash),
+    });
+}
+
+///Stores a preprocessed image and queues it for our workers, unless it is already being scanned, and submits its job for the
+///project
+///
+/// # Arguments
+/// services: &ScanServices - The services the image is stored and queued with
+/// project_id: u64 - The project the job is submitted for
+/// image: &web::Bytes - The preprocessed image
+/// hash: &str - The hash of the image, which its result is stored under
+/// pipeline: &PipelineConfig - The pipeline the image has been preprocessed with, whose version is recorded in the job
+/// callback_url: Option<&str> - The URL the result is posted to once it is done
+///
+/// # Returns
+/// Result<(), ScanError> - An error if the image could not be stored or queued
+async fn queue_scan(services: &ScanServices, project_id: u64, image: &web::Bytes, hash: &str, pipeline: &PipelineConfig, callback_url: Option<&str>) -> Result<(), ScanError> {
+    let config = services.config.get_ref();
+
+    //Registered before the work is queued, so a worker can't post the result before the callbacks wait for it
+    services.webhooks.register(hash, project_id, callback_url).await;
+
+    //The image is already being scanned for someone, so we just wait for the same result instead of queueing it again
+    if !services.jobs.is_pending(hash) {
+        //Get the data extension from our Object
+        let data_extension_ref = misc::get_image_extension(image)
+            .ok_or_else(|| ScanError::InvalidImage("We could not determine the item's data extension. Please ensure it's valid".to_string()))?;
+
+        let data_url = s3_helpers::store_s3(config, image, hash, &data_extension_ref, &format!("image/{}", data_extension_ref)).await
+            .ok_or_else(|| ScanError::StorageUnavailable("We could not store the data in our S3 bucket. Arborting process. Please try again later".to_string()))?;
+        
+        //Attempt to add our work to the queue if not exit here.
+        if !worker_service::add_work(config, &services.events, hash, &data_url, IMAGE_DATA_TYPE, &data_extension_ref, &pipeline.version).await {
+            return Err(ScanError::QueueUnavailable("We could not add the work to the queue. Aborting process. Please try again later".to_string()));
         }
     }
 
-    let data_extension = misc::get_image_extension(&unwrapped_image);
+    services.jobs.submit(hash, project_id);
+    return Ok(());
+}
+
+///Flags a result of another model than the current one as `"stale": true`, as the image is being scanned again
+fn mark_stale(mut result: Value) -> String {
+    result["stale"] = Value::Bool(true);
+    return result.to_string();
+}
 
-    //Check if we have a valid extension
-    if data_extension.is_none(){
-        return Err((400, "We could not determine the item's data extension. Please ensure it's valid".to_string()));
+///Builds the verdict of an image that is on one of our hash lists, which is returned instead of a scan result. It carries the
+///verdict of the list, block or allow, and the entry the image matched as `"listMatch": {"list": ..., "scope": ..., "hash": ...,
+///"distance": ..., "label": ...}`.
+fn list_verdict(hash: &str, list_match: &ListMatch) -> String {
+    return json!({ "key": hash, "dataType": IMAGE_DATA_TYPE, "verdict": list_match.list, "listMatch": list_match }).to_string();
+}
+
+///Looks up the result of an image by its hash alone: the verdict of a hash list it is on, the result stored under the hash or
+///the result of a near duplicate, see `find_near_duplicate`. Nothing is scanned, stored or queued.
+///
+/// # Arguments
+/// services: &ScanServices - The services the result is looked up with
+/// project_id: u64 - The project whose hash lists are checked
+/// hash: &ImageHash - The hash of the image
+///
+/// # Returns
+/// Option<String> - The result of the image, if we know a valid one. Results of another model than the current one are flagged
+/// as `"stale": true`.
+pub async fn lookup_result(services: &ScanServices, project_id: u64, hash: &ImageHash) -> Option<String> {
+    if let Some(list_match) = services.lists.find_match(project_id, hash) {
+        return Some(list_verdict(&hash.to_string(), &list_match));
     }
 
-    //Get the data extension from our Object
-    let data_extension_ref = data_extension.unwrap();
-    let data_url = s3_helpers::store_s3(&unwrapped_image, &unwrapped_image_hash, &data_extension_ref, &format!("image/{}", data_extension_ref)).await;
+    let exact = db_api_helper::get_scan(&services.tokens, &hash.to_string()).await
+        .and_then(|result| misc::get_json_value(&result))
+        .filter(misc::is_valid_recognition_result);
 
-    if data_url.is_none(){
-        return Err((500, "We could not store the data in our S3 bucket. Arborting process. Please try again later".to_string()));
+    //Results of another model are still returned, flagged as stale, as a lookup doesn't queue the image to be scanned again
+    if let Some(exact) = exact {
+        services.hashes.add(*hash).await;
+
+        return match services.models.is_stale(&exact) {
+            true => Some(mark_stale(exact)),
+            false => Some(exact.to_string()),
+        };
     }
-    
-    //Attempt to add our work to the queue if not exit here.
-    if !worker_service::add_work(&unwrapped_image_hash, &data_url.unwrap(), &String::from("image"), &data_extension_ref).await {
-        return Err((500, "We could not add the work to the queue. Aborting process. Please try again later".to_string()));
+
+    return find_near_duplicate(services, hash).await;
+}
+
+///Looks up the result of the closest near duplicate of an image we know, if its hash differs from the hash of the image in at most
+///`hashing.max_distance` bits. The result is returned with the hash it is stored under and its distance as
+///`"match": {"hash": ..., "distance": ...}`, and isn't stored under the hash of the image, as it hasn't been scanned itself.
+///
+/// # Arguments
+/// services: &ScanServices - The services the near duplicate is looked up with
+/// hash: &ImageHash - The hash of the image
+///
+/// # Returns
+/// Option<String> - The result of the near duplicate, if there is a valid one
+async fn find_near_duplicate(services: &ScanServices, hash: &ImageHash) -> Option<String> {
+    let max_distance = services.config.hashing.max_distance;
+
+    if max_distance == 0 {
+        return None;
     }
 
+    let (matched_hash, distance) = services.hashes.find_closest(hash, max_distance)?;
+    let mut result = misc::get_json_value(&db_api_helper::get_scan(&services.tokens, &matched_hash.to_string()).await?)?;
 
-    let result = worker_service::get_work_result(&unwrapped_image_hash).await;
+    //Results of another model aren't passed on to images that haven't been scanned themselves
+    if !misc::is_valid_recognition_result(&result) || services.models.is_stale(&result) {
+        return None;
+    }
+
+    result["match"] = json!({ "hash": matched_hash.to_string(), "distance": distance });
+    return serde_json::to_string(&result).ok();
+}
 
-    //We could not poll a result in a timely manner this means we likely timed out.
-    if result.is_none(){
-        return Err((301, ("We could not process your result in a timely manner. Please try again later.".to_string())));
+///Looks up the result of an image under the hash the database API computes for it, which our results were stored under before
+///we hashed images ourselves. A valid result is stored under the perceptual hash of the image, so it is found there next time.
+///Failures are only logged, as the image is simply scanned again then.
+///
+/// # Arguments
+/// tokens: &TokenManager - The token manager used to authenticate with our Database API
+/// decoder: &ImageDecoder - The decoder the image is resized with, like it was before our preprocessing pipeline
+/// image: &Upload - The image the client sent
+/// hash: &str - The perceptual hash of the image
+///
+/// # Returns
+/// Option<String> - The result of the image, keyed by its perceptual hash, if there is a valid one
+async fn migrate_legacy_result(tokens: &TokenManager, decoder: &ImageDecoder, image: &Upload, hash: &str) -> Option<String> {
+    //The database API hashed the image as we resized it back then, not the output of our current pipeline
+    let legacy_image = decoder.resize_legacy(image).await.ok()?;
+    let legacy_hash = db_api_helper::get_image_hash(tokens, &legacy_image).await?;
+    let mut result = misc::get_json_value(&db_api_helper::get_scan(tokens, &legacy_hash).await?)?;
+
+    if !misc::is_valid_recognition_result(&result) {
+        return None;
     }
 
-    return Ok(result.unwrap());
-}
\ No newline at end of file
+    let key = if result.get("key").is_some() { "key" } else { "Key" };
+    result[key] = Value::from(hash);
+    let result = serde_json::to_string(&result).ok()?;
+
+    if !db_api_helper::set_scan(tokens, &result).await {
+        eprintln!("Could not store the result of {} under its perceptual hash {}.", legacy_hash, hash);
+    }
+
+    return Some(result);
+}
+
+///Checks the monthly quota of the client's project and counts the request in its usage
+/// 
+/// # Returns
+/// Result<(), ScanError> - The error to reject the request with, if the project exhausted its quota
+pub async fn meter_request(usage: &UsageMeter, client: &AuthenticatedClient, size: usize) -> Result<(), ScanError> {
+    usage.check_quota(client.project_id).await?;
+    usage.record(client.project_id, Usage { requests: 1, bytes: size as u64, ..Usage::default() }).await;
+    return Ok(());
+}
@@ -0,0 +1,242 @@
+use actix_web::{delete, get, post, HttpMessage, HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::hash_lists::{HashLists, ListKind, ListScope};
+use crate::helper::perceptual_hash::ImageHash;
+use crate::helper::scan_error::ScanError;
+use crate::helper::upload;
+
+///Entries that are listed if the client does not ask for a number
+const DEFAULT_LIST_LIMIT: u32 = 100;
+///Most entries that can be listed at once
+const MAX_LIST_LIMIT: u32 = 1000;
+///Hashes have 64 bits, so they can't differ in more
+const MAX_ENTRY_DISTANCE: u32 = 64;
+
+#[derive(Deserialize)]
+pub struct ScopeQuery {
+    ///global for the lists that apply to every project, which only pamaxie's internal clients may manage, or project
+    scope: Option<String>,
+    ///Project whose lists are managed. Only pamaxie's internal clients may manage the lists of other projects.
+    project_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct EntriesQuery {
+    scope: Option<String>,
+    project_id: Option<u64>,
+    ///Number of entries to list, newest first
+    limit: Option<u32>,
+    ///Number of entries to skip
+    offset: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    scope: Option<String>,
+    project_id: Option<u64>,
+    ///Distance of the entries that don't set their own. Defaults to 0, so only images with the exact hash match them.
+    max_distance: Option<u32>,
+}
+
+///A hash that is added to a list, as a JSON object or a line of an NDJSON file
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct NewEntry {
+    hash: String,
+    ///Most bits the hash of an image may differ in from the hash for the image to match it
+    max_distance: Option<u32>,
+    ///Note on why the hash is on the list, e.g. the case it came from
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportResult {
+    imported: usize,
+}
+
+///Returns the scope of the lists a client asked for, if it may manage them
+fn get_scope(client: &AuthenticatedClient, scope: Option<&str>, project_id: Option<u64>) -> Result<ListScope, ScanError> {
+    return match scope.unwrap_or("project") {
+        "global" if client.is_internal => Ok(ListScope::Global),
+        "global" => Err(ScanError::Forbidden("Only pamaxie's internal clients can manage the global hash lists.".to_string())),
+        "project" => {
+            let project_id = project_id.unwrap_or(client.project_id);
+
+            if project_id != client.project_id && !client.is_internal {
+                return Err(ScanError::Forbidden("You can only manage the hash lists of your own project.".to_string()));
+            }
+
+            Ok(ListScope::Project(project_id))
+        },
+        _ => Err(ScanError::InvalidRequest("Please specify the scope as project or global.".to_string())),
+    };
+}
+
+fn get_list(list: &str) -> Result<ListKind, ScanError> {
+    return list.parse().map_err(|_| ScanError::InvalidRequest("Please specify the list as block or allow.".to_string()));
+}
+
+fn parse_hash(hash: &str) -> Result<ImageHash, ScanError> {
+    return hash.trim().parse().map_err(|_| ScanError::InvalidRequest(format!("\"{}\" is not a hash computed with our perceptual hash algorithm.", hash)));
+}
+
+///Checks an entry that is added to a list
+fn parse_entry(entry: NewEntry, default_distance: u32, line_number: Option<usize>) -> Result<(ImageHash, u32, Option<String>), ScanError> {
+    let location = line_number.map(|line_number| format!("Line {}: ", line_number)).unwrap_or_default();
+    let hash = parse_hash(&entry.hash).map_err(|err| ScanError::InvalidRequest(format!("{}{}", location, err)))?;
+    let max_distance = entry.max_distance.unwrap_or(default_distance);
+
+    if max_distance > MAX_ENTRY_DISTANCE {
+        return Err(ScanError::InvalidRequest(format!("{}The distance may be at most {}.", location, MAX_ENTRY_DISTANCE)));
+    }
+
+    return Ok((hash, max_distance, entry.label.filter(|label| !label.is_empty())));
+}
+
+///Reads the entries of a CSV file, whose columns are the hash, its distance and its label. Only the hash has to be set, and a
+///header row starting with "hash" is skipped.
+fn read_csv(body: &[u8], default_distance: u32) -> Result<Vec<(ImageHash, u32, Option<String>)>, ScanError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(body);
+    let mut entries = Vec::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|_| ScanError::InvalidRequest(format!("Line {} of the file is not valid CSV.", index + 1)))?;
+        let hash = record.get(0).unwrap_or_default();
+
+        if (index == 0 && hash.eq_ignore_ascii_case("hash")) || record.iter().all(str::is_empty) {
+            continue;
+        }
+
+        let max_distance = match record.get(1).filter(|distance| !distance.is_empty()) {
+            Some(distance) => Some(distance.parse()
+                .map_err(|_| ScanError::InvalidRequest(format!("Line {}: the distance has to be a whole number.", index + 1)))?),
+            None => None,
+        };
+
+        let entry = NewEntry { hash: hash.to_string(), max_distance, label: record.get(2).map(str::to_string) };
+        entries.push(parse_entry(entry, default_distance, Some(index + 1))?);
+    }
+
+    return Ok(entries);
+}
+
+///Reads the entries of an NDJSON file, where every line is an object like the one our list endpoint accepts
+fn read_ndjson(body: &[u8], default_distance: u32) -> Result<Vec<(ImageHash, u32, Option<String>)>, ScanError> {
+    let mut entries = Vec::new();
+
+    for (line_number, line) in body.split(|byte| *byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let entry: NewEntry = serde_json::from_slice(line)
+            .map_err(|_| ScanError::InvalidRequest(format!("Line {} of the file has to be an object with the hash and optionally its maxDistance and label.", line_number + 1)))?;
+        entries.push(parse_entry(entry, default_distance, Some(line_number + 1))?);
+    }
+
+    return Ok(entries);
+}
+
+///Lists the hashes on a blocklist or allowlist of the client's project, or on a global list
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// lists: web::Data<HashLists> - Our hash lists
+/// list: web::Path<String> - block or allow
+/// query: web::Query<EntriesQuery> - The scope of the list and the page of its entries to list
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/lists/{list}")]
+pub async fn get_entries(client: AuthenticatedClient, lists: web::Data<HashLists>, list: web::Path<String>, query: web::Query<EntriesQuery>) -> Result<HttpResponse, ScanError> {
+    let scope = get_scope(&client, query.scope.as_deref(), query.project_id)?;
+    let list = get_list(&list)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+
+    let entries = lists.list(scope, list, limit, query.offset.unwrap_or(0)).await.map_err(|err| {
+        eprintln!("Could not list the {} list of scope {}. {}", list.as_str(), scope.name(), err);
+        ScanError::DatabaseUnavailable("We could not list the hash list. Please try again later.".to_string())
+    })?;
+
+    return Ok(HttpResponse::Ok().json(entries));
+}
+
+///Adds hashes to a blocklist or allowlist. Images that match a hash on a list get the verdict of the list without being scanned.
+///
+///The body is either a single JSON object like `{"hash": "dh1_f0e1d2c3b4a59687", "maxDistance": 2, "label": "case 42"}`, an
+///NDJSON file with one such object per line, or a CSV file whose columns are the hash, its distance and its label. Either all
+///hashes of a file are added or, if one of them is invalid, none.
+///
+/// # Arguments
+/// req: HttpRequest - The request, whose content type tells the format of the body
+/// client: AuthenticatedClient - The client that sent the request
+/// config: web::Data<Config> - The configuration, which limits the size of the body
+/// lists: web::Data<HashLists> - Our hash lists
+/// list: web::Path<String> - block or allow
+/// query: web::Query<ImportQuery> - The scope of the list and the distance of entries that don't set their own
+/// payload: web::Payload - The body of the request
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[post("scan/v1/lists/{list}")]
+pub async fn add_entries(req: HttpRequest, client: AuthenticatedClient, config: web::Data<Config>, lists: web::Data<HashLists>, list: web::Path<String>, query: web::Query<ImportQuery>, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let scope = get_scope(&client, query.scope.as_deref(), query.project_id)?;
+    let list = get_list(&list)?;
+    let default_distance = query.max_distance.unwrap_or(0);
+    let body = upload::read_bytes(payload, config.hash_lists.max_import_bytes).await?;
+
+    let entries = match req.content_type() {
+        "application/json" => {
+            let entry: NewEntry = serde_json::from_slice(&body)
+                .map_err(|_| ScanError::InvalidRequest("Please send a JSON object with the hash and optionally its maxDistance and label.".to_string()))?;
+            vec![parse_entry(entry, default_distance, None)?]
+        },
+        "application/x-ndjson" | "application/ndjson" | "application/jsonl" => read_ndjson(&body, default_distance)?,
+        "text/csv" | "application/csv" => read_csv(&body, default_distance)?,
+        _ => return Err(ScanError::UnsupportedMediaType("Please send the hashes as application/json, application/x-ndjson or text/csv.".to_string())),
+    };
+
+    if entries.is_empty() {
+        return Err(ScanError::MissingBody);
+    }
+
+    lists.add(scope, list, &entries).await.map_err(|err| {
+        eprintln!("Could not add to the {} list of scope {}. {}", list.as_str(), scope.name(), err);
+        ScanError::DatabaseUnavailable("We could not add the hashes to the list. Please try again later.".to_string())
+    })?;
+
+    return Ok(HttpResponse::Ok().json(ImportResult { imported: entries.len() }));
+}
+
+///Removes a hash from a blocklist or allowlist
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// lists: web::Data<HashLists> - Our hash lists
+/// path: web::Path<(String, String)> - block or allow, and the hash to remove
+/// query: web::Query<ScopeQuery> - The scope of the list
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[delete("scan/v1/lists/{list}/{hash}")]
+pub async fn remove_entry(client: AuthenticatedClient, lists: web::Data<HashLists>, path: web::Path<(String, String)>, query: web::Query<ScopeQuery>) -> Result<HttpResponse, ScanError> {
+    let scope = get_scope(&client, query.scope.as_deref(), query.project_id)?;
+    let (list, hash) = path.into_inner();
+    let (list, hash) = (get_list(&list)?, parse_hash(&hash)?);
+
+    let removed = lists.remove(scope, list, &hash).await.map_err(|err| {
+        eprintln!("Could not remove {} from the {} list of scope {}. {}", hash, list.as_str(), scope.name(), err);
+        ScanError::DatabaseUnavailable("We could not remove the hash from the list. Please try again later.".to_string())
+    })?;
+
+    if !removed {
+        return Err(ScanError::ListEntryNotFound);
+    }
+
+    return Ok(HttpResponse::NoContent().finish());
+}
@@ -0,0 +1,106 @@
+use actix_web::{post, HttpResponse, web};
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::misc;
+use crate::helper::perceptual_hash::ImageHash;
+use crate::helper::scan_error::ScanError;
+use crate::helper::upload;
+use crate::helper::usage_store::Usage;
+
+use super::file_recognition_service::{self, ScanServices};
+
+///Most hashes that can be looked up with a single request
+const MAX_LOOKUP_HASHES: usize = 100;
+///Largest body of a lookup request in bytes, which fits MAX_LOOKUP_HASHES hashes with plenty of room to spare
+const MAX_LOOKUP_BYTES: usize = 64 * 1024;
+
+///Body of a lookup request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LookupRequest {
+    hashes: Vec<String>,
+}
+
+///Whether we know the result of a hash
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum LookupStatus {
+    ///We know the result of the hash, or of a near duplicate of it
+    Found,
+    ///We don't know a result for the hash. The image has to be sent to one of our detection endpoints to be scanned.
+    Unknown,
+}
+
+///Outcome of looking up a single hash
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LookupItem {
+    hash: String,
+    status: LookupStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LookupResponse {
+    ///Outcomes in the order the hashes have been sent in
+    results: Vec<LookupItem>,
+}
+
+///API endpoint, that returns the results we know for images by their perceptual hashes, so clients can check images without
+///sending them to us. The hashes have to be computed with the same algorithm we use, see `perceptual_hash::ImageHash`.
+///Verdicts of our hash lists and results of near duplicates are returned like our detection endpoints return them. Nothing is scanned, stored or queued, so
+///hashes we don't know a result for are answered with the status unknown.
+///
+///The body is a JSON object like `{"hashes": ["dh1_f0e1d2c3b4a59687"]}`.
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// services: ScanServices - The services the results are looked up and metered with
+/// payload: web::Payload - The body of the request
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[post("scan/v1/detection/lookup")]
+pub async fn lookup(client: AuthenticatedClient, services: ScanServices, payload: web::Payload) -> Result<HttpResponse, ScanError> {
+    let body = upload::read_bytes(payload, MAX_LOOKUP_BYTES).await?;
+
+    if body.is_empty() {
+        return Err(ScanError::MissingBody);
+    }
+
+    let request: LookupRequest = serde_json::from_slice(&body)
+        .map_err(|_| ScanError::InvalidRequest("Please send a JSON object with the hashes to look up.".to_string()))?;
+
+    if request.hashes.is_empty() || request.hashes.len() > MAX_LOOKUP_HASHES {
+        return Err(ScanError::InvalidRequest(format!("Please send between 1 and {} hashes.", MAX_LOOKUP_HASHES)));
+    }
+
+    let hashes = request.hashes.iter()
+        .map(|hash| hash.trim().parse::<ImageHash>()
+            .map_err(|_| ScanError::InvalidRequest(format!("\"{}\" is not a hash computed with our perceptual hash algorithm.", hash))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    file_recognition_service::meter_request(&services.usage, &client, body.len()).await?;
+
+    let results: Vec<LookupItem> = stream::iter(hashes)
+        .map(|hash| lookup_hash(&services, client.project_id, hash))
+        .buffered(services.config.batch.concurrency)
+        .collect().await;
+
+    let cache_hits = results.iter().filter(|item| item.result.is_some()).count() as u64;
+    services.usage.record(client.project_id, Usage { cache_hits, ..Usage::default() }).await;
+
+    return Ok(HttpResponse::Ok().json(LookupResponse { results }));
+}
+
+///Looks up the result of a single hash
+async fn lookup_hash(services: &ScanServices, project_id: u64, hash: ImageHash) -> LookupItem {
+    let result = file_recognition_service::lookup_result(services, project_id, &hash).await.and_then(|result| misc::get_json_value(&result));
+    let status = if result.is_some() { LookupStatus::Found } else { LookupStatus::Unknown };
+
+    return LookupItem { hash: hash.to_string(), status, result };
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+use actix_web::{get, HttpResponse, web};
+use actix_web::web::Bytes;
+use futures::stream;
+use tokio::{sync::broadcast::{self, error::RecvError}, time::sleep};
+use crate::helper::misc;
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::job_registry::{JobRegistry, JobState};
+use crate::helper::model_versions::ModelVersions;
+use crate::helper::scan_error::ScanError;
+use crate::helper::scan_events::{ScanEvent, ScanEvents, ScanState};
+use crate::helper::token_manager::TokenManager;
+
+use super::worker_service;
+
+///Time between the comments we send to keep idle streams from being closed by proxies
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+///State of a single status stream
+struct StatusStream {
+    hash: String,
+    tokens: web::Data<TokenManager>,
+    jobs: web::Data<JobRegistry>,
+    models: web::Data<ModelVersions>,
+    ///False if the job has been submitted to another instance, so we can't tell if it failed
+    is_local_job: bool,
+    receiver: broadcast::Receiver<ScanEvent>,
+    ///Event that is sent before anything that is received
+    initial: Option<ScanEvent>,
+    finished: bool,
+}
+
+///Formats an event as a Server-Sent Event, named after the state of the scan
+fn to_sse(event: &ScanEvent) -> Bytes {
+    return Bytes::from(format!("event: {}\ndata: {}\n\n", event.state.as_str(), serde_json::to_string(event).unwrap()));
+}
+
+///Returns the next chunk of a status stream, or none once the scan is done or failed
+async fn next_chunk(mut state: StatusStream) -> Option<(Result<Bytes, actix_web::Error>, StatusStream)> {
+    if state.finished {
+        return None;
+    }
+
+    if let Some(event) = state.initial.take() {
+        state.finished = event.is_final();
+        return Some((Ok(to_sse(&event)), state));
+    }
+
+    let event = tokio::select! {
+        event = state.receiver.recv() => match event {
+            Ok(event) => Some(event),
+            //We only missed states that have been replaced by newer ones, which we will receive next
+            Err(RecvError::Lagged(_)) => None,
+            Err(RecvError::Closed) => return None,
+        },
+        _ = sleep(KEEP_ALIVE_INTERVAL) => None,
+    };
+
+    //No worker posted a result to us in time. It may have posted it to another instance, so it only failed if none is stored.
+    let event = match event {
+        None if state.is_local_job && !state.jobs.is_pending(&state.hash) => {
+            match worker_service::get_work_result(&state.tokens, &state.models, &state.hash, Duration::ZERO).await {
+                Some(result) => Some(ScanEvent::new(&state.hash, ScanState::Done, misc::get_json_value(&result))),
+                None => Some(ScanEvent::new(&state.hash, ScanState::Failed, None)),
+            }
+        },
+        event => event,
+    };
+
+    return match event {
+        Some(event) => {
+            state.finished = event.is_final();
+            Some((Ok(to_sse(&event)), state))
+        },
+        None => Some((Ok(Bytes::from_static(b": keep-alive\n\n")), state)),
+    };
+}
+
+///API endpoint, that streams the state transitions of a scan job as Server-Sent Events, so clients can show the progress of a scan
+///without polling. The current state is sent right away, followed by every transition: queued, leased (by one of our workers),
+///scanning (once the worker downloaded the data), done (together with the result) or failed. The stream ends once the scan is
+///done or failed.
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// tokens: web::Data<TokenManager> - The token manager used to look up the result of scans that are already done
+/// jobs: web::Data<JobRegistry> - The registry the scan job has been submitted to
+/// events: web::Data<ScanEvents> - The events the state transitions of our scans are published to
+/// models: web::Data<ModelVersions> - The models that tell a stale result, whose rescan is still pending, from a current one
+/// hash: web::Path<String> - The ID of the job, which is the hash of the scanned data
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/detection/stream/{hash}")]
+pub async fn stream_status(client: AuthenticatedClient, tokens: web::Data<TokenManager>, jobs: web::Data<JobRegistry>, events: web::Data<ScanEvents>, models: web::Data<ModelVersions>, hash: web::Path<String>) -> Result<HttpResponse, ScanError> {
+    let hash = hash.into_inner();
+    let job_state = jobs.get_state(&hash, client.project_id);
+
+    //Internal clients may follow the scans of every project
+    if job_state.is_none() && !client.is_internal {
+        return Err(ScanError::JobNotFound);
+    }
+
+    //Subscribed before we look at the current state, so we can't miss a transition
+    let (last_event, receiver) = events.subscribe(&hash);
+
+    let initial = match last_event {
+        Some(event) if event.is_final() => event,
+        last_event => match worker_service::get_work_result(&tokens, &models, &hash, Duration::ZERO).await {
+            Some(result) => ScanEvent::new(&hash, ScanState::Done, misc::get_json_value(&result)),
+            None if job_state == Some(JobState::Failed) => ScanEvent::new(&hash, ScanState::Failed, None),
+            None => match last_event {
+                Some(event) => event,
+                None if job_state.is_some() => ScanEvent::new(&hash, ScanState::Queued, None),
+                None => return Err(ScanError::JobNotFound),
+            },
+        },
+    };
+
+    let state = StatusStream { hash, tokens: tokens.clone(), jobs: jobs.clone(), models: models.clone(), is_local_job: job_state.is_some(), receiver, initial: Some(initial), finished: false };
+
+    return Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        //Keeps nginx from buffering the stream
+        .append_header(("X-Accel-Buffering", "no"))
+        .streaming(stream::unfold(state, next_chunk)));
+}
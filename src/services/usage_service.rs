@@ -0,0 +1,53 @@
+use actix_web::{get, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::scan_error::ScanError;
+use crate::helper::usage::{self, Quota, UsageMeter};
+use crate::helper::usage_store::Usage;
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    ///Month to get the usage of, formatted as YYYY-MM. Defaults to the current month.
+    period: Option<String>,
+    ///Project to get the usage of. Only pamaxie's internal clients may get the usage of other projects.
+    project_id: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageResponse {
+    project_id: u64,
+    period: String,
+    usage: Usage,
+    quota: Quota,
+}
+
+///Returns the usage of the client's project in a month, together with its monthly quotas
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// query: web::Query<UsageQuery> - The month and project to get the usage of
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/usage")]
+pub async fn get_usage(client: AuthenticatedClient, usage: web::Data<UsageMeter>, query: web::Query<UsageQuery>) -> Result<HttpResponse, ScanError> {
+    let project_id = query.project_id.unwrap_or(client.project_id);
+
+    if project_id != client.project_id && !client.is_internal {
+        return Err(ScanError::Forbidden("You can only get the usage of your own project.".to_string()));
+    }
+
+    let period = query.period.clone().unwrap_or_else(usage::current_period);
+
+    if !usage::is_valid_period(&period) {
+        return Err(ScanError::InvalidRequest("Please specify the period as a month formatted as YYYY-MM.".to_string()));
+    }
+
+    let project_usage = usage.get_usage(project_id, &period).await.map_err(|err| {
+        eprintln!("Could not get the usage of project {}. {}", project_id, err);
+        ScanError::DatabaseUnavailable("We could not get the usage of your project. Please try again later.".to_string())
+    })?;
+
+    return Ok(HttpResponse::Ok().json(UsageResponse { project_id, period, usage: project_usage, quota: usage.get_quota(project_id) }));
+}
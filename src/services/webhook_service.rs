@@ -0,0 +1,90 @@
+use actix_web::{get, post, HttpResponse, web};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::scan_error::ScanError;
+use crate::helper::webhook_outbox::DeliveryStatus;
+use crate::helper::webhooks::Webhooks;
+
+///Deliveries that are listed if the client does not ask for a number
+const DEFAULT_LIST_LIMIT: u32 = 50;
+///Most deliveries that can be listed at once
+const MAX_LIST_LIMIT: u32 = 500;
+
+#[derive(Deserialize)]
+pub struct DeliveriesQuery {
+    ///Only list deliveries in this state: pending, delivered or failed
+    status: Option<String>,
+    ///Number of deliveries to list, newest first
+    limit: Option<u32>,
+    ///Project to list the deliveries of. Only pamaxie's internal clients may list the deliveries of other projects.
+    project_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ReplayQuery {
+    ///Project the delivery belongs to. Only pamaxie's internal clients may replay the deliveries of other projects.
+    project_id: Option<u64>,
+}
+
+///Returns the project a client asked for, if it may access it
+fn get_project_id(client: &AuthenticatedClient, project_id: Option<u64>) -> Result<u64, ScanError> {
+    let project_id = project_id.unwrap_or(client.project_id);
+
+    if project_id != client.project_id && !client.is_internal {
+        return Err(ScanError::Forbidden("You can only access the webhook deliveries of your own project.".to_string()));
+    }
+
+    return Ok(project_id);
+}
+
+///Lists the most recent webhook deliveries of the client's project, together with the outcome of their last attempt
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// query: web::Query<DeliveriesQuery> - The state, number and project of the deliveries to list
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[get("scan/v1/webhooks/deliveries")]
+pub async fn get_deliveries(client: AuthenticatedClient, webhooks: web::Data<Webhooks>, query: web::Query<DeliveriesQuery>) -> Result<HttpResponse, ScanError> {
+    let project_id = get_project_id(&client, query.project_id)?;
+
+    let status = match &query.status {
+        Some(status) => Some(status.parse::<DeliveryStatus>()
+            .map_err(|_| ScanError::InvalidRequest("Please specify the status as pending, delivered or failed.".to_string()))?),
+        None => None,
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+
+    let deliveries = webhooks.outbox().list(project_id, status, limit).await.map_err(|err| {
+        eprintln!("Could not list the webhook deliveries of project {}. {}", project_id, err);
+        ScanError::DatabaseUnavailable("We could not list the webhook deliveries of your project. Please try again later.".to_string())
+    })?;
+
+    return Ok(HttpResponse::Ok().json(deliveries));
+}
+
+///Sends a webhook delivery again, regardless of whether it succeeded or failed before. It is attempted right away and retried
+///like a new delivery.
+///
+/// # Arguments
+/// client: AuthenticatedClient - The client that sent the request
+/// delivery_id: web::Path<i64> - The ID of the delivery, as sent in the X-Pamaxie-Delivery header
+///
+/// # Returns
+/// Result<HttpResponse, ScanError> - The response object
+#[post("scan/v1/webhooks/deliveries/{delivery_id}/replay")]
+pub async fn replay_delivery(client: AuthenticatedClient, webhooks: web::Data<Webhooks>, delivery_id: web::Path<i64>, query: web::Query<ReplayQuery>) -> Result<HttpResponse, ScanError> {
+    let project_id = get_project_id(&client, query.project_id)?;
+    let delivery_id = delivery_id.into_inner();
+
+    let delivery = webhooks.outbox().replay(delivery_id, project_id, OffsetDateTime::now_utc().unix_timestamp()).await.map_err(|err| {
+        eprintln!("Could not replay webhook delivery {}. {}", delivery_id, err);
+        ScanError::DatabaseUnavailable("We could not replay the webhook delivery. Please try again later.".to_string())
+    })?.ok_or(ScanError::DeliveryNotFound)?;
+
+    webhooks.wake();
+    return Ok(HttpResponse::Ok().json(delivery));
+}
@@ -1,12 +1,22 @@
 use std::ops::Range;
-use std::time::Duration;
-use actix_web::{get, post, HttpResponse, HttpRequest, web};
+use std::time::{Duration, Instant};
+use actix_web::{get, post, HttpResponse, ResponseError, web};
 use serde::{Serialize, Deserialize};
 use tokio::time::sleep;
+use crate::config::Config;
 use crate::helper::{db_api_helper, sqs_helpers, misc, s3_helpers};
-use crate::web_helper;
+use crate::helper::auth::AuthenticatedClient;
+use crate::helper::model_versions::{self, ModelVersions};
+use crate::helper::scan_error::ScanError;
+use crate::helper::token_manager::TokenManager;
+use crate::helper::scan_events::{ScanEvent, ScanEvents, ScanState};
 use serde_json::{Value, json};
-use aws_sdk_sqs::{Client, Region};
+use super::file_recognition_service::ScanServices;
+
+///Time we wait between checks for the result of a scan
+const RESULT_POLL_INTERVAL: Duration = Duration::from_millis(450);
+///Longest time a client waits for the result of its scan. This seems long but depending on API load it is realistic.
+pub const MAX_RESULT_WAIT: Duration = Duration::from_secs(60);
 
 ///Queue data that is used to store our current work that still needs to be processed
 #[allow(non_snake_case)]
@@ -15,46 +25,35 @@ pub struct WorkQueueData{
     pub ImageHash: String,
     pub ImageUrl: String,
     pub DataType: String,
-    pub DataExtension: String
+    pub DataExtension: String,
+    ///Version of the preprocessing pipeline the data has been run through, so results can be traced to it
+    #[serde(default)]
+    pub PipelineVersion: String,
 }
 
 ///Get work from the queue
 /// 
 /// # Arguments
-/// req: HttpRequest - The request object
+/// client: AuthenticatedClient - The worker that sent the request, which has to be one of pamaxie's internal clients
+/// events: web::Data<ScanEvents> - The events the lease of the work is published to
+/// models: web::Data<ModelVersions> - The models that tell whether work that has been scanned before is scanned again
 /// 
 /// # Returns
 /// HttpResponse - The response object
 #[get("scan/v1/worker/get_work")]
-pub async fn get_work(req: HttpRequest) -> HttpResponse {
-
-    //Check if this request is authorized to access this API
-    if !web_helper::check_auth(&req).await {
-        return HttpResponse::Unauthorized().finish();
-    }
-
-    //Check if this is an internal request from one of our workers
-    if !web_helper::is_internal_auth(&req).await {
-        return HttpResponse::Unauthorized().body("Currently only pamaxie's own clients are allowed to scan files. Stay tuned for more.");
-    }
-
-
-    let token_payload = web_helper::get_scan_token_payload(&req);
-
-    if token_payload.is_none(){
-        return HttpResponse::Unauthorized().body("The token sent to authorize with us is invalid and could not be pased.");
+pub async fn get_work(client: AuthenticatedClient, config: web::Data<Config>, tokens: web::Data<TokenManager>, events: web::Data<ScanEvents>, models: web::Data<ModelVersions>) -> HttpResponse {
+    if let Err(err) = client.require_internal() {
+        return err.error_response();
     }
 
-    let _unwrapped_token_payload = token_payload.unwrap();
-    let shared_config = aws_config::from_env().region(Region::new(sqs_helpers::get_aws_default_region())).load().await;
-    let client = Client::new(&shared_config);
-    let queue_url = sqs_helpers::get_aws_sqs_queue_url();
+    let client = sqs_helpers::get_client(&config.sqs).await;
+    let queue_url = &config.sqs.queue_url;
 
     //Start polling until we find work we can return.
     let x = Range{start: 0, end: 10};
 
     for _i in x{
-        let result = sqs_helpers::get_message(&client, &queue_url).await;
+        let result = sqs_helpers::get_message(&client, queue_url).await;
 
         if result.is_err() {
             return HttpResponse::InternalServerError().body("Something went wrong while attempting to poll messages. Please try again later.");
@@ -88,12 +87,15 @@ pub async fn get_work(req: HttpRequest) -> HttpResponse {
 
         let image_hash = &deparsed_result_value["ImageHash"].as_str();
 
-        //Check we have an image hash and that it hasn't been scanned before
-        if db_api_helper::get_scan(&image_hash.unwrap().to_string()).await.is_some() {
-            continue;
+        //Check we have an image hash and that it hasn't been scanned before, unless it has been scanned by another model
+        if let Some(existing_result) = db_api_helper::get_scan(&tokens, image_hash.unwrap()).await {
+            if !misc::get_json_value(&existing_result).is_some_and(|existing_result| models.is_stale(&existing_result)) {
+                continue;
+            }
         }
 
         //Checks passed. Return the result to our Requester so they can get to work!
+        events.publish(ScanEvent::new(image_hash.unwrap(), ScanState::Leased, None));
         return HttpResponse::Ok().body(unwrapped_result);
     }
 
@@ -103,37 +105,28 @@ pub async fn get_work(req: HttpRequest) -> HttpResponse {
 ///Sets a piece of work as completed and posts it's results to the database
 /// 
 /// # Arguments
-/// req: HttpRequest - The request object
+/// client: AuthenticatedClient - The worker that sent the request, which has to be one of pamaxie's internal clients
+/// services: ScanServices - The services the result is stored with. Its webhooks are delivered, its completion is published
+/// to the scan events and its hash is added to the index of near duplicates.
+/// body: String - The result, which has to record the model that produced it as ModelName and ModelVersion
 /// 
 /// # Returns
 /// HttpResponse - The response object
 #[post("scan/v1/worker/post_result")]
-pub async fn post_work(req: HttpRequest, body: String) -> HttpResponse {
-    if !web_helper::check_auth(&req).await {
-        return HttpResponse::Unauthorized().finish();
-    }
-
-    let is_pam_scan;
-
-    //Check if this is an internal request from one of our workers
-    if !web_helper::is_internal_auth(&req).await{
-        return HttpResponse::Unauthorized().body("Currently only pamaxie's own clients are allowed to scan files. Stay tuned for more.");
-    }else{
-        is_pam_scan = true;
-    }
-
-    let jwt_payload = web_helper::get_scan_token_payload(&req);
-
-    if jwt_payload.is_none(){
-        return HttpResponse::BadRequest().body("Invalid JWT bearer payload data. Could not read required data from it.");
+pub async fn post_work(client: AuthenticatedClient, services: ScanServices, body: String) -> HttpResponse {
+    if let Err(err) = client.require_internal() {
+        return err.error_response();
     }
 
     //Check if the body is valid
-    if body.len() == 0{
+    if body.is_empty(){
         return HttpResponse::BadRequest().body("No body found in request");
     }
 
-    let mut result: Value = serde_json::from_str(&body).unwrap();
+    let mut result: Value = match serde_json::from_str(&body) {
+        Ok(result) => result,
+        Err(_) => return ScanError::InvalidRequest("The result has to be a JSON object.".to_string()).error_response(),
+    };
 
     //Check if the data is valid
     let validation_result = misc::is_valid_recognition_result(&result);
@@ -142,36 +135,51 @@ pub async fn post_work(req: HttpRequest, body: String) -> HttpResponse {
         return HttpResponse::BadRequest().body("Invalid data found in request");
     }
 
+    if !model_versions::records_model(&result) {
+        return HttpResponse::BadRequest().body("The result has to record the model that produced it as ModelName and ModelVersion");
+    }
+
+    let (scan_hash, data_extension) = match (result["Key"].as_str(), result["DataExtension"].as_str()) {
+        (Some(scan_hash), Some(data_extension)) => (scan_hash.to_string(), data_extension.to_string()),
+        _ => return ScanError::InvalidRequest("The result has to name its Key and DataExtension.".to_string()).error_response(),
+    };
+
     //Set values that could've been maliciously modified by the client
-    result["IsUserScan"] = json!(is_pam_scan);
-    result["ScanMachineGuid"] = json!(jwt_payload.as_ref().unwrap().apiTokenMachineGuid);
+    result["IsUserScan"] = json!(client.is_internal);
+    result["ScanMachineGuid"] = json!(client.machine_guid);
     
     //Remove the Result from S3 storage
-    let s3_removal_result = s3_helpers::remove_s3(&result["Key"].as_str().unwrap().to_string(), &result["DataExtension"].as_str().unwrap().to_string()).await;
+    let s3_removal_result = s3_helpers::remove_s3(&services.config, &scan_hash, &data_extension).await;
 
     if s3_removal_result.is_err() {
         return HttpResponse::NotFound().body("Something went wrong while attempting to remove the file from S3. Please try again later. This usually happens because the requested file does not exist. Please check that the filename is correct. If you are sure it is correct, contact Pamaxie's support.");
     }
 
     //Save the scan data to our API
-    let storage_result = db_api_helper::set_scan(&serde_json::to_string(&result).unwrap().to_string()).await;
+    let storage_result = db_api_helper::set_scan(&services.tokens, &serde_json::to_string(&result).unwrap()).await;
 
-    if storage_result{
-        return HttpResponse::Ok().body("Data has been accepted and stored by our Db API".to_string());
-    }else {
+    if !storage_result{
         return HttpResponse::InternalServerError().body("Data could not be stored by our Db API. Please try again later.".to_string());
     }
+
+    if let Ok(hash) = scan_hash.parse() {
+        services.hashes.add(hash).await;
+    }
+
+    services.webhooks.scan_completed(&scan_hash, &result).await;
+    services.events.publish(ScanEvent::new(&scan_hash, ScanState::Done, Some(result.clone())));
+
+    return HttpResponse::Ok().body("Data has been accepted and stored by our Db API".to_string());
 }
 
+///API endpoint, that hands our workers the data of the work they leased. The scan of the data starts once it has been downloaded.
 #[get("scan/v1/worker/get_image/{image_name}")]
-pub async fn get_image(path: web::Path<String>) -> HttpResponse {
-    //Disabled for now since it's not really required.
-    //if !web_helper::check_auth(&req).await{
-    //    return HttpResponse::Unauthorized().finish();
-    //}
-    
+pub async fn get_image(client: AuthenticatedClient, config: web::Data<Config>, events: web::Data<ScanEvents>, path: web::Path<String>) -> HttpResponse {
+    if let Err(err) = client.require_internal() {
+        return err.error_response();
+    }
 
-    let image_data = s3_helpers::get_s3_item(&path).await;
+    let image_data = s3_helpers::get_s3_item(&config, &path).await;
 
     if image_data.is_none(){
         return HttpResponse::NotFound().body("Could not find the requested item on our storage API");
@@ -180,16 +188,23 @@ pub async fn get_image(path: web::Path<String>) -> HttpResponse {
     let unwrapped_image_data = image_data.unwrap();
     let data_type = format!("image/{}", misc::get_image_extension(&unwrapped_image_data).unwrap());
 
+    //Our items are named after the hash of their data, followed by their data extension
+    if let Some((scan_hash, _)) = path.rsplit_once('.') {
+        events.publish(ScanEvent::new(scan_hash, ScanState::Scanning, None));
+    }
+
     return HttpResponse::Ok().content_type(data_type).body(unwrapped_image_data);
 }
 
 ///Add Work to our processing queue
 /// 
 /// # Arguments
+/// events: &ScanEvents - The events the queueing of the work is published to
 /// scan_hash: String - The hash of the scan we want to add to the queue
 /// scan_url: String - The URL of the scan we want to add to the queue
 /// data_type: String - The type of data we want to add to the queue
 /// data_extension: String - The extension of the data we want to add to the queue
+/// pipeline_version: String - The version of the preprocessing pipeline the data has been run through
 /// 
 /// # Returns
 /// bool - True if the work was added to the queue, false if it wasn't
@@ -199,28 +214,31 @@ pub async fn get_image(path: web::Path<String>) -> HttpResponse {
 /// 
 /// # Notes
 /// None
-pub async fn add_work(scan_hash: &String, scan_url: &String, data_type: &String, data_extension: &String) -> bool {
+pub async fn add_work(config: &Config, events: &ScanEvents, scan_hash: &str, scan_url: &str, data_type: &str, data_extension: &str, pipeline_version: &str) -> bool {
     //Get the Queue Configuration
-    let shared_config = aws_config::from_env().region(Region::new(sqs_helpers::get_aws_default_region())).load().await;
-    let client = Client::new(&shared_config);
-    let queue_url = sqs_helpers::get_aws_sqs_queue_url();
+    let client = sqs_helpers::get_client(&config.sqs).await;
 
     //create our work object and seralize it's work data
     let new_work_data = WorkQueueData{
         ImageHash: scan_hash.to_string(),
         ImageUrl: scan_url.to_string(),
         DataType: data_type.to_string(),
-        DataExtension: data_extension.to_string()
+        DataExtension: data_extension.to_string(),
+        PipelineVersion: pipeline_version.to_string(),
     };
 
     let seralized_work_data = serde_json::to_string(&new_work_data);
 
 
-    let result = sqs_helpers::send_message(&client, &queue_url, &seralized_work_data.unwrap().to_string()).await;
+    let result = sqs_helpers::send_message(&client, &config.sqs.queue_url, &seralized_work_data.unwrap()).await;
     
+    if result.is_ok() {
+        events.publish(ScanEvent::new(scan_hash, ScanState::Queued, None));
+    }
+
     //Remove the item if we find an error. This should always be done
-    if !result.is_ok(){
-        let s3_removal = s3_helpers::remove_s3(&scan_hash, &data_extension).await;
+    if result.is_err(){
+        let s3_removal = s3_helpers::remove_s3(config, scan_hash, data_extension).await;
 
         if s3_removal.is_err(){
             eprintln!("Could not remove the data from our S3 bucket. Please ensure connection parameters are correct.");
@@ -233,7 +251,10 @@ pub async fn add_work(scan_hash: &String, scan_url: &String, data_type: &String,
 ///Get a work result from the queue
 /// 
 /// # Arguments
+/// tokens: &TokenManager - The token manager used to authenticate with our Database API
+/// models: &ModelVersions - The models that tell stale results, which are scanned again, from current ones
 /// item_hash: String - The hash of the scan we want to get the result for
+/// timeout: Duration - The longest time we wait for the result. With a zero timeout we only check once.
 /// 
 /// # Returns
 /// String - The result of the scan
@@ -243,38 +264,35 @@ pub async fn add_work(scan_hash: &String, scan_url: &String, data_type: &String,
 /// 
 /// # Notes
 /// None
-pub async fn get_work_result(item_hash: &String) -> Option<String> {
-    let x = Range{start: 0, end: 134};
+pub async fn get_work_result(tokens: &TokenManager, models: &ModelVersions, item_hash: &str, timeout: Duration) -> Option<String> {
+    let deadline = Instant::now() + timeout;
 
-    //Loop 134 times which amounts to roughly 1 minute of waiting for a scan result. This seems long but depending on API load it is realistic.
-    for _i in x {
-        let result = db_api_helper::get_scan(item_hash).await;
+    loop {
+        let result = db_api_helper::get_scan(tokens, item_hash).await;
         
-        if result.is_some() {
-
-            let unwrapped_result = result.unwrap();
-            let result: Value = serde_json::from_str(&unwrapped_result).unwrap();
-            eprintln!("{}", result["key"]);
-
+        if let Some(unwrapped_result) = result {
             //Check if the data is valid
-            let validation_result = misc::is_valid_recognition_result(&result);
+            let json = misc::get_json_value(&unwrapped_result).filter(misc::is_valid_recognition_result);
         
-            if !validation_result {
-                //Remove the invalid item hash so we don't encouter it again.
-                let deletion_result = db_api_helper::remove_scan(item_hash).await;
-
-                if deletion_result.is_err(){
-                    eprintln!("Could not remove an invalid scan result from our databse. Please ensure connection parameters are correct.");
-                }
-
-                continue;
+            match json {
+                //The result of an older model is kept until the rescan replaces it
+                Some(json) if models.is_stale(&json) => {},
+                Some(_) => return Some(unwrapped_result),
+                None => {
+                    //Remove the invalid item hash so we don't encouter it again.
+                    let deletion_result = db_api_helper::remove_scan(tokens, item_hash).await;
+
+                    if deletion_result.is_err(){
+                        eprintln!("Could not remove an invalid scan result from our databse. Please ensure connection parameters are correct.");
+                    }
+                },
             }
+        }
 
-            return Some(unwrapped_result);
+        if Instant::now() + RESULT_POLL_INTERVAL > deadline {
+            return None;
         }
 
-        sleep(Duration::from_millis(450)).await;
+        sleep(RESULT_POLL_INTERVAL).await;
     }
-
-    return None;
-}
\ No newline at end of file
+}